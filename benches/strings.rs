@@ -0,0 +1,63 @@
+//! Times scripts that read and pass around long strings
+//!
+//! Run with `cargo bench --bench strings`. Each script is parsed once
+//! and evaluated repeatedly, so the timings cover evaluation only.
+
+use std::{hint::black_box, time::Instant};
+
+use boba::{
+    cache::CacheSpan,
+    parser::{ast::Program, Lexer},
+    BobaCache, Engine,
+};
+
+const ITERATIONS: u32 = 200;
+
+/// Reads a long global string through many variables and function calls
+fn reads(len: usize) -> String {
+    let mut source = format!("static text = '{}'\n", "x".repeat(len));
+    source.push_str("let same = fn(s): s\n");
+    for i in 0..100 {
+        source.push_str(&format!("let copy{i} = same(text)\n"));
+    }
+    source
+}
+
+/// Compares long strings, which needs both operands cloned out of scope
+fn compares(len: usize) -> String {
+    let mut source = format!("static a = '{}'\nstatic b = a\n", "y".repeat(len));
+    for _ in 0..100 {
+        source.push_str("a == b\n");
+    }
+    source
+}
+
+fn bench(name: &str, source: String) {
+    let mut cache = BobaCache::new();
+    let data = cache
+        .store(name, source)
+        .expect("benchmark fits in the cache");
+    let mut program = match Program::parse(&mut Lexer::new(data)) {
+        Ok(program) => program,
+        Err(_) => panic!("benchmark '{name}' failed to parse"),
+    };
+    program.dedup_strings();
+
+    // engines are created up front so loading the builtins is not timed
+    let mut engines: Vec<Engine<CacheSpan>> = (0..ITERATIONS).map(|_| Engine::new()).collect();
+    let start = Instant::now();
+    for engine in &mut engines {
+        let output = engine.eval_program(&program.statements);
+        assert!(output.is_ok(), "benchmark '{name}' failed to run");
+        black_box(output.ok());
+    }
+    let per_iter = start.elapsed() / ITERATIONS;
+    println!("{name:<24} {per_iter:>12.2?} / iter");
+}
+
+fn main() {
+    for len in [16, 4096, 65536] {
+        bench(&format!("reads/{len}"), reads(len));
+        bench(&format!("compares/{len}"), compares(len));
+    }
+}
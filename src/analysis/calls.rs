@@ -0,0 +1,227 @@
+use std::{collections::VecDeque, ops::Deref, slice};
+
+use hashbrown::HashSet;
+
+use crate::{
+    diagnostic::Warning,
+    parser::ast::{Expr, Node, Statement},
+};
+
+use super::{ConstantPool, ResolutionMap};
+
+/// Code that can call other functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Caller {
+    /// The top level statements of the program
+    Script,
+    /// A function literal, by its index in [`CallGraph::functions`]
+    Function(usize),
+}
+
+/// A function that can be called
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Callee {
+    /// A function literal, by its index in [`CallGraph::functions`]
+    Function(usize),
+    /// An identifier that is not defined in the program, so it must be provided by the host
+    Native(String),
+}
+
+/// A function literal and everything it can call
+#[derive(Debug, Clone)]
+pub struct FunctionNode<Data> {
+    /// The variable the function was defined with, if any
+    pub name: Option<String>,
+    pub data: Data,
+    pub callees: Vec<Callee>,
+}
+
+/// The static call graph of a program
+///
+/// Any reference to a function counts as a call, so passing `print` as an argument
+/// or aliasing it with `let p = print` is treated the same as calling it directly.
+/// This keeps reachability conservative, so hosts can safely reject scripts that
+/// could possibly reach a forbidden native.
+/// Calls through parameters or reassigned variables cannot be followed statically.
+#[derive(Debug, Clone)]
+pub struct CallGraph<Data> {
+    script: Vec<Callee>,
+    functions: Vec<FunctionNode<Data>>,
+}
+
+impl<Data: Clone> CallGraph<Data> {
+    pub fn build(statements: &[Node<Data, Statement<Data>>]) -> Self {
+        Self::from_resolution(&ResolutionMap::build(statements))
+    }
+
+    pub fn from_resolution(map: &ResolutionMap<Data>) -> Self {
+        let mut graph = Self {
+            script: Vec::new(),
+            functions: (map.functions().iter().cloned())
+                .map(|data| FunctionNode {
+                    name: None,
+                    data,
+                    callees: Vec::new(),
+                })
+                .collect(),
+        };
+
+        for definition in map.definitions() {
+            if let Some(index) = definition.function {
+                graph.functions[index].name = Some(definition.ident.clone());
+            }
+        }
+
+        for resolution in map.uses() {
+            let callee = match map.binding(resolution) {
+                None => Callee::Native(resolution.ident.clone()),
+                Some(definition) => match definition.function {
+                    Some(index) => Callee::Function(index),
+                    None => continue,
+                },
+            };
+
+            let callees = match resolution.scope {
+                None => &mut graph.script,
+                Some(index) => &mut graph.functions[index].callees,
+            };
+            if !callees.contains(&callee) {
+                callees.push(callee);
+            }
+        }
+
+        graph
+    }
+
+    /// Returns warnings for named functions that are never reachable from the script or `entries`
+    ///
+    /// See [`CallGraph::dead_functions`] for how entry points are used.
+    pub fn warnings(&self, entries: &[&str]) -> Vec<Warning<Data>> {
+        let mut warnings = Vec::new();
+        for index in self.dead_functions(entries) {
+            let function = &self.functions[index];
+            if let Some(ident) = &function.name {
+                warnings.push(Warning::UnusedFunction {
+                    ident: ident.clone(),
+                    data: function.data.clone(),
+                });
+            }
+        }
+
+        warnings
+    }
+}
+
+impl<Data> CallGraph<Data> {
+    pub fn functions(&self) -> &[FunctionNode<Data>] {
+        &self.functions
+    }
+
+    /// Returns everything `caller` calls directly
+    pub fn callees(&self, caller: Caller) -> &[Callee] {
+        match caller {
+            Caller::Script => &self.script,
+            Caller::Function(index) => match self.functions.get(index) {
+                Some(function) => &function.callees,
+                None => &[],
+            },
+        }
+    }
+
+    /// Returns everything that calls `callee` directly
+    pub fn callers<'a>(&'a self, callee: &'a Callee) -> impl Iterator<Item = Caller> + 'a {
+        let script = self.script.contains(callee).then_some(Caller::Script);
+        let functions = (self.functions.iter().enumerate())
+            .filter(move |(_, function)| function.callees.contains(callee))
+            .map(|(index, _)| Caller::Function(index));
+        script.into_iter().chain(functions)
+    }
+
+    /// Returns everything that can be called directly or indirectly from `caller`
+    pub fn reachable(&self, caller: Caller) -> Vec<&Callee> {
+        let mut found = HashSet::new();
+        let mut output = Vec::new();
+        let mut queue = VecDeque::from([caller]);
+        while let Some(caller) = queue.pop_front() {
+            for callee in self.callees(caller) {
+                if !found.insert(callee) {
+                    continue;
+                }
+
+                output.push(callee);
+                if let Callee::Function(index) = callee {
+                    queue.push_back(Caller::Function(*index));
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Returns the index of every function that is never reachable from the script or `entries`
+    ///
+    /// Entry points are the names of functions the host calls directly after running the script.
+    pub fn dead_functions(&self, entries: &[&str]) -> Vec<usize> {
+        let entries = (self.functions.iter().enumerate())
+            .filter(|(_, function)| match &function.name {
+                Some(name) => entries.contains(&name.as_str()),
+                None => false,
+            })
+            .map(|(index, _)| Caller::Function(index));
+
+        let mut live = HashSet::new();
+        for caller in [Caller::Script].into_iter().chain(entries) {
+            if let Caller::Function(index) = caller {
+                live.insert(index);
+            }
+
+            for callee in self.reachable(caller) {
+                if let Callee::Function(index) = callee {
+                    live.insert(*index);
+                }
+            }
+        }
+
+        (0..self.functions.len())
+            .filter(|index| !live.contains(index))
+            .collect()
+    }
+
+    /// Returns true if `caller` can possibly call the native `ident`
+    pub fn can_reach_native(&self, caller: Caller, ident: &str) -> bool {
+        self.reachable(caller)
+            .into_iter()
+            .any(|callee| matches!(callee, Callee::Native(native) if native == ident))
+    }
+}
+
+/// Removes top level definitions of functions that are never reachable from the script or `entries`
+///
+/// Hosts can use this to shrink scripts before shipping them.
+/// Returns the number of definitions removed.
+pub fn strip_dead_functions<Data: Clone>(
+    statements: &mut Vec<Node<Data, Statement<Data>>>,
+    entries: &[&str],
+) -> usize {
+    let dead = CallGraph::build(statements).dead_functions(entries);
+
+    // function literals are indexed in the order they appear,
+    // so count them to find the index of each top level definition
+    let mut next = 0;
+    let before = statements.len();
+    statements.retain(|statement| {
+        let index = next;
+        next += ConstantPool::build(slice::from_ref(statement))
+            .functions()
+            .len();
+
+        match statement.deref() {
+            Statement::Init(init) if matches!(init.expr.deref(), Expr::Func(_)) => {
+                !dead.contains(&index)
+            }
+            _ => true,
+        }
+    });
+
+    before - statements.len()
+}
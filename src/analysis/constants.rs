@@ -0,0 +1,131 @@
+use std::{ops::Deref, sync::Arc};
+
+use indexmap::IndexMap;
+
+use crate::{
+    parser::ast::{Expr, Node, Statement},
+    symbol::Symbol,
+};
+
+/// A function literal found in a program
+#[derive(Debug, Clone)]
+pub struct FunctionInfo<Data> {
+    /// The variable the function was directly assigned to, if any
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub data: Data,
+}
+
+/// Every literal value and function defined in a program
+///
+/// Hosts can use the pool to audit a script before running it,
+/// for example to scan its strings for unexpected urls or paths.
+/// Literals are listed in the order they first appear, along with every place they are used.
+#[derive(Debug, Clone)]
+pub struct ConstantPool<Data> {
+    strings: IndexMap<Arc<str>, Vec<Data>>,
+    numbers: IndexMap<String, Vec<Data>>,
+    functions: Vec<FunctionInfo<Data>>,
+}
+
+impl<Data: Clone> ConstantPool<Data> {
+    pub fn build(statements: &[Node<Data, Statement<Data>>]) -> Self {
+        let mut pool = Self {
+            strings: IndexMap::new(),
+            numbers: IndexMap::new(),
+            functions: Vec::new(),
+        };
+
+        for statement in statements {
+            pool.statement(statement);
+        }
+
+        pool
+    }
+
+    /// Returns each distinct string literal, including the text of prefixed literals
+    pub fn strings(&self) -> impl Iterator<Item = (&str, &[Data])> {
+        self.strings
+            .iter()
+            .map(|(string, uses)| (string.deref(), uses.as_slice()))
+    }
+
+    /// Returns each distinct number literal in its normalized form
+    pub fn numbers(&self) -> impl Iterator<Item = (&str, &[Data])> {
+        self.numbers
+            .iter()
+            .map(|(number, uses)| (number.as_str(), uses.as_slice()))
+    }
+
+    pub fn functions(&self) -> &[FunctionInfo<Data>] {
+        &self.functions
+    }
+
+    fn statement(&mut self, statement: &Node<Data, Statement<Data>>) {
+        match statement.deref() {
+            Statement::Expr(expr) => self.expr(expr, None),
+            Statement::Init(init) => self.expr(&init.expr, Some((*init.ident).clone())),
+            Statement::Custom(_) => (),
+            Statement::With(with) => {
+                self.expr(&with.resource, None);
+                self.expr(&with.body, None);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Node<Data, Expr<Data>>, name: Option<Symbol>) {
+        let data = expr.data().clone();
+        match expr.deref() {
+            Expr::None | Expr::Var(_) | Expr::Bool(_) => (),
+            Expr::Int(v) => self.numbers.entry(v.to_string()).or_default().push(data),
+            Expr::Float(v) => self.numbers.entry(v.to_string()).or_default().push(data),
+            Expr::String(v) => self.strings.entry(v.clone()).or_default().push(data),
+            Expr::Prefixed(_, text) => {
+                let text: Arc<str> = text.as_str().into();
+                self.strings.entry(text).or_default().push(data);
+            }
+            Expr::Suffixed(number, _) => self.expr(number, None),
+            Expr::Func(func) => {
+                self.functions.push(FunctionInfo {
+                    name: name.map(String::from),
+                    params: func.params.iter().map(|p| p.to_string()).collect(),
+                    data,
+                });
+                for statement in func.body.iter() {
+                    self.statement(statement);
+                }
+            }
+            Expr::Call(_, params) => {
+                for param in params {
+                    self.expr(param, None);
+                }
+            }
+            Expr::Neg(inner) | Expr::Not(inner) => self.expr(inner, None),
+            Expr::Add(lhs, rhs)
+            | Expr::Sub(lhs, rhs)
+            | Expr::Mul(lhs, rhs)
+            | Expr::Div(lhs, _, rhs)
+            | Expr::Mod(lhs, _, rhs)
+            | Expr::Pow(lhs, _, rhs)
+            | Expr::And(lhs, rhs)
+            | Expr::Or(lhs, rhs)
+            | Expr::Eq(lhs, rhs)
+            | Expr::Lt(lhs, rhs)
+            | Expr::Gt(lhs, rhs)
+            | Expr::NEq(lhs, rhs)
+            | Expr::LtEq(lhs, rhs)
+            | Expr::GtEq(lhs, rhs) => {
+                self.expr(lhs, None);
+                self.expr(rhs, None);
+            }
+            Expr::Assign(ident, rhs) | Expr::Walrus(ident, rhs) => {
+                self.expr(rhs, Some((**ident).clone()))
+            }
+            Expr::Ternary(cond, lhs, rhs) => {
+                self.expr(cond, None);
+                self.expr(lhs, None);
+                self.expr(rhs, None);
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::{fmt::Display, ops::Deref, str::FromStr};
+
+use crate::{
+    diagnostic::Warning,
+    parser::ast::{
+        visit::{walk_expr, Visit},
+        Expr, Node, Statement,
+    },
+};
+
+use super::{CallGraph, ResolutionMap};
+
+/// A check performed by the [`Linter`], named so it can be allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// Local variables and parameters that are never read
+    UnusedVariable,
+    /// Named functions that can never be called
+    UnusedFunction,
+    /// Definitions that hide an earlier definition with the same name
+    ShadowedBinding,
+    /// Parameters that hide a global or another parameter with the same name
+    ShadowedParameter,
+    /// Ternary conditions that do not depend on any variable
+    ConstantCondition,
+}
+
+impl Rule {
+    /// Every rule, in the order they are documented
+    pub const ALL: [Rule; 5] = [
+        Rule::UnusedVariable,
+        Rule::UnusedFunction,
+        Rule::ShadowedBinding,
+        Rule::ShadowedParameter,
+        Rule::ConstantCondition,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::UnusedFunction => "unused-function",
+            Rule::ShadowedBinding => "shadowed-binding",
+            Rule::ShadowedParameter => "shadowed-parameter",
+            Rule::ConstantCondition => "constant-condition",
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match Rule::ALL.into_iter().find(|rule| rule.name() == name) {
+            Some(rule) => Ok(rule),
+            None => Err(format!("unknown lint rule '{name}'")),
+        }
+    }
+}
+
+/// Checks a program for likely mistakes without running it
+///
+/// Every rule is enabled by default. Rules can be [allowed](Linter::allow)
+/// by name, so hosts and the `check` command can suppress the ones they disagree with.
+#[derive(Debug, Clone, Default)]
+pub struct Linter {
+    allowed: Vec<Rule>,
+    entries: Vec<String>,
+}
+
+impl Linter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops reporting warnings for `rule`
+    pub fn allow(mut self, rule: Rule) -> Self {
+        if !self.allowed.contains(&rule) {
+            self.allowed.push(rule);
+        }
+        self
+    }
+
+    /// Treats the function named `ident` as called by the host, so it is never unused
+    pub fn entry(mut self, ident: impl Into<String>) -> Self {
+        self.entries.push(ident.into());
+        self
+    }
+
+    pub fn allows(&self, rule: Rule) -> bool {
+        self.allowed.contains(&rule)
+    }
+
+    /// Returns the warnings for every rule that is not allowed
+    pub fn lint<Data: Clone>(
+        &self,
+        statements: &[Node<Data, Statement<Data>>],
+    ) -> Vec<Warning<Data>> {
+        let resolution = ResolutionMap::build(statements);
+        let calls = CallGraph::from_resolution(&resolution);
+        let entries: Vec<&str> = self.entries.iter().map(String::as_str).collect();
+
+        let mut conditions = ConstantConditions {
+            warnings: Vec::new(),
+        };
+        for statement in statements {
+            conditions.visit_statement(statement);
+        }
+
+        let mut warnings = resolution.warnings();
+        warnings.extend(calls.warnings(&entries));
+        warnings.extend(conditions.warnings);
+        warnings.retain(|warning| match warning.rule() {
+            Some(rule) => !self.allows(rule),
+            None => true,
+        });
+        warnings
+    }
+}
+
+/// Returns true if `expr` is built only from literals, so it evaluates the same every time
+///
+/// Prefixed and suffixed literals are converted by host hooks, so they are never constant.
+fn is_constant<Data>(expr: &Node<Data, Expr<Data>>) -> bool {
+    match expr.deref() {
+        Expr::None | Expr::Bool(_) | Expr::Int(_) | Expr::Float(_) | Expr::String(_) => true,
+        Expr::Neg(inner) | Expr::Not(inner) => is_constant(inner),
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, _, rhs)
+        | Expr::Mod(lhs, _, rhs)
+        | Expr::Pow(lhs, _, rhs)
+        | Expr::And(lhs, rhs)
+        | Expr::Or(lhs, rhs)
+        | Expr::Eq(lhs, rhs)
+        | Expr::Lt(lhs, rhs)
+        | Expr::Gt(lhs, rhs)
+        | Expr::NEq(lhs, rhs)
+        | Expr::LtEq(lhs, rhs)
+        | Expr::GtEq(lhs, rhs) => is_constant(lhs) && is_constant(rhs),
+        Expr::Ternary(cond, lhs, rhs) => is_constant(cond) && is_constant(lhs) && is_constant(rhs),
+        _ => false,
+    }
+}
+
+/// Returns the value of a condition made of bool literals and `not`
+fn literal_bool<Data>(expr: &Node<Data, Expr<Data>>) -> Option<bool> {
+    match expr.deref() {
+        Expr::Bool(value) => Some(*value),
+        Expr::Not(inner) => literal_bool(inner).map(|value| !value),
+        _ => None,
+    }
+}
+
+struct ConstantConditions<Data> {
+    warnings: Vec<Warning<Data>>,
+}
+
+impl<Data: Clone> Visit<Data> for ConstantConditions<Data> {
+    fn visit_expr(&mut self, expr: &Node<Data, Expr<Data>>) {
+        if let Expr::Ternary(cond, lhs, rhs) = expr.deref() {
+            if is_constant(cond) {
+                let unreachable = match literal_bool(cond) {
+                    Some(true) => Some(rhs.data().clone()),
+                    Some(false) => Some(lhs.data().clone()),
+                    None => None,
+                };
+                self.warnings.push(Warning::ConstantCondition {
+                    data: cond.data().clone(),
+                    unreachable,
+                });
+            }
+        }
+
+        walk_expr(self, expr);
+    }
+}
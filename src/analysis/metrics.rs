@@ -0,0 +1,160 @@
+use std::ops::Deref;
+
+use crate::{
+    parser::ast::{Expr, Node, Statement},
+    symbol::Symbol,
+};
+
+/// Size and complexity measurements for a block of code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Measure {
+    /// Number of statements, not counting statements in nested functions
+    pub statements: usize,
+    /// Deepest nesting of ternary branches and function literals
+    pub max_depth: usize,
+    /// Number of independent paths through the code, starting at 1
+    pub complexity: usize,
+}
+
+/// Metrics for a single function literal
+#[derive(Debug, Clone)]
+pub struct FunctionMetrics<Data> {
+    /// The variable the function was defined with, if any
+    pub name: Option<String>,
+    pub data: Data,
+    pub measure: Measure,
+}
+
+/// Size and complexity metrics for a program and each of its functions
+///
+/// Only ternaries add to the complexity, since `and` and `or` always evaluate both sides.
+/// Functions are listed in the order they appear, matching [`CallGraph`](super::CallGraph).
+#[derive(Debug, Clone)]
+pub struct Metrics<Data> {
+    script: Measure,
+    functions: Vec<FunctionMetrics<Data>>,
+}
+
+impl<Data: Clone> Metrics<Data> {
+    pub fn build(statements: &[Node<Data, Statement<Data>>]) -> Self {
+        let mut metrics = Self {
+            script: Measure::new(),
+            functions: Vec::new(),
+        };
+
+        for statement in statements {
+            metrics.statement(statement, None, 0);
+        }
+
+        metrics
+    }
+
+    /// Returns the metrics for the top level statements of the program
+    pub fn script(&self) -> &Measure {
+        &self.script
+    }
+
+    pub fn functions(&self) -> &[FunctionMetrics<Data>] {
+        &self.functions
+    }
+
+    fn measure(&mut self, target: Option<usize>) -> &mut Measure {
+        match target {
+            None => &mut self.script,
+            Some(index) => &mut self.functions[index].measure,
+        }
+    }
+
+    fn statement(
+        &mut self,
+        statement: &Node<Data, Statement<Data>>,
+        target: Option<usize>,
+        depth: usize,
+    ) {
+        self.measure(target).statements += 1;
+        match statement.deref() {
+            Statement::Expr(expr) => self.expr(expr, target, depth, None),
+            Statement::Init(init) => {
+                self.expr(&init.expr, target, depth, Some((*init.ident).clone()))
+            }
+            Statement::Custom(_) => (),
+            Statement::With(with) => {
+                self.expr(&with.resource, target, depth, None);
+                self.expr(&with.body, target, depth, None);
+            }
+        }
+    }
+
+    fn expr(
+        &mut self,
+        expr: &Node<Data, Expr<Data>>,
+        target: Option<usize>,
+        depth: usize,
+        name: Option<Symbol>,
+    ) {
+        let measure = self.measure(target);
+        measure.max_depth = measure.max_depth.max(depth);
+
+        match expr.deref() {
+            Expr::None | Expr::Var(_) | Expr::Bool(_) | Expr::Int(_) | Expr::Float(_) => (),
+            Expr::String(_) | Expr::Prefixed(..) => (),
+            Expr::Suffixed(number, _) => self.expr(number, target, depth, None),
+            Expr::Func(func) => {
+                let measure = self.measure(target);
+                measure.max_depth = measure.max_depth.max(depth + 1);
+
+                let index = self.functions.len();
+                self.functions.push(FunctionMetrics {
+                    name: name.map(String::from),
+                    data: expr.data().clone(),
+                    measure: Measure::new(),
+                });
+                for statement in func.body.iter() {
+                    self.statement(statement, Some(index), 0);
+                }
+            }
+            Expr::Call(_, params) => {
+                for param in params {
+                    self.expr(param, target, depth, None);
+                }
+            }
+            Expr::Neg(inner) | Expr::Not(inner) => self.expr(inner, target, depth, None),
+            Expr::Add(lhs, rhs)
+            | Expr::Sub(lhs, rhs)
+            | Expr::Mul(lhs, rhs)
+            | Expr::Div(lhs, _, rhs)
+            | Expr::Mod(lhs, _, rhs)
+            | Expr::Pow(lhs, _, rhs)
+            | Expr::And(lhs, rhs)
+            | Expr::Or(lhs, rhs)
+            | Expr::Eq(lhs, rhs)
+            | Expr::Lt(lhs, rhs)
+            | Expr::Gt(lhs, rhs)
+            | Expr::NEq(lhs, rhs)
+            | Expr::LtEq(lhs, rhs)
+            | Expr::GtEq(lhs, rhs) => {
+                self.expr(lhs, target, depth, None);
+                self.expr(rhs, target, depth, None);
+            }
+            Expr::Assign(ident, rhs) | Expr::Walrus(ident, rhs) => {
+                self.expr(rhs, target, depth, Some((**ident).clone()))
+            }
+            Expr::Ternary(cond, lhs, rhs) => {
+                self.measure(target).complexity += 1;
+                self.expr(cond, target, depth, None);
+                self.expr(lhs, target, depth + 1, None);
+                self.expr(rhs, target, depth + 1, None);
+            }
+        }
+    }
+}
+
+impl Measure {
+    fn new() -> Self {
+        Self {
+            statements: 0,
+            max_depth: 0,
+            complexity: 1,
+        }
+    }
+}
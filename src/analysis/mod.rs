@@ -0,0 +1,11 @@
+pub mod calls;
+pub mod constants;
+pub mod lint;
+pub mod metrics;
+pub mod resolve;
+
+pub use calls::CallGraph;
+pub use constants::ConstantPool;
+pub use lint::{Linter, Rule};
+pub use metrics::Metrics;
+pub use resolve::ResolutionMap;
@@ -0,0 +1,321 @@
+use std::ops::Deref;
+
+use crate::{
+    cache::CacheSpan,
+    diagnostic::Warning,
+    parser::ast::{init::InitStyle, Expr, Node, Statement},
+    symbol::Symbol,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefinitionKind {
+    Let,
+    Static,
+    Const,
+    Param,
+}
+
+/// A location where an identifier is introduced
+#[derive(Debug, Clone)]
+pub struct Definition<Data> {
+    pub ident: String,
+    pub kind: DefinitionKind,
+    pub data: Data,
+    /// The definition that was visible under the same name before this one
+    pub shadows: Option<usize>,
+    /// Index of the function literal the definition is initialized with, if any
+    pub function: Option<usize>,
+}
+
+/// A location where an identifier is used, and the definition it binds to
+#[derive(Debug, Clone)]
+pub struct Resolution<Data> {
+    pub ident: String,
+    pub data: Data,
+    /// Index of the bound definition, or `None` if it must be provided by the host
+    pub binding: Option<usize>,
+    /// Index of the innermost function literal containing the use, or `None` at the top level
+    pub scope: Option<usize>,
+}
+
+#[derive(Default)]
+struct Frame {
+    locals: Vec<(String, usize)>,
+}
+
+/// Maps every identifier use in a program to the definition it binds to
+///
+/// Resolution follows the same rules as the [`Engine`](crate::Engine):
+/// locals are searched before globals, and function bodies can only see
+/// their own parameters and globals. Globals are looked up when the function
+/// is called, so a body can use a global defined after the function itself.
+#[derive(Debug, Clone)]
+pub struct ResolutionMap<Data> {
+    definitions: Vec<Definition<Data>>,
+    uses: Vec<Resolution<Data>>,
+    functions: Vec<Data>,
+}
+
+impl<Data: Clone> ResolutionMap<Data> {
+    pub fn build(statements: &[Node<Data, Statement<Data>>]) -> Self {
+        let mut builder = Builder {
+            map: Self {
+                definitions: Vec::new(),
+                uses: Vec::new(),
+                functions: Vec::new(),
+            },
+            frames: vec![Frame::default()],
+            globals: Vec::new(),
+            scopes: Vec::new(),
+        };
+
+        for statement in statements {
+            builder.statement(statement);
+        }
+
+        // function bodies run after the whole program has been defined,
+        // so names they could not find yet may be later globals
+        let globals = &builder.globals;
+        for resolution in builder.map.uses.iter_mut() {
+            if resolution.scope.is_some() && resolution.binding.is_none() {
+                let mut globals = globals.iter().rev();
+                resolution.binding = globals
+                    .find(|(name, _)| *name == resolution.ident)
+                    .map(|(_, index)| *index);
+            }
+        }
+
+        builder.map
+    }
+
+    pub fn definitions(&self) -> &[Definition<Data>] {
+        &self.definitions
+    }
+
+    pub fn uses(&self) -> &[Resolution<Data>] {
+        &self.uses
+    }
+
+    /// Returns the location of every function literal, in the order they appear
+    pub fn functions(&self) -> &[Data] {
+        &self.functions
+    }
+
+    /// Returns the definition a use binds to, if any
+    pub fn binding(&self, resolution: &Resolution<Data>) -> Option<&Definition<Data>> {
+        self.definitions.get(resolution.binding?)
+    }
+
+    /// Returns every use that binds to the definition at `index`
+    pub fn references(&self, index: usize) -> impl Iterator<Item = &Resolution<Data>> {
+        self.uses
+            .iter()
+            .filter(move |resolution| resolution.binding == Some(index))
+    }
+
+    /// Returns every use that could not be bound to a definition in the program
+    pub fn unresolved(&self) -> impl Iterator<Item = &Resolution<Data>> {
+        self.uses
+            .iter()
+            .filter(|resolution| resolution.binding.is_none())
+    }
+
+    /// Returns warnings for unused local variables and shadowed parameters,
+    /// and hints for other shadowed bindings
+    ///
+    /// Globals may be used by the host, and identifiers starting
+    /// with an underscore are intentionally unused, so neither are reported.
+    pub fn warnings(&self) -> Vec<Warning<Data>> {
+        let mut warnings = Vec::new();
+        for (index, definition) in self.definitions.iter().enumerate() {
+            if let Some(shadowed) = definition.shadows {
+                let ident = definition.ident.clone();
+                let data = definition.data.clone();
+                let shadowed = self.definitions[shadowed].data.clone();
+                warnings.push(match definition.kind {
+                    DefinitionKind::Param => Warning::ShadowedParameter {
+                        ident,
+                        data,
+                        shadowed,
+                    },
+                    _ => Warning::ShadowedBinding {
+                        ident,
+                        data,
+                        shadowed,
+                    },
+                });
+            }
+
+            let local = matches!(definition.kind, DefinitionKind::Let | DefinitionKind::Param);
+            let unused = self.references(index).next().is_none();
+            if local && unused && !definition.ident.starts_with('_') {
+                warnings.push(Warning::UnusedVariable {
+                    ident: definition.ident.clone(),
+                    data: definition.data.clone(),
+                });
+            }
+        }
+
+        warnings
+    }
+}
+
+impl ResolutionMap<CacheSpan> {
+    /// Returns the use located at the byte `offset`, if any
+    pub fn use_at(&self, offset: usize) -> Option<&Resolution<CacheSpan>> {
+        self.uses
+            .iter()
+            .find(|resolution| resolution.data.range().contains(&offset))
+    }
+
+    /// Returns the definition located at the byte `offset`, if any
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition<CacheSpan>> {
+        self.definitions
+            .iter()
+            .find(|definition| definition.data.range().contains(&offset))
+    }
+}
+
+struct Builder<Data> {
+    map: ResolutionMap<Data>,
+    frames: Vec<Frame>,
+    globals: Vec<(String, usize)>,
+    scopes: Vec<usize>,
+}
+
+impl<Data: Clone> Builder<Data> {
+    fn lookup(&self, ident: &str) -> Option<usize> {
+        let frame = self.frames.last().expect("root frame");
+        let mut locals = frame.locals.iter().rev();
+        let mut globals = self.globals.iter().rev();
+        match locals.find(|(name, _)| name == ident) {
+            Some((_, index)) => Some(*index),
+            None => globals.find(|(name, _)| name == ident).map(|(_, i)| *i),
+        }
+    }
+
+    fn define(
+        &mut self,
+        ident: &Node<Data, Symbol>,
+        kind: DefinitionKind,
+        function: Option<usize>,
+    ) {
+        let index = self.map.definitions.len();
+        self.map.definitions.push(Definition {
+            ident: ident.to_string(),
+            kind,
+            data: ident.data().clone(),
+            shadows: self.lookup(ident),
+            function,
+        });
+
+        let entry = (ident.to_string(), index);
+        match kind {
+            DefinitionKind::Static | DefinitionKind::Const => self.globals.push(entry),
+            DefinitionKind::Let | DefinitionKind::Param => {
+                let frame = self.frames.last_mut().expect("root frame");
+                frame.locals.push(entry);
+            }
+        }
+    }
+
+    fn use_ident(&mut self, ident: &str, data: &Data) {
+        let binding = self.lookup(ident);
+        self.map.uses.push(Resolution {
+            ident: ident.to_string(),
+            data: data.clone(),
+            binding,
+            scope: self.scopes.last().copied(),
+        });
+    }
+
+    fn statement(&mut self, statement: &Node<Data, Statement<Data>>) {
+        match statement.deref() {
+            Statement::Expr(expr) => self.expr(expr),
+            Statement::Init(init) => {
+                // the next function literal found is the one being bound
+                let function = match init.expr.deref() {
+                    Expr::Func(_) => Some(self.map.functions.len()),
+                    _ => None,
+                };
+                self.expr(&init.expr);
+                let kind = match init.style.deref() {
+                    InitStyle::Let => DefinitionKind::Let,
+                    InitStyle::Static => DefinitionKind::Static,
+                    InitStyle::Const => DefinitionKind::Const,
+                };
+                self.define(&init.ident, kind, function);
+            }
+            // custom statements are opaque to the analysis
+            Statement::Custom(_) => (),
+            Statement::With(with) => {
+                self.expr(&with.resource);
+
+                // the resource is only visible inside the body
+                let frame = self.frames.last().expect("root frame");
+                let visible = frame.locals.len();
+                self.define(&with.ident, DefinitionKind::Let, None);
+                self.expr(&with.body);
+                let frame = self.frames.last_mut().expect("root frame");
+                frame.locals.truncate(visible);
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Node<Data, Expr<Data>>) {
+        match expr.deref() {
+            Expr::None | Expr::Bool(_) | Expr::Int(_) | Expr::Float(_) | Expr::String(_) => (),
+            Expr::Prefixed(..) => (),
+            Expr::Suffixed(number, _) => self.expr(number),
+            Expr::Var(ident) => self.use_ident(ident, expr.data()),
+            Expr::Func(func) => {
+                self.scopes.push(self.map.functions.len());
+                self.map.functions.push(expr.data().clone());
+
+                // function bodies only see their parameters and globals
+                self.frames.push(Frame::default());
+                for param in func.params.iter() {
+                    self.define(param, DefinitionKind::Param, None);
+                }
+                for statement in func.body.iter() {
+                    self.statement(statement);
+                }
+                self.frames.pop();
+                self.scopes.pop();
+            }
+            Expr::Call(ident, params) => {
+                for param in params {
+                    self.expr(param);
+                }
+                self.use_ident(ident, ident.data());
+            }
+            Expr::Neg(inner) | Expr::Not(inner) => self.expr(inner),
+            Expr::Add(lhs, rhs)
+            | Expr::Sub(lhs, rhs)
+            | Expr::Mul(lhs, rhs)
+            | Expr::Div(lhs, _, rhs)
+            | Expr::Mod(lhs, _, rhs)
+            | Expr::Pow(lhs, _, rhs)
+            | Expr::And(lhs, rhs)
+            | Expr::Or(lhs, rhs)
+            | Expr::Eq(lhs, rhs)
+            | Expr::Lt(lhs, rhs)
+            | Expr::Gt(lhs, rhs)
+            | Expr::NEq(lhs, rhs)
+            | Expr::LtEq(lhs, rhs)
+            | Expr::GtEq(lhs, rhs) => {
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+            Expr::Assign(ident, rhs) | Expr::Walrus(ident, rhs) => {
+                self.expr(rhs);
+                self.use_ident(ident, ident.data());
+            }
+            Expr::Ternary(cond, lhs, rhs) => {
+                self.expr(cond);
+                self.expr(lhs);
+                self.expr(rhs);
+            }
+        }
+    }
+}
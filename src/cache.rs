@@ -1,6 +1,8 @@
 use std::{
     fmt::{Debug, Display},
-    ops::{Index, Range},
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
     sync::atomic::{AtomicU32, Ordering},
 };
 
@@ -41,6 +43,17 @@ impl CacheSpan {
     pub fn range(&self) -> &Range<usize> {
         &self.range
     }
+
+    /// Returns the zero-indexed range of lines covered by this span
+    ///
+    /// Returns `None` if the span does not belong to `cache`.
+    pub fn line_range(&self, cache: &BobaCache) -> Option<Range<usize>> {
+        let data = cache.load(self.id)?;
+        let (start, _) = data.line_col(self.range.start)?;
+        let last = self.range.end.saturating_sub(1).max(self.range.start);
+        let (end, _) = data.line_col(last)?;
+        Some(start..end + 1)
+    }
 }
 
 /// Handle to a specific location in a [`BobaCache`]
@@ -68,6 +81,7 @@ impl CacheId {
 pub struct CacheData {
     label: String,
     source: Source,
+    path: Option<PathBuf>,
     id: CacheId,
 }
 
@@ -80,6 +94,11 @@ impl CacheData {
         &self.label
     }
 
+    /// Returns the path the source was loaded from, if it came from a file
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
     pub fn source(&self) -> &Source {
         &self.source
     }
@@ -91,21 +110,62 @@ impl CacheData {
     pub fn span(&self, range: Range<usize>) -> CacheSpan {
         CacheSpan { range, id: self.id }
     }
+
+    /// Converts a byte offset into a zero-indexed line and column
+    ///
+    /// The column is counted in characters from the start of the line.
+    /// Returns `None` if the offset is past the end of the source.
+    pub fn line_col(&self, offset: usize) -> Option<(usize, usize)> {
+        let (line, index, byte_col) = self.source.get_byte_line(offset)?;
+        let text = self.source.get_line_text(line)?;
+        let column = text
+            .char_indices()
+            .take_while(|(i, _)| *i < byte_col)
+            .count();
+        Some((index, column))
+    }
+}
+
+/// Error returned when storing source in a [`BobaCache`] would exceed its limits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheError {
+    EntryTooLarge {
+        label: String,
+        size: usize,
+        limit: usize,
+    },
+    CacheFull {
+        label: String,
+        size: usize,
+        limit: usize,
+    },
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::EntryTooLarge { label, size, limit } => write!(
+                f,
+                "'{label}' is {size} bytes, which exceeds the entry limit of {limit} bytes"
+            ),
+            CacheError::CacheFull { label, size, limit } => write!(
+                f,
+                "storing '{label}' would grow the cache to {size} bytes, exceeding the limit of {limit} bytes"
+            ),
+        }
+    }
 }
 
+impl std::error::Error for CacheError {}
+
 /// Stores data and provides reference to it via a [`CacheId`]
 #[derive(Debug)]
 pub struct BobaCache {
-    store: Vec<CacheData>,
+    store: Vec<Option<CacheData>>,
     cache_id: u32,
-}
-
-impl Index<CacheId> for BobaCache {
-    type Output = CacheData;
-
-    fn index(&self, id: CacheId) -> &Self::Output {
-        self.load(id).expect("invalid cache id")
-    }
+    size: usize,
+    entry_limit: Option<usize>,
+    total_limit: Option<usize>,
 }
 
 impl Cache<CacheId> for BobaCache {
@@ -130,25 +190,140 @@ impl BobaCache {
         Self {
             cache_id,
             store: vec![],
+            size: 0,
+            entry_limit: None,
+            total_limit: None,
         }
     }
 
+    /// Returns the total size in bytes of all stored sources
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Sets the maximum size in bytes of a single stored source
+    pub fn set_entry_limit(&mut self, limit: Option<usize>) {
+        self.entry_limit = limit;
+    }
+
+    /// Sets the maximum size in bytes of all stored sources combined
+    pub fn set_total_limit(&mut self, limit: Option<usize>) {
+        self.total_limit = limit;
+    }
+
     pub fn load(&self, id: CacheId) -> Option<&CacheData> {
         if id.cache_id() != self.cache_id {
             return None;
         }
 
-        self.store.get(id.uindex())
+        self.store.get(id.uindex())?.as_ref()
     }
 
-    pub fn store(&mut self, label: impl Into<String>, data: impl Into<String>) -> &CacheData {
-        let id = CacheId::build(self.cache_id, self.store.len());
-        self.store.push(CacheData {
-            label: label.into(),
-            source: Source::from(data.into()),
-            id,
-        });
+    /// Stores source text in the cache, failing if it would exceed the configured limits
+    pub fn store(
+        &mut self,
+        label: impl Into<String>,
+        data: impl Into<String>,
+    ) -> Result<&CacheData, CacheError> {
+        self.insert(label.into(), data.into(), None)
+    }
+
+    /// Reads a file into the cache, using its path as the label
+    ///
+    /// The path is recorded in the [`CacheData`] so the source can be found again later.
+    /// Errors from exceeding the cache limits are returned as [`io::ErrorKind::Other`].
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> io::Result<&CacheData> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let label = path.display().to_string();
+        let path = Some(path.to_path_buf());
+        self.insert(label, text, path).map_err(io::Error::other)
+    }
+
+    /// Replaces the source text of an existing entry, keeping its label and path
+    ///
+    /// Spans into the old source are not updated, so they should be discarded.
+    /// Returns `Ok(None)` if the entry does not exist in this cache.
+    pub fn replace(
+        &mut self,
+        id: CacheId,
+        data: impl Into<String>,
+    ) -> Result<Option<&CacheData>, CacheError> {
+        let data = data.into();
+        let Some(entry) = self.load(id) else {
+            return Ok(None);
+        };
+
+        self.size = self.check_limits(&entry.label, data.len(), entry.text().len())?;
+        let entry = self.store.get_mut(id.uindex()).and_then(Option::as_mut);
+        Ok(entry.map(|entry| {
+            entry.source = Source::from(data);
+            &*entry
+        }))
+    }
 
-        &self.store[id.uindex()]
+    /// Reads the file an entry was loaded from again, replacing its source text
+    ///
+    /// Fails with [`io::ErrorKind::NotFound`] if the entry does not exist or was not loaded from a file.
+    pub fn reload(&mut self, id: CacheId) -> io::Result<&CacheData> {
+        let path = match self.load(id).and_then(|entry| entry.path()) {
+            Some(path) => path.to_path_buf(),
+            None => return Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
+
+        let text = fs::read_to_string(path)?;
+        match self.replace(id, text) {
+            Ok(Some(entry)) => Ok(entry),
+            Ok(None) => unreachable!("entry was loaded above"),
+            Err(error) => Err(io::Error::other(error)),
+        }
+    }
+
+    /// Removes an entry from the cache, returning it if it existed
+    ///
+    /// The [`CacheId`] of the removed entry is never reused.
+    pub fn remove(&mut self, id: CacheId) -> Option<CacheData> {
+        if id.cache_id() != self.cache_id {
+            return None;
+        }
+
+        let entry = self.store.get_mut(id.uindex())?.take()?;
+        self.size -= entry.text().len();
+        Some(entry)
+    }
+
+    /// Returns the new total size if `size` bytes can replace `freed` bytes within the limits
+    fn check_limits(&self, label: &str, size: usize, freed: usize) -> Result<usize, CacheError> {
+        if let Some(limit) = self.entry_limit.filter(|limit| size > *limit) {
+            let label = label.to_string();
+            return Err(CacheError::EntryTooLarge { label, size, limit });
+        }
+
+        let total = self.size - freed + size;
+        if let Some(limit) = self.total_limit.filter(|limit| total > *limit) {
+            let (label, size) = (label.to_string(), total);
+            return Err(CacheError::CacheFull { label, size, limit });
+        }
+
+        Ok(total)
+    }
+
+    fn insert(
+        &mut self,
+        label: String,
+        data: String,
+        path: Option<PathBuf>,
+    ) -> Result<&CacheData, CacheError> {
+        let total = self.check_limits(&label, data.len(), 0)?;
+        let index = self.store.len();
+        let id = CacheId::build(self.cache_id, index);
+        self.store.push(None);
+        self.size = total;
+        Ok(self.store[index].insert(CacheData {
+            label,
+            source: Source::from(data),
+            path,
+            id,
+        }))
     }
 }
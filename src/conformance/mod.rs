@@ -0,0 +1,313 @@
+//! Programs that pin down the behavior of the language
+//!
+//! Each case is a `.boba` program starting with two header comments:
+//! `# spec: <section>` names the part of the language it covers, and
+//! `# expect: <value>` gives the displayed value of the last statement,
+//! or `# expect: error <code>` for programs that must fail with that error code.
+//! Cases with a `# lockstep` header run with [lockstep](Engine::set_lockstep) enabled,
+//! so their expected values must come out the same on every platform.
+//!
+//! [`run_panic_freedom`] reuses the cases as seeds for randomly mutated programs,
+//! checking that malformed input always produces an error instead of a panic.
+
+use std::fmt::Display;
+
+use crate::{
+    cache::CacheSpan,
+    engine::{error::RunError, FuncValue, NativeBuilder, Rng, Value},
+    BobaError, Engine,
+};
+
+macro_rules! cases {
+    ($($name:literal),* $(,)?) => {
+        &[$(($name, include_str!(concat!("cases/", $name, ".boba")))),*]
+    };
+}
+
+const CASES: &[(&str, &str)] = cases![
+    "arithmetic_precedence",
+    "subtraction_grouping",
+    "float_division",
+    "modulo",
+    "power",
+    "negation",
+    "division_by_zero",
+    "division_by_zero_power",
+    "division_by_zero_float_power",
+    "string_concat",
+    "adjacent_strings",
+    "boolean_logic",
+    "logic_or",
+    "let_binding",
+    "static_assignment",
+    "const_assignment",
+    "unknown_variable",
+    "function_call",
+    "function_globals",
+    "function_locals_hidden",
+    "parameter_count",
+    "recursion_limit",
+    "tail_recursion",
+    "ternary",
+    "ternary_condition",
+    "with_scope",
+    "unclosed_string",
+    "nesting_limit",
+    "long_chain",
+    "chain_limit",
+    "lockstep_precision",
+    "lockstep_int_division",
+    "lockstep_sqrt",
+    "lockstep_exp",
+    "lockstep_trig",
+    "lockstep_trig_reduction",
+];
+
+/// A single conformance program
+#[derive(Debug, Clone, Copy)]
+pub struct Case {
+    pub name: &'static str,
+    pub spec: &'static str,
+    pub expect: &'static str,
+    pub lockstep: bool,
+    pub source: &'static str,
+}
+
+/// Returns every case in the suite
+pub fn cases() -> Vec<Case> {
+    CASES
+        .iter()
+        .map(|&(name, source)| {
+            let header = |key: &str| {
+                source
+                    .lines()
+                    .find_map(|line| line.strip_prefix(key))
+                    .map(str::trim)
+                    .unwrap_or_default()
+            };
+
+            Case {
+                name,
+                spec: header("# spec:"),
+                expect: header("# expect:"),
+                lockstep: source.lines().any(|line| line.trim() == "# lockstep"),
+                source,
+            }
+        })
+        .collect()
+}
+
+/// The outcome of running a single case
+#[derive(Debug, Clone)]
+pub struct CaseResult {
+    pub case: Case,
+    /// The displayed value or `error <code>` the program produced
+    pub actual: String,
+}
+
+impl CaseResult {
+    pub fn passed(&self) -> bool {
+        self.case.expect == self.actual
+    }
+}
+
+impl Display for CaseResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.passed() {
+            true => write!(f, "pass {} [{}]", self.case.name, self.case.spec),
+            false => write!(
+                f,
+                "FAIL {} [{}]: expected {}, got {}",
+                self.case.name, self.case.spec, self.case.expect, self.actual
+            ),
+        }
+    }
+}
+
+/// Results for every case in the suite, in suite order
+#[derive(Debug, Clone)]
+pub struct ConformanceReport {
+    pub results: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    pub fn passed(&self) -> impl Iterator<Item = &CaseResult> {
+        self.results.iter().filter(|result| result.passed())
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &CaseResult> {
+        self.results.iter().filter(|result| !result.passed())
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(CaseResult::passed)
+    }
+}
+
+/// Runs every case in the suite, each in a fresh engine created by `config`
+///
+/// Hosts pass the same setup they use in production, so custom natives or
+/// disabled builtins that change core behavior show up as failures.
+pub fn run_conformance(mut config: impl FnMut() -> Engine<CacheSpan>) -> ConformanceReport {
+    let results = cases()
+        .into_iter()
+        .map(|case| {
+            let mut engine = config();
+            engine.set_lockstep(case.lockstep);
+            let actual = match engine.eval_str(case.name, case.source) {
+                Ok(value) => value.to_string(),
+                Err(error) if error.internal_message().is_some() => {
+                    format!("panic {}", error.internal_message().unwrap_or_default())
+                }
+                Err(BobaError::Parse(error)) => format!("error {}", error.code()),
+                Err(BobaError::Run(error)) => format!("error {}", error.code()),
+                Err(BobaError::Cache(error)) => format!("error {error}"),
+                Err(BobaError::Internal(message)) => format!("panic {message}"),
+            };
+            CaseResult { case, actual }
+        })
+        .collect();
+
+    ConformanceReport { results }
+}
+
+/// Program that defines a global, then panics inside a script function call
+const RECOVERY_SOURCE: &str = "let x = 5\nlet f = fn(): host_panic()\nf()";
+
+/// Checks that one engine stays usable after evaluation panics, returning every failed check
+///
+/// A host native that panics is called from inside a script function, so the panic
+/// unwinds past an open call frame and stashed scope. The same engine must then still
+/// see the global defined before the panic and have an empty call stack, both after
+/// [`Engine::eval_str`] and after calling the function directly with [`Engine::eval_func`].
+pub fn run_panic_recovery(mut config: impl FnMut() -> Engine<CacheSpan>) -> Vec<String> {
+    let mut engine = config();
+    let host_panic = NativeBuilder::new("host_panic").build(|| -> bool {
+        panic!("host native panicked on purpose");
+    });
+    engine.init_const("host_panic", Value::Func(FuncValue::native(host_panic)));
+
+    let mut failures = Vec::new();
+    match engine.eval_str("recovery", RECOVERY_SOURCE) {
+        Err(error) if error.internal_message().is_some() => (),
+        output => failures.push(format!("eval_str: expected a caught panic, got {output:?}")),
+    }
+    check_recovered(&mut engine, "eval_str", &mut failures);
+
+    let Some(Value::Func(func)) = engine.get_value("f").cloned() else {
+        failures.push("eval_str: 'f' is no longer defined".to_string());
+        return failures;
+    };
+    let data = engine
+        .cache_mut()
+        .store("recovery", "f()")
+        .map(|data| data.span(0..3));
+    match data.map(|data| engine.eval_func(&func, Vec::new(), &data)) {
+        Ok(Err(RunError::Internal { .. })) => (),
+        output => failures.push(format!(
+            "eval_func: expected a caught panic, got {output:?}"
+        )),
+    }
+    check_recovered(&mut engine, "eval_func", &mut failures);
+
+    failures
+}
+
+/// Checks that `engine` has no call frames left open and still sees the global `x`
+fn check_recovered(engine: &mut Engine<CacheSpan>, after: &str, failures: &mut Vec<String>) {
+    if !engine.call_stack().is_empty() {
+        let depth = engine.call_stack().len();
+        failures.push(format!("{after}: {depth} call frames were left open"));
+    }
+
+    match engine.eval_str("recovery", "x") {
+        Ok(value) if value.to_string() == "5" => (),
+        output => failures.push(format!("{after}: expected 'x' to be 5, got {output:?}")),
+    }
+}
+
+/// Characters inserted by mutations, biased towards ones the lexer treats specially
+const MUTATION_CHARS: &str = "()+-*/%<>=!?:,.'\"#_ \n019exfn\u{e9}\u{1f600}";
+
+/// Fuel given to each mutated program, so mutations that recurse forever still finish
+const MUTATION_FUEL: u64 = 10_000;
+
+/// A mutated program that made the engine panic or left it unusable
+#[derive(Debug, Clone)]
+pub struct PanicCase {
+    pub source: String,
+    pub message: String,
+}
+
+/// Runs `rounds` random mutations of every case, returning the programs that panicked
+///
+/// Each engine is reused for a small probe program afterwards, so programs that
+/// leave the engine unusable are returned as well.
+///
+/// Mutations are generated from `seed`, so failures can be reproduced by running again with the same seed.
+pub fn run_panic_freedom(
+    mut config: impl FnMut() -> Engine<CacheSpan>,
+    rounds: usize,
+    seed: u64,
+) -> Vec<PanicCase> {
+    let mut rng = Rng::new(seed);
+    let mut panics = Vec::new();
+    for case in cases() {
+        for _ in 0..rounds {
+            let source = mutate(case.source, &mut rng);
+            let mut engine = config();
+            engine.set_fuel(Some(MUTATION_FUEL));
+            if let Err(error) = engine.eval_str(case.name, source.clone()) {
+                if let Some(message) = error.internal_message() {
+                    let message = message.to_string();
+                    panics.push(PanicCase { source, message });
+                    continue;
+                }
+            }
+
+            // the engine must stay usable, whatever the mutated program left behind
+            engine.set_fuel(None);
+            let probe = engine
+                .eval_str("probe", "1 + 1")
+                .map(|value| value.to_string());
+            if !engine.call_stack().is_empty() || !matches!(probe.as_deref(), Ok("2")) {
+                let message = format!("engine unusable afterwards: {probe:?}");
+                panics.push(PanicCase { source, message });
+            }
+        }
+    }
+
+    panics
+}
+
+/// Applies between one and four random edits to `source`
+fn mutate(source: &str, rng: &mut Rng) -> String {
+    let mut chars: Vec<char> = source.chars().collect();
+    for _ in 0..=pick(rng, 4) {
+        let index = pick(rng, chars.len() + 1);
+        match pick(rng, 4) {
+            0 => chars.truncate(index),
+            1 if index < chars.len() => drop(chars.remove(index)),
+            2 => {
+                let count = MUTATION_CHARS.chars().count();
+                let c = MUTATION_CHARS.chars().nth(pick(rng, count)).unwrap_or(' ');
+                chars.insert(index, c);
+            }
+            _ => {
+                let end = (index + pick(rng, 8)).min(chars.len());
+                let copy = chars[index.min(end)..end].to_vec();
+                chars.splice(index..index, copy);
+            }
+        }
+    }
+
+    chars.into_iter().collect()
+}
+
+/// Returns a random index below `len`, or 0 if `len` is 0
+fn pick(rng: &mut Rng, len: usize) -> usize {
+    match len {
+        0 => 0,
+        len => rng.range(0, len as i64 - 1).unwrap_or_default() as usize,
+    }
+}
@@ -0,0 +1,214 @@
+use ariadne::{Color, Label, Report, ReportKind, Span};
+
+use crate::{analysis::Rule, cache::CacheSpan, BobaError};
+
+/// How serious a [`Diagnostic`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Hint,
+    Warning,
+    Error,
+}
+
+/// A non-fatal problem found in a script
+#[derive(Debug, Clone)]
+pub enum Warning<Data> {
+    UnusedVariable {
+        ident: String,
+        data: Data,
+    },
+    FloatEquality {
+        data: Data,
+    },
+    ShadowedBinding {
+        ident: String,
+        data: Data,
+        shadowed: Data,
+    },
+    UnusedFunction {
+        ident: String,
+        data: Data,
+    },
+    ShadowedParameter {
+        ident: String,
+        data: Data,
+        shadowed: Data,
+    },
+    ConstantCondition {
+        data: Data,
+        /// The branch that can never be taken, when the condition is a plain bool
+        unreachable: Option<Data>,
+    },
+}
+
+impl<Data> Warning<Data> {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Warning::UnusedVariable { .. }
+            | Warning::FloatEquality { .. }
+            | Warning::UnusedFunction { .. }
+            | Warning::ShadowedParameter { .. }
+            | Warning::ConstantCondition { .. } => Severity::Warning,
+            Warning::ShadowedBinding { .. } => Severity::Hint,
+        }
+    }
+
+    pub fn data(&self) -> &Data {
+        match self {
+            Warning::UnusedVariable { data, .. }
+            | Warning::FloatEquality { data }
+            | Warning::ShadowedBinding { data, .. }
+            | Warning::UnusedFunction { data, .. }
+            | Warning::ShadowedParameter { data, .. }
+            | Warning::ConstantCondition { data, .. } => data,
+        }
+    }
+
+    /// Returns the lint rule that produces the warning, or `None` if it is found while running
+    pub fn rule(&self) -> Option<Rule> {
+        match self {
+            Warning::UnusedVariable { .. } => Some(Rule::UnusedVariable),
+            Warning::FloatEquality { .. } => None,
+            Warning::ShadowedBinding { .. } => Some(Rule::ShadowedBinding),
+            Warning::UnusedFunction { .. } => Some(Rule::UnusedFunction),
+            Warning::ShadowedParameter { .. } => Some(Rule::ShadowedParameter),
+            Warning::ConstantCondition { .. } => Some(Rule::ConstantCondition),
+        }
+    }
+}
+
+impl Warning<CacheSpan> {
+    pub fn report(&self) -> Report<'_, CacheSpan> {
+        let kind = match self.severity() {
+            Severity::Hint => ReportKind::Advice,
+            _ => ReportKind::Warning,
+        };
+
+        let report = match self {
+            Warning::UnusedVariable { ident, data } => {
+                Report::build(kind, *data.source(), data.start())
+                    .with_message("Unused Variable")
+                    .with_code("W-001")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Yellow)
+                            .with_message(format!("'{ident}' is never used")),
+                    )
+                    .with_help(format!("prefix it with an underscore: '_{ident}'"))
+            }
+            Warning::FloatEquality { data } => Report::build(kind, *data.source(), data.start())
+                .with_message("Float Equality")
+                .with_code("W-002")
+                .with_label(
+                    Label::new(data.clone())
+                        .with_color(Color::Yellow)
+                        .with_message("floats are compared for exact equality"),
+                ),
+            Warning::ShadowedBinding {
+                ident,
+                data,
+                shadowed,
+            } => Report::build(kind, *data.source(), data.start())
+                .with_message("Shadowed Binding")
+                .with_code("W-003")
+                .with_label(
+                    Label::new(data.clone())
+                        .with_color(Color::Cyan)
+                        .with_message(format!("'{ident}' shadows an earlier definition")),
+                )
+                .with_label(
+                    Label::new(shadowed.clone())
+                        .with_color(Color::Blue)
+                        .with_message("previously defined here"),
+                ),
+            Warning::UnusedFunction { ident, data } => {
+                Report::build(kind, *data.source(), data.start())
+                    .with_message("Unused Function")
+                    .with_code("W-004")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Yellow)
+                            .with_message(format!("'{ident}' can never be called")),
+                    )
+            }
+            Warning::ShadowedParameter {
+                ident,
+                data,
+                shadowed,
+            } => Report::build(kind, *data.source(), data.start())
+                .with_message("Shadowed Parameter")
+                .with_code("W-005")
+                .with_label(
+                    Label::new(data.clone())
+                        .with_color(Color::Yellow)
+                        .with_message(format!("parameter '{ident}' hides an earlier definition")),
+                )
+                .with_label(
+                    Label::new(shadowed.clone())
+                        .with_color(Color::Blue)
+                        .with_message("previously defined here"),
+                )
+                .with_help("rename the parameter"),
+            Warning::ConstantCondition { data, unreachable } => {
+                let mut report = Report::build(kind, *data.source(), data.start())
+                    .with_message("Constant Condition")
+                    .with_code("W-006")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Yellow)
+                            .with_message("this condition is the same on every run"),
+                    );
+                if let Some(unreachable) = unreachable {
+                    report = report.with_label(
+                        Label::new(unreachable.clone())
+                            .with_color(Color::Blue)
+                            .with_message("this branch is never evaluated"),
+                    );
+                }
+                report
+            }
+        };
+
+        match self.rule() {
+            Some(rule) => report.with_note(format!("reported by the '{rule}' lint rule")),
+            None => report,
+        }
+        .finish()
+    }
+}
+
+/// Any error, warning or hint produced while checking or running a script
+#[derive(Debug, Clone)]
+pub enum Diagnostic {
+    Error(BobaError),
+    Warning(Warning<CacheSpan>),
+}
+
+impl From<BobaError> for Diagnostic {
+    fn from(error: BobaError) -> Self {
+        Self::Error(error)
+    }
+}
+
+impl From<Warning<CacheSpan>> for Diagnostic {
+    fn from(warning: Warning<CacheSpan>) -> Self {
+        Self::Warning(warning)
+    }
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Diagnostic::Error(_) => Severity::Error,
+            Diagnostic::Warning(warning) => warning.severity(),
+        }
+    }
+
+    /// Builds an ariadne [`Report`] for the diagnostic, or `None` if it has no source location
+    pub fn report(&self) -> Option<Report<'_, CacheSpan>> {
+        match self {
+            Diagnostic::Error(error) => error.report(),
+            Diagnostic::Warning(warning) => Some(warning.report()),
+        }
+    }
+}
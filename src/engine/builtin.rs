@@ -1,21 +1,822 @@
-use crate::{engine::Value, Engine};
+use std::{
+    cmp::Ordering,
+    env::{self, VarError},
+    fs, io,
+    str::FromStr,
+    time::Duration,
+};
 
-use super::{FuncValue, NativeFunc};
+use dashu::{
+    base::{Abs, SquareRoot},
+    float::DBig,
+    integer::IBig,
+};
+use unicode_segmentation::UnicodeSegmentation;
 
-pub fn load_builtins<Data: Clone>(engine: &mut Engine<Data>) {
-    load_print(engine);
+use crate::{
+    cache::CacheSpan,
+    engine::{value::ValueType, Value},
+    BobaError, Engine,
+};
+
+use super::{
+    error::{NativeError, RunError},
+    Capability, FloatFormat, FuncValue, NativeBuilder, WeakUserData,
+};
+
+/// Loads the builtin natives, skipping groups that need a capability the engine does not grant
+pub fn load_builtins<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let capabilities = *engine.capabilities();
+    if capabilities.io {
+        load_print(engine);
+        load_input(engine);
+        #[cfg(feature = "prompt")]
+        super::prompt::load_prompt(engine);
+    }
+    load_format(engine);
+    load_radix(engine);
+    load_weak(engine);
+    load_math(engine);
+    load_string(engine);
+    load_style(engine);
+    load_introspection(engine);
+    if capabilities.random {
+        load_random(engine);
+    }
+    if capabilities.time {
+        load_time(engine);
+    }
+    if capabilities.env {
+        load_env(engine);
+    }
+    if capabilities.filesystem {
+        load_filesystem(engine);
+    }
+    #[cfg(feature = "serde")]
+    super::json::load_json(engine);
+}
+
+/// Helper functions written in boba, embedded in the crate at build time
+pub const PRELUDE: &str = include_str!("prelude.boba");
+
+/// Evaluates the [`PRELUDE`] in `engine`, which must already have the builtins loaded
+pub fn load_prelude(engine: &mut Engine<CacheSpan>) -> Result<(), BobaError> {
+    engine.eval_str("prelude", PRELUDE)?;
+    Ok(())
+}
+
+/// Wraps a host io error, keeping it as the source so reports show what caused it
+pub(crate) fn io_error(message: impl Into<String>, error: io::Error) -> NativeError {
+    NativeError::new(message).with_kind("io").with_source(error)
+}
+
+pub fn load_print<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let print = NativeBuilder::new("print")
+        .arg::<Value<Data>>()
+        .build_with_engine(|engine, value| {
+            let line = match value {
+                Value::None => String::new(),
+                Value::String(v) => v.to_string(),
+                value => value.display(engine.float_format()).to_string(),
+            };
+
+            engine
+                .print(&line)
+                .map_err(|error| io_error("failed to print", error))
+        });
+
+    let print = print.requires(Capability::Io);
+    engine.init_const("print", Value::Func(FuncValue::native(print)));
+}
+
+pub fn load_input<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let input = NativeBuilder::new("input")
+        .arg::<String>()
+        .build_with_engine(|engine, prompt| {
+            engine
+                .read_line(&prompt)
+                .map_err(|error| io_error("failed to read input", error))
+        });
+
+    let input = input.requires(Capability::Io);
+    engine.init_const("input", Value::Func(FuncValue::native(input)));
+}
+
+pub fn load_format<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let format = NativeBuilder::new("format")
+        .arg::<Value<Data>>()
+        .arg::<i64>()
+        .build_with_engine(|engine, value, digits| {
+            // zero significant digits would turn rounding off instead
+            let digits = match usize::try_from(digits) {
+                Ok(digits) if digits > 0 => digits,
+                _ => return Err(NativeError::new("digits must be positive").with_arg(1)),
+            };
+
+            let format = FloatFormat {
+                max_digits: Some(digits),
+                ..*engine.float_format()
+            };
+
+            Ok(match value {
+                Value::String(v) => Value::String(v),
+                value => Value::String(value.display(&format).to_string().into()),
+            })
+        });
+
+    engine.init_const("format", Value::Func(FuncValue::native(format)));
+}
+
+fn check_radix(radix: i64) -> Result<u32, NativeError> {
+    match u32::try_from(radix) {
+        Ok(radix @ 2..=36) => Ok(radix),
+        _ => Err(NativeError::new(format!("invalid radix {radix}"))
+            .with_help("radix must be between 2 and 36")
+            .with_arg(1)),
+    }
+}
+
+fn to_radix(value: &IBig, radix: i64) -> Result<String, NativeError> {
+    let radix = check_radix(radix)?;
+    Ok(value.in_radix(radix).to_string())
+}
+
+fn from_radix(str: &str, radix: i64) -> Result<IBig, NativeError> {
+    let radix = check_radix(radix)?;
+
+    // allow the common prefixes for hex and binary strings
+    let (sign, digits) = match str.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", str),
+    };
+    let digits = match radix {
+        16 => digits.trim_start_matches("0x").trim_start_matches("0X"),
+        2 => digits.trim_start_matches("0b").trim_start_matches("0B"),
+        _ => digits,
+    };
+
+    match IBig::from_str_radix(&format!("{sign}{digits}"), radix) {
+        Ok(value) => Ok(value),
+        Err(error) => {
+            let message = format!("could not parse '{str}' in base {radix}: {error}");
+            Err(NativeError::new(message).with_arg(0))
+        }
+    }
+}
+
+pub fn load_radix<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let natives = [
+        (
+            "to_hex",
+            NativeBuilder::new("to_hex")
+                .arg::<IBig>()
+                .build(|value| to_radix(&value, 16)),
+        ),
+        (
+            "to_bin",
+            NativeBuilder::new("to_bin")
+                .arg::<IBig>()
+                .build(|value| to_radix(&value, 2)),
+        ),
+        (
+            "to_base",
+            NativeBuilder::new("to_base")
+                .arg::<IBig>()
+                .arg::<i64>()
+                .build(|value, radix| to_radix(&value, radix)),
+        ),
+        (
+            "from_hex",
+            NativeBuilder::new("from_hex")
+                .arg::<String>()
+                .build(|str| from_radix(&str, 16)),
+        ),
+        (
+            "from_bin",
+            NativeBuilder::new("from_bin")
+                .arg::<String>()
+                .build(|str| from_radix(&str, 2)),
+        ),
+        (
+            "from_base",
+            NativeBuilder::new("from_base")
+                .arg::<String>()
+                .arg::<i64>()
+                .build(|str, radix| from_radix(&str, radix)),
+        ),
+    ];
+
+    for (ident, native) in natives {
+        engine.init_const(ident, Value::Func(FuncValue::native(native)));
+    }
+}
+
+pub fn load_weak<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let is_alive = NativeBuilder::new("is_alive")
+        .arg::<WeakUserData>()
+        .build(|weak| weak.is_alive());
+
+    let upgrade = NativeBuilder::new("upgrade")
+        .arg::<WeakUserData>()
+        .build(|weak| weak.upgrade());
+
+    engine.init_const("is_alive", Value::Func(FuncValue::native(is_alive)));
+    engine.init_const("upgrade", Value::Func(FuncValue::native(upgrade)));
+}
+
+/// Number of significant digits used for results that are not exact, like `sqrt(2)`
+const MATH_PRECISION: usize = 32;
+
+const PI: &str = "3.1415926535897932384626433832795028841971693993751";
+const E: &str = "2.7182818284590452353602874713526624977572470936999";
+
+fn with_math_precision(value: DBig) -> DBig {
+    let precision = value.precision().max(MATH_PRECISION);
+    value.with_precision(precision).value()
+}
+
+/// Largest number of integer digits an angle can have before sin and cos refuse it
+///
+/// Reducing an angle needs PI to as many digits as the angle has, on top of the result precision.
+const MAX_ANGLE_DIGITS: usize = 1000;
+
+/// Calculates the arctangent of `1 / n` using a taylor series
+fn atan_inverse(n: u32, precision: usize) -> DBig {
+    let n = DBig::from(n).with_precision(precision).value();
+    let n_squared = &n * &n;
+    let mut power = DBig::ONE.with_precision(precision).value() / &n;
+    let mut sum = power.clone();
+    for k in 1u32.. {
+        power /= &n_squared;
+        let term = &power / DBig::from(2 * k + 1);
+        let next = match k % 2 {
+            0 => &sum + term,
+            _ => &sum - term,
+        };
+        if next == sum {
+            break;
+        }
+        sum = next;
+    }
+    sum
+}
+
+/// Returns PI to at least `digits` significant digits, using Machin's formula past the constant
+fn pi(digits: usize) -> DBig {
+    let pi = DBig::from_str(PI).expect("PI is a valid float");
+    if digits < pi.digits() {
+        return pi.with_precision(digits).value();
+    }
+
+    let work = digits + 8;
+    let pi = atan_inverse(5, work) * DBig::from(16u8) - atan_inverse(239, work) * DBig::from(4u8);
+    pi.with_precision(digits).value()
+}
+
+/// Calculates the sine or cosine of `value` using a taylor series
+///
+/// This avoids the platform math library, so results are identical everywhere.
+fn sin_cos(value: DBig, cos: bool) -> Result<DBig, NativeError> {
+    let precision = value.precision().max(MATH_PRECISION);
+    let work = precision + 8;
+
+    // the angle keeps all of its integer digits while it is reduced,
+    // so PI needs those digits on top of the working precision
+    let repr = value.repr();
+    let integer_digits = (repr.digits() as isize + repr.exponent()).max(0) as usize;
+    if integer_digits > MAX_ANGLE_DIGITS {
+        let message = format!("angle has more than {MAX_ANGLE_DIGITS} integer digits");
+        return Err(NativeError::new(message).with_arg(0));
+    }
+    let reduce = work + integer_digits;
+
+    // reduce the angle to the range -PI..=PI so the series converges quickly
+    let two_pi = pi(reduce) * DBig::from(2u8);
+    let value = value.with_precision(reduce).value();
+    let turns = (&value / &two_pi).round();
+    let x = (value - two_pi * turns).with_precision(work).value();
+    let x_squared = &x * &x;
+
+    let (mut term, mut n) = match cos {
+        true => (DBig::ONE.with_precision(work).value(), 0u32),
+        false => (x, 1u32),
+    };
+    let mut sum = term.clone();
+    loop {
+        term = -term * &x_squared / DBig::from((n + 1) * (n + 2));
+        n += 2;
+
+        let next = &sum + &term;
+        if next == sum {
+            break;
+        }
+        sum = next;
+    }
+
+    Ok(sum.with_precision(precision).value())
+}
+
+fn number_arg<Data>(value: &Value<Data>, index: usize) -> Result<DBig, NativeError> {
+    match value {
+        Value::Int(v) => Ok(DBig::from(v.clone())),
+        Value::Float(v) => Ok(v.clone()),
+        value => Err(
+            NativeError::new(format!("expected a number, found '{}'", value.get_type()))
+                .with_arg(index),
+        ),
+    }
+}
+
+fn abs<Data>(value: Value<Data>) -> Result<Value<Data>, NativeError> {
+    match value {
+        Value::Int(v) => Ok(Value::Int(v.abs())),
+        value => Ok(Value::Float(number_arg(&value, 0)?.abs())),
+    }
+}
+
+/// Applies a rounding function to floats, leaving ints unchanged
+fn round_with<Data>(value: Value<Data>, round: fn(&DBig) -> DBig) -> Result<IBig, NativeError> {
+    match value {
+        Value::Int(v) => Ok(v),
+        value => Ok(round(&number_arg(&value, 0)?).to_int().value()),
+    }
+}
+
+/// Returns whichever argument is picked by `pick`, keeping its original type
+fn compare_with<Data>(
+    v1: Value<Data>,
+    v2: Value<Data>,
+    pick: Ordering,
+) -> Result<Value<Data>, NativeError> {
+    let order = number_arg(&v1, 0)?.cmp(&number_arg(&v2, 1)?);
+    match order == pick || order == Ordering::Equal {
+        true => Ok(v1),
+        false => Ok(v2),
+    }
+}
+
+/// Loads math natives and constants
+///
+/// Every native is implemented with decimal software arithmetic
+/// so results are bit identical across platforms.
+pub fn load_math<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let natives = [
+        (
+            "sqrt",
+            NativeBuilder::new("sqrt").arg::<DBig>().build(|value| {
+                if value < DBig::ZERO {
+                    return Err(NativeError::new(
+                        "cannot take the square root of a negative number",
+                    )
+                    .with_arg(0));
+                }
+                Ok(with_math_precision(value).sqrt())
+            }),
+        ),
+        (
+            "abs",
+            NativeBuilder::new("abs").arg::<Value<Data>>().build(abs),
+        ),
+        (
+            "floor",
+            NativeBuilder::new("floor")
+                .arg::<Value<Data>>()
+                .build(|value| round_with(value, DBig::floor)),
+        ),
+        (
+            "ceil",
+            NativeBuilder::new("ceil")
+                .arg::<Value<Data>>()
+                .build(|value| round_with(value, DBig::ceil)),
+        ),
+        (
+            "round",
+            NativeBuilder::new("round")
+                .arg::<Value<Data>>()
+                .build(|value| round_with(value, DBig::round)),
+        ),
+        (
+            "sin",
+            NativeBuilder::new("sin")
+                .arg::<DBig>()
+                .build(|value| sin_cos(value, false)),
+        ),
+        (
+            "cos",
+            NativeBuilder::new("cos")
+                .arg::<DBig>()
+                .build(|value| sin_cos(value, true)),
+        ),
+        (
+            "tan",
+            NativeBuilder::new("tan").arg::<DBig>().build(|value| {
+                Ok::<_, NativeError>(sin_cos(value.clone(), false)? / sin_cos(value, true)?)
+            }),
+        ),
+        (
+            "log",
+            NativeBuilder::new("log").arg::<DBig>().build(|value| {
+                if value <= DBig::ZERO {
+                    return Err(
+                        NativeError::new("logarithm is only defined for positive numbers")
+                            .with_arg(0),
+                    );
+                }
+                Ok(with_math_precision(value).ln())
+            }),
+        ),
+        (
+            "exp",
+            NativeBuilder::new("exp")
+                .arg::<DBig>()
+                .build(|value| with_math_precision(value).exp()),
+        ),
+        (
+            "min",
+            NativeBuilder::new("min")
+                .arg::<Value<Data>>()
+                .arg::<Value<Data>>()
+                .build(|v1, v2| compare_with(v1, v2, Ordering::Less)),
+        ),
+        (
+            "max",
+            NativeBuilder::new("max")
+                .arg::<Value<Data>>()
+                .arg::<Value<Data>>()
+                .build(|v1, v2| compare_with(v1, v2, Ordering::Greater)),
+        ),
+    ];
+
+    for (ident, native) in natives {
+        engine.init_const(ident, Value::Func(FuncValue::native(native)));
+    }
+
+    for (ident, digits) in [("PI", PI), ("E", E)] {
+        let value = DBig::from_str(digits).expect("math constants are valid floats");
+        engine.init_const(ident, Value::Float(value));
+    }
+}
+
+/// Converts a grapheme index argument into a byte offset into `str`
+fn grapheme_offset(str: &str, index: i64, arg: usize) -> Result<usize, NativeError> {
+    let error = || {
+        let count = str.graphemes(true).count();
+        NativeError::new(format!(
+            "index {index} is out of range for a string of length {count}"
+        ))
+        .with_arg(arg)
+    };
+
+    let index = usize::try_from(index).map_err(|_| error())?;
+    match str.grapheme_indices(true).nth(index) {
+        Some((offset, _)) => Ok(offset),
+        None if index == str.graphemes(true).count() => Ok(str.len()),
+        None => Err(error()),
+    }
+}
+
+fn substring(str: String, start: i64, end: i64) -> Result<String, NativeError> {
+    let start_offset = grapheme_offset(&str, start, 1)?;
+    let end_offset = grapheme_offset(&str, end, 2)?;
+    match start_offset <= end_offset {
+        true => Ok(str[start_offset..end_offset].to_string()),
+        false => Err(NativeError::new(format!("start {start} is after end {end}")).with_arg(1)),
+    }
+}
+
+/// Styles that can be applied to text, with their SGR escape code
+const STYLES: [(&str, u8); 12] = [
+    ("bold", 1),
+    ("dim", 2),
+    ("italic", 3),
+    ("underline", 4),
+    ("black", 30),
+    ("red", 31),
+    ("green", 32),
+    ("yellow", 33),
+    ("blue", 34),
+    ("magenta", 35),
+    ("cyan", 36),
+    ("white", 37),
+];
+
+/// Loads a `style_` native for each of the [`STYLES`]
+///
+/// The natives return the text unchanged when the engine has
+/// [color disabled](Engine::set_color_enabled), so scripts never need to check.
+pub fn load_style<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    for (name, code) in STYLES {
+        let ident = format!("style_{name}");
+        let native = NativeBuilder::new(&ident)
+            .arg::<String>()
+            .build_with_engine(move |engine, text| {
+                Ok::<_, NativeError>(match engine.is_color_enabled() {
+                    true => format!("\x1b[{code}m{text}\x1b[0m"),
+                    false => text,
+                })
+            });
+        engine.init_const(ident.as_str(), Value::Func(FuncValue::native(native)));
+    }
+}
+
+/// Loads natives for text processing
+///
+/// Lengths and indices count user perceived characters,
+/// so `len('héllo')` is 5 no matter how the accent is encoded.
+pub fn load_string<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let natives = [
+        (
+            "len",
+            NativeBuilder::new("len")
+                .arg::<String>()
+                .build(|str| IBig::from(str.graphemes(true).count())),
+        ),
+        (
+            "upper",
+            NativeBuilder::new("upper")
+                .arg::<String>()
+                .build(|str| str.to_uppercase()),
+        ),
+        (
+            "lower",
+            NativeBuilder::new("lower")
+                .arg::<String>()
+                .build(|str| str.to_lowercase()),
+        ),
+        (
+            "trim",
+            NativeBuilder::new("trim")
+                .arg::<String>()
+                .build(|str| str.trim().to_string()),
+        ),
+        (
+            "contains",
+            NativeBuilder::new("contains")
+                .arg::<String>()
+                .arg::<String>()
+                .build(|str, pattern| str.contains(&pattern)),
+        ),
+        (
+            "starts_with",
+            NativeBuilder::new("starts_with")
+                .arg::<String>()
+                .arg::<String>()
+                .build(|str, pattern| str.starts_with(&pattern)),
+        ),
+        (
+            "ends_with",
+            NativeBuilder::new("ends_with")
+                .arg::<String>()
+                .arg::<String>()
+                .build(|str, pattern| str.ends_with(&pattern)),
+        ),
+        (
+            "replace",
+            NativeBuilder::new("replace")
+                .arg::<String>()
+                .arg::<String>()
+                .arg::<String>()
+                .build(|str, from, to| str.replace(&from, &to)),
+        ),
+        (
+            "substring",
+            NativeBuilder::new("substring")
+                .arg::<String>()
+                .arg::<i64>()
+                .arg::<i64>()
+                .build(substring),
+        ),
+    ];
+
+    for (ident, native) in natives {
+        engine.init_const(ident, Value::Func(FuncValue::native(native)));
+    }
+}
+
+/// Lists the visible identifiers that either are or are not functions
+///
+/// Locals are listed before globals, and globals hidden by a local are skipped.
+fn visible_idents<Data: Clone>(engine: &Engine<Data>, funcs: bool) -> String {
+    let locals: Vec<_> = engine.locals().collect();
+    let globals = (engine.globals()).filter(|(ident, _)| !locals.iter().any(|(l, _)| l == ident));
+
+    let idents: Vec<_> = (locals.iter().copied().chain(globals))
+        .filter(|(_, value)| matches!(value, Value::Func(_)) == funcs)
+        .map(|(ident, _)| ident)
+        .collect();
+    idents.join(", ")
+}
+
+/// Loads natives for exploring values and scopes, mostly useful in the shell
+pub fn load_introspection<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let type_of = NativeBuilder::new("type")
+        .arg::<Value<Data>>()
+        .build(|value| value.get_type().to_string());
+
+    let vars = NativeBuilder::new("vars").build_with_engine(|engine| visible_idents(engine, false));
+    let funcs =
+        NativeBuilder::new("funcs").build_with_engine(|engine| visible_idents(engine, true));
+
+    engine.init_const("type", Value::Func(FuncValue::native(type_of)));
+    engine.init_const("vars", Value::Func(FuncValue::native(vars)));
+    engine.init_const("funcs", Value::Func(FuncValue::native(funcs)));
+}
+
+/// Loads natives for generating random numbers from the engine's [`Rng`](super::Rng)
+pub fn load_random<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let random =
+        NativeBuilder::new("random").build_with_engine(|engine| engine.rng_mut().next_f64());
+
+    let rand_int = NativeBuilder::new("rand_int")
+        .arg::<i64>()
+        .arg::<i64>()
+        .build_with_engine(
+            |engine, low, high| match engine.rng_mut().range(low, high) {
+                Some(value) => Ok(value),
+                None => Err(NativeError::new(format!("range {low} to {high} is empty"))
+                    .with_help("the low value must not be greater than the high value")
+                    .with_arg(1)),
+            },
+        );
+
+    let rand_seed = NativeBuilder::new("rand_seed")
+        .arg::<i64>()
+        .build_with_engine(|engine, seed| engine.rng_mut().seed(seed as u64));
+
+    for (ident, native) in [
+        ("random", random),
+        ("rand_int", rand_int),
+        ("rand_seed", rand_seed),
+    ] {
+        let native = native.requires(Capability::Random);
+        engine.init_const(ident, Value::Func(FuncValue::native(native)));
+    }
+}
+
+fn check_clock<Data: Clone>(engine: &Engine<Data>) -> Result<(), NativeError> {
+    match engine.is_lockstep() {
+        false => Ok(()),
+        true => Err(NativeError::new(
+            "clocks are not available in lockstep mode",
+        )),
+    }
+}
+
+fn sleep<Data: Clone>(engine: &mut Engine<Data>, seconds: f64) -> Result<(), NativeError> {
+    if !engine.is_sleep_allowed() {
+        return Err(NativeError::new("sleep is disabled by the host"));
+    }
+
+    let duration = match Duration::try_from_secs_f64(seconds) {
+        Ok(duration) => duration,
+        Err(_) => {
+            let message = format!("cannot sleep for {seconds} seconds");
+            return Err(NativeError::new(message).with_arg(0));
+        }
+    };
+
+    let deadline = engine.deadline().filter(|_| !engine.is_lockstep());
+    engine
+        .clock()
+        .sleep(duration, deadline, engine.cancel_token());
+    Ok(())
+}
+
+/// Calls `func` until it succeeds, sleeping `delay` seconds between failed attempts
+///
+/// Interruptions like cancellation or running out of fuel are returned as they are.
+fn retry<Data: Clone>(
+    engine: &mut Engine<Data>,
+    func: FuncValue<Data>,
+    attempts: i64,
+    delay: f64,
+) -> Result<Value<Data>, NativeError> {
+    if attempts < 1 {
+        let message = format!("cannot retry with {attempts} attempts");
+        return Err(NativeError::new(message)
+            .with_help("at least one attempt is needed")
+            .with_arg(1));
+    }
+
+    if func.param_count() != 0 {
+        let found = ValueType::Func(func.param_count());
+        let message = format!("expected a function without parameters, found '{found}'");
+        return Err(NativeError::new(message).with_arg(0));
+    }
+
+    // errors from the retried function point at the retry call
+    let Some(data) = engine.call_stack().last().map(|frame| frame.data.clone()) else {
+        return Err(NativeError::new("retry must be called from a script"));
+    };
+
+    let mut last = String::new();
+    for attempt in 1..=attempts {
+        match engine.eval_func(&func, Vec::new(), &data) {
+            Ok(value) => return Ok(value),
+            Err(error) => {
+                // interruptions would fail every attempt the same way
+                let interrupted = matches!(
+                    error.root(),
+                    RunError::Cancelled { .. }
+                        | RunError::Timeout { .. }
+                        | RunError::OutOfFuel { .. }
+                        | RunError::OutputLimit { .. }
+                );
+                if interrupted {
+                    return Err(engine.interrupt_native(error));
+                }
+
+                last = match error.root() {
+                    RunError::NativeCallError { message, .. } => message.clone(),
+                    error => format!("error {}", error.code()),
+                };
+            }
+        }
+
+        if attempt < attempts {
+            sleep(engine, delay)?;
+        }
+    }
+
+    let message = format!("gave up after {attempts} attempts, the last failed with: {last}");
+    Err(NativeError::new(message).with_kind("retry"))
+}
+
+/// Loads natives for reading the time, sleeping and retrying with a delay
+pub fn load_time<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let time = NativeBuilder::new("time").build_with_engine(|engine| {
+        check_clock(engine)?;
+        match engine.clock().unix_time() {
+            Some(elapsed) => Ok(elapsed.as_secs_f64()),
+            None => Err(NativeError::new("system time is before the unix epoch")),
+        }
+    });
+
+    let clock = NativeBuilder::new("clock").build_with_engine(|engine| {
+        check_clock(engine)?;
+        Ok::<_, NativeError>(engine.uptime().as_secs_f64())
+    });
+
+    let sleep = NativeBuilder::new("sleep")
+        .arg::<f64>()
+        .build_with_engine(sleep);
+
+    let retry = NativeBuilder::new("retry")
+        .arg::<FuncValue<Data>>()
+        .arg::<i64>()
+        .arg::<f64>()
+        .build_with_engine(retry);
+
+    for (ident, native) in [
+        ("time", time),
+        ("clock", clock),
+        ("sleep", sleep),
+        ("retry", retry),
+    ] {
+        let native = native.requires(Capability::Time);
+        engine.init_const(ident, Value::Func(FuncValue::native(native)));
+    }
 }
 
-pub fn load_print<Data: Clone>(engine: &mut Engine<Data>) {
-    engine.init_const(
-        "print",
-        Value::Func(FuncValue::native(NativeFunc::new(1, |values| {
-            match &values[0] {
-                Value::None => println!(""),
-                Value::String(v) => println!("{v}"),
-                value => println!("{value}"),
+/// Loads the `env` native for reading environment variables
+pub fn load_env<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let env = NativeBuilder::new("env")
+        .arg::<String>()
+        .build(|name| match env::var(&name) {
+            Ok(value) => Ok(Value::String(value.into())),
+            Err(VarError::NotPresent) => Ok(Value::None),
+            Err(error @ VarError::NotUnicode(_)) => {
+                let message = format!("failed to read environment variable '{name}'");
+                Err(NativeError::new(message)
+                    .with_kind("env")
+                    .with_source(error)
+                    .with_arg(0))
             }
-            Ok(Value::None)
-        }))),
-    );
+        })
+        .requires(Capability::Env);
+
+    engine.init_const("env", Value::Func(FuncValue::native(env)));
+}
+
+/// Loads natives for reading and writing whole files
+pub fn load_filesystem<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let read_file = NativeBuilder::new("read_file")
+        .arg::<String>()
+        .build(|path| {
+            fs::read_to_string(&path)
+                .map_err(|error| io_error(format!("failed to read '{path}'"), error).with_arg(0))
+        });
+
+    let write_file = NativeBuilder::new("write_file")
+        .arg::<String>()
+        .arg::<String>()
+        .build(|path, text| {
+            fs::write(&path, text)
+                .map_err(|error| io_error(format!("failed to write '{path}'"), error).with_arg(0))
+        });
+
+    for (ident, native) in [("read_file", read_file), ("write_file", write_file)] {
+        let native = native.requires(Capability::Filesystem);
+        engine.init_const(ident, Value::Func(FuncValue::native(native)));
+    }
 }
@@ -0,0 +1,32 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// A shared flag that stops an engine from another thread
+///
+/// Clones share the same flag. The engine checks it before every statement
+/// and expression, failing with [`RunError::Cancelled`](super::error::RunError::Cancelled)
+/// once it is set. The flag stays set until [`reset`](CancelToken::reset) is called.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests that every engine using this token stops evaluating
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears the flag so engines using this token can evaluate again
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt::Display;
+
+/// Access to something outside the engine that a native function needs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Printing to the output and reading input
+    Io,
+    /// Reading the clock and sleeping
+    Time,
+    /// Generating random numbers
+    Random,
+    /// Reading environment variables
+    Env,
+    /// Reading and writing files
+    Filesystem,
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Capability::Io => write!(f, "io"),
+            Capability::Time => write!(f, "time"),
+            Capability::Random => write!(f, "random"),
+            Capability::Env => write!(f, "env"),
+            Capability::Filesystem => write!(f, "filesystem"),
+        }
+    }
+}
+
+/// The capabilities an engine grants to the scripts it runs
+///
+/// Builtins that need a capability are only registered when it is granted,
+/// and natives that [require](super::NativeFunc::requires) a capability fail
+/// when called from an engine that does not grant it.
+///
+/// The default grants io, time and random, but not env or filesystem access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EngineCapabilities {
+    pub io: bool,
+    pub time: bool,
+    pub random: bool,
+    pub env: bool,
+    pub filesystem: bool,
+}
+
+impl Default for EngineCapabilities {
+    fn default() -> Self {
+        Self {
+            io: true,
+            time: true,
+            random: true,
+            env: false,
+            filesystem: false,
+        }
+    }
+}
+
+impl EngineCapabilities {
+    /// Grants every capability
+    pub fn all() -> Self {
+        Self {
+            io: true,
+            time: true,
+            random: true,
+            env: true,
+            filesystem: true,
+        }
+    }
+
+    /// Grants no capabilities, for running untrusted scripts
+    pub fn none() -> Self {
+        Self {
+            io: false,
+            time: false,
+            random: false,
+            env: false,
+            filesystem: false,
+        }
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Io => self.io,
+            Capability::Time => self.time,
+            Capability::Random => self.random,
+            Capability::Env => self.env,
+            Capability::Filesystem => self.filesystem,
+        }
+    }
+
+    pub fn with(mut self, capability: Capability) -> Self {
+        *self.flag(capability) = true;
+        self
+    }
+
+    pub fn without(mut self, capability: Capability) -> Self {
+        *self.flag(capability) = false;
+        self
+    }
+
+    fn flag(&mut self, capability: Capability) -> &mut bool {
+        match capability {
+            Capability::Io => &mut self.io,
+            Capability::Time => &mut self.time,
+            Capability::Random => &mut self.random,
+            Capability::Env => &mut self.env,
+            Capability::Filesystem => &mut self.filesystem,
+        }
+    }
+}
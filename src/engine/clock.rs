@@ -0,0 +1,132 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::CancelToken;
+
+/// Longest uninterrupted sleep, bounding how late a cancellation is noticed
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+/// The source of time for the `time`, `clock` and `sleep` natives
+///
+/// Engines use the [`SystemClock`] by default. Tests and simulations can
+/// swap in a [`VirtualClock`], so scripts see time only move when the host says so.
+pub trait Clock: Send + Sync {
+    /// Returns the time elapsed since the unix epoch, or `None` if the clock is set before it
+    fn unix_time(&self) -> Option<Duration>;
+
+    /// Returns a monotonic reading, measured from an arbitrary fixed point
+    fn monotonic(&self) -> Duration;
+
+    /// Waits for `duration` to pass, returning early once `cancel` is set
+    ///
+    /// Clocks that block the thread should also wake by the engine's wall clock `deadline`,
+    /// so the timeout is reported on time.
+    fn sleep(&self, duration: Duration, deadline: Option<Instant>, cancel: &CancelToken);
+}
+
+/// Reads the operating system clocks and blocks the thread to sleep
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clock for SystemClock {
+    fn unix_time(&self) -> Option<Duration> {
+        SystemTime::now().duration_since(UNIX_EPOCH).ok()
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration, deadline: Option<Instant>, cancel: &CancelToken) {
+        let mut end = Instant::now() + duration;
+        if let Some(deadline) = deadline {
+            end = end.min(deadline);
+        }
+
+        // sleep in short slices so a cancelled engine wakes up promptly
+        while !cancel.is_cancelled() {
+            let remaining = end.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(SLEEP_SLICE));
+        }
+    }
+}
+
+/// A clock that only moves when it is advanced
+///
+/// Clones share the same time, so the host can keep a clone to advance an engine's clock
+/// from outside. Sleeping advances the clock by the requested duration and returns
+/// immediately without regard for the deadline, so scripts that wait run as fast as possible.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock {
+    epoch: Duration,
+    elapsed: Arc<AtomicU64>,
+}
+
+impl VirtualClock {
+    /// Creates a clock frozen at the unix epoch
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a clock frozen at `epoch` past the unix epoch
+    pub fn starting_at(epoch: Duration) -> Self {
+        Self {
+            epoch,
+            elapsed: Default::default(),
+        }
+    }
+
+    /// Returns the time the clock has been advanced by since it was created
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed.load(Ordering::Relaxed))
+    }
+
+    /// Moves the clock forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let _ = self
+            .elapsed
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |elapsed| {
+                Some(elapsed.saturating_add(nanos))
+            });
+    }
+}
+
+impl Clock for VirtualClock {
+    fn unix_time(&self) -> Option<Duration> {
+        Some(self.epoch + self.elapsed())
+    }
+
+    fn monotonic(&self) -> Duration {
+        self.elapsed()
+    }
+
+    fn sleep(&self, duration: Duration, _deadline: Option<Instant>, _cancel: &CancelToken) {
+        self.advance(duration);
+    }
+}
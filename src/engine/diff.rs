@@ -0,0 +1,155 @@
+use indexmap::IndexMap;
+
+use super::{Engine, Value};
+
+/// A single difference between two values
+#[derive(Debug, Clone)]
+pub enum Change<Data> {
+    Added {
+        path: String,
+        value: Value<Data>,
+    },
+    Removed {
+        path: String,
+        value: Value<Data>,
+    },
+    Changed {
+        path: String,
+        old: Value<Data>,
+        new: Value<Data>,
+    },
+}
+
+impl<Data> Change<Data> {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+/// Every change needed to turn one value or [`Snapshot`] into another
+#[derive(Debug, Clone)]
+pub struct Diff<Data> {
+    changes: Vec<Change<Data>>,
+}
+
+impl<Data> Diff<Data> {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn changes(&self) -> &[Change<Data>] {
+        &self.changes
+    }
+
+    pub fn into_changes(self) -> Vec<Change<Data>> {
+        self.changes
+    }
+}
+
+/// Returns true if both values are the same type and hold the same value
+///
+/// Unlike the `==` operator, ints and floats are never equal to each other,
+/// and functions and userdata are compared by identity.
+pub fn same_value<Data>(a: &Value<Data>, b: &Value<Data>) -> bool {
+    match (a, b) {
+        (Value::None, Value::None) => true,
+        (Value::Bool(v1), Value::Bool(v2)) => v1 == v2,
+        (Value::Int(v1), Value::Int(v2)) => v1 == v2,
+        (Value::Float(v1), Value::Float(v2)) => v1 == v2,
+        (Value::String(v1), Value::String(v2)) => v1 == v2,
+        (Value::Func(v1), Value::Func(v2)) => v1.ptr_eq(v2),
+        (Value::Custom(v1), Value::Custom(v2)) => v1.ptr_eq(v2),
+        (Value::Weak(v1), Value::Weak(v2)) => v1.ptr_eq(v2),
+        _ => false,
+    }
+}
+
+/// Diffs two values, reporting a change at the empty path if they differ
+pub fn value_diff<Data: Clone>(a: &Value<Data>, b: &Value<Data>) -> Diff<Data> {
+    let mut changes = Vec::new();
+    push_value_diff(&mut changes, String::new(), a, b);
+    Diff { changes }
+}
+
+fn push_value_diff<Data: Clone>(
+    changes: &mut Vec<Change<Data>>,
+    path: String,
+    a: &Value<Data>,
+    b: &Value<Data>,
+) {
+    if !same_value(a, b) {
+        changes.push(Change::Changed {
+            path,
+            old: a.clone(),
+            new: b.clone(),
+        });
+    }
+}
+
+/// The visible globals and locals of an [`Engine`] at a point in time
+///
+/// Paths in a [`Diff`] between snapshots are prefixed with `globals.` or `locals.`
+#[derive(Debug, Clone)]
+pub struct Snapshot<Data> {
+    globals: IndexMap<String, Value<Data>>,
+    locals: IndexMap<String, Value<Data>>,
+}
+
+impl<Data: Clone> Snapshot<Data> {
+    pub fn capture(engine: &Engine<Data>) -> Self {
+        let capture = |(ident, value): (&str, &Value<Data>)| (ident.to_string(), value.clone());
+        Self {
+            globals: engine.globals().map(capture).collect(),
+            locals: engine.locals().map(capture).collect(),
+        }
+    }
+
+    pub fn globals(&self) -> &IndexMap<String, Value<Data>> {
+        &self.globals
+    }
+
+    pub fn locals(&self) -> &IndexMap<String, Value<Data>> {
+        &self.locals
+    }
+
+    /// Returns the changes needed to turn this snapshot into `other`
+    ///
+    /// Changes are ordered by scope, then by the order identifiers were defined.
+    pub fn diff(&self, other: &Self) -> Diff<Data> {
+        let mut changes = Vec::new();
+        diff_maps(&mut changes, "globals", &self.globals, &other.globals);
+        diff_maps(&mut changes, "locals", &self.locals, &other.locals);
+        Diff { changes }
+    }
+}
+
+fn diff_maps<Data: Clone>(
+    changes: &mut Vec<Change<Data>>,
+    prefix: &str,
+    old: &IndexMap<String, Value<Data>>,
+    new: &IndexMap<String, Value<Data>>,
+) {
+    for (ident, value) in old {
+        let path = format!("{prefix}.{ident}");
+        match new.get(ident) {
+            Some(new_value) => push_value_diff(changes, path, value, new_value),
+            None => changes.push(Change::Removed {
+                path,
+                value: value.clone(),
+            }),
+        }
+    }
+
+    for (ident, value) in new {
+        if !old.contains_key(ident) {
+            changes.push(Change::Added {
+                path: format!("{prefix}.{ident}"),
+                value: value.clone(),
+            });
+        }
+    }
+}
@@ -1,8 +1,58 @@
-use std::{mem::replace, ops::Deref};
+use std::{
+    any::{type_name, Any, TypeId},
+    env,
+    fmt::{self, Display},
+    io::{self, stdin, stdout, BufRead, Write},
+    mem::{replace, take},
+    ops::Deref,
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::parser::ast::{init::InitStyle, Expr, Node, Statement};
+use hashbrown::HashSet;
+use indexmap::{IndexMap, IndexSet};
 
-use super::{error::RunError, load_builtins, scope::Scope, value::ValueType, OpManager, Value};
+use crate::{
+    cache::CacheSpan,
+    diagnostic::Warning,
+    error::panic_message,
+    parser::{
+        ast::{
+            init::InitStyle, CustomStatement, Expr, Func, Node, Program, SharedProgram, Statement,
+            With,
+        },
+        Lexer,
+    },
+    symbol::{AsSymbol, Symbol},
+    BobaCache, BobaError,
+};
+
+use super::{
+    builtin::load_prelude,
+    error::{NativeError, RunError},
+    load_builtins,
+    migrate::{Migration, MigrationError},
+    ops::{BinaryOp, CoercionPolicy, UnaryOp},
+    random::Rng,
+    scope::{Scope, ScopeDepth},
+    value::ValueType,
+    CancelToken, Clock, EngineCapabilities, EngineHooks, FloatFormat, FuncValue, OpManager,
+    PauseToken, Profiler, Snapshot, SystemClock, TraceEvent, TraceOp, TransferHook, UserData,
+    Value, WeakUserData,
+};
+
+fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Some(line))
+}
 
 enum GlobalValue<Data> {
     Static(Value<Data>),
@@ -22,13 +72,212 @@ pub enum SetError {
     Const,
 }
 
-pub struct Engine<Data: Clone> {
+/// Evaluates a [`CustomStatement`] parsed by a host registered keyword
+pub type CustomHandler<Data> = Arc<
+    dyn Fn(&mut Engine<Data>, &Node<Data, CustomStatement>) -> Result<Value<Data>, RunError<Data>>
+        + Send
+        + Sync,
+>;
+
+/// Converts a host literal into a [`Value`]
+///
+/// Prefix hooks receive the string following the prefix,
+/// and suffix hooks receive the number preceding the suffix.
+pub type LiteralHook<Data> =
+    Arc<dyn Fn(Value<Data>) -> Result<Value<Data>, NativeError> + Send + Sync>;
+
+/// Receives non-fatal warnings emitted while evaluating
+pub type WarningSink<Data> = Box<dyn FnMut(Warning<Data>) + Send + Sync>;
+
+/// Receives each line printed by scripts, without the trailing newline
+pub type PrintSink = Box<dyn FnMut(&str) -> io::Result<()> + Send + Sync>;
+
+/// Provides a line of input for a prompt, or `None` once the input has ended
+pub type InputSource = Box<dyn FnMut(&str) -> io::Result<Option<String>> + Send + Sync>;
+
+/// Receives a [`TraceEvent`] for each value produced while evaluating
+pub type TraceSink<Data> = Box<dyn FnMut(TraceEvent<Data>) + Send + Sync>;
+
+/// Inspects each statement before it runs, receiving its data and the current call depth
+pub type StatementHook<Data> = Box<dyn FnMut(&Data, usize) -> StepDecision + Send + Sync>;
+
+/// Number of nested calls allowed by default, low enough to fit in the main thread stack
+pub const DEFAULT_RECURSION_LIMIT: usize = 256;
+
+/// Significant digits floats are rounded to in [lockstep](Engine::set_lockstep) mode,
+/// matching the precision of builtins like `sqrt`
+pub const LOCKSTEP_PRECISION: usize = 32;
+
+/// How long a paused engine waits before calling the [`StatementHook`] again
+const PAUSE_POLL: Duration = Duration::from_millis(10);
+
+/// What the engine does with a statement after the [`StatementHook`] has seen it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepDecision {
+    /// Runs the statement
+    Continue,
+    /// Waits briefly, then calls the hook again for the same statement
+    ///
+    /// Lets a UI hold execution on the current line until the user steps,
+    /// without blocking inside the hook. The deadline still applies while paused.
+    Pause,
+    /// Skips the statement, which then evaluates to none
+    Skip,
+}
+
+/// Userdata counts collected by [`Engine::shutdown`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Distinct userdata objects that were held by the engine
+    pub userdata: usize,
+    /// Userdata that was released when the engine dropped its scopes
+    pub released: usize,
+    /// Userdata that is still alive because it is referenced outside the engine
+    pub live: usize,
+}
+
+/// Limits on how much scripts may print, see [`Engine::set_output_limit`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputLimit {
+    /// Total bytes of printed text, counting the newline that ends each line
+    pub bytes: Option<usize>,
+    pub lines: Option<usize>,
+}
+
+impl Display for OutputLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.bytes, self.lines) {
+            (Some(bytes), Some(lines)) => write!(f, "{bytes} bytes and {lines} lines"),
+            (Some(bytes), None) => write!(f, "{bytes} bytes"),
+            (None, Some(lines)) => write!(f, "{lines} lines"),
+            (None, None) => write!(f, "unlimited output"),
+        }
+    }
+}
+
+/// Output printed by scripts since the output limit was last set
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputUsage {
+    pub bytes: usize,
+    pub lines: usize,
+}
+
+/// Work done by scripts since the engine was created or [`Engine::reset_metrics`] was called
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EngineMetrics {
+    /// Statements run, including those in function bodies but not skipped ones
+    pub statements: u64,
+    /// Function calls made, including natives and tail calls
+    pub calls: u64,
+    /// Deepest the call stack has been
+    pub peak_depth: usize,
+    /// Bytes of the strings built by operators and returned by natives
+    ///
+    /// Literals and strings passed along unchanged are not counted again.
+    pub string_bytes: u64,
+}
+
+/// A single function call that is currently being evaluated
+#[derive(Debug, Clone)]
+pub struct CallFrame<Data> {
+    pub ident: Symbol,
+    pub data: Data,
+}
+
+/// A pending step of [`Engine::eval`]
+///
+/// Operands are scheduled as [`Task::Eval`] ahead of the task that consumes them,
+/// which then pops their results from the value stack.
+enum Task<'a, Data> {
+    Eval(&'a Node<Data, Expr<Data>>),
+    /// Reports the value of an expression once it has been evaluated
+    Trace(&'a Node<Data, Expr<Data>>),
+    Suffix(&'a Node<Data, String>, &'a Data),
+    Unary(UnaryOp, &'a Data),
+    Binary(BinaryOp, &'a Data),
+    Branch(
+        &'a Node<Data, Expr<Data>>,
+        &'a Node<Data, Expr<Data>>,
+        &'a Node<Data, Expr<Data>>,
+    ),
+    Assign(&'a Node<Data, Symbol>, &'a Data, bool),
+    Call(
+        &'a Node<Data, Symbol>,
+        &'a [Node<Data, Expr<Data>>],
+        &'a Data,
+        FuncValue<Data>,
+    ),
+}
+
+/// How a function body finished, see [`Engine::call_custom`]
+enum Tail<Data> {
+    Return(Value<Data>),
+    /// A script function called as the last step of the body, with its evaluated arguments
+    Call(Symbol, Arc<Func<Data>>, Vec<Value<Data>>, Data),
+}
+
+/// How deep the engine's stacks were before a call, see [`Engine::catch_panic`]
+struct Checkpoint {
+    stack: usize,
+    locals: ScopeDepth,
+    globals: ScopeDepth,
+    profiler: usize,
+}
+
+/// Evaluates programs and holds their global and local state
+///
+/// Every collection the engine keeps is insertion ordered, so iterating over
+/// globals, locals or registered hooks gives the same order on every run and
+/// platform. Hosts can rely on this for reproducible output and replays.
+pub struct Engine<Data> {
     ops: OpManager<Data>,
     globals: Scope<GlobalValue<Data>>,
     locals: Scope<Value<Data>>,
+    stack: Vec<CallFrame<Data>>,
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    lockstep: bool,
+    sleep: bool,
+    clock: Arc<dyn Clock>,
+    started: Duration,
+    frozen: IndexSet<Symbol>,
+    float_format: FloatFormat,
+    color: bool,
+    custom: IndexMap<String, CustomHandler<Data>>,
+    prefixes: IndexMap<String, LiteralHook<Data>>,
+    suffixes: IndexMap<String, LiteralHook<Data>>,
+    transfer: IndexMap<TypeId, TransferHook>,
+    warnings: Option<WarningSink<Data>>,
+    /// Addresses of the comparisons already reported as float equality warnings
+    ///
+    /// Cleared whenever a program or expression starts, since a freed tree
+    /// can hand its addresses to the next one.
+    float_warned: HashSet<usize>,
+    statement_hook: Option<StatementHook<Data>>,
+    hooks: Option<Box<dyn EngineHooks<Data>>>,
+    trace: Option<TraceSink<Data>>,
+    pause: PauseToken,
+    print: Option<PrintSink>,
+    input: Option<InputSource>,
+    #[cfg(feature = "prompt")]
+    secret: Option<InputSource>,
+    rng: Rng,
+    capabilities: EngineCapabilities,
+    cancel: CancelToken,
+    recursion_limit: Option<usize>,
+    output_limit: OutputLimit,
+    output: OutputUsage,
+    output_exceeded: bool,
+    /// Set by [`Engine::interrupt_native`] to replace the error of the native being called
+    interrupt: Option<RunError<Data>>,
+    metrics: EngineMetrics,
+    #[cfg(feature = "flamegraph")]
+    flame: Option<super::FlameRecorder>,
+    profiler: Option<Profiler>,
+    cache: BobaCache,
 }
 
-impl<Data: Clone> Default for Engine<Data> {
+impl<Data: Clone + 'static> Default for Engine<Data> {
     fn default() -> Self {
         let mut engine = Self::empty();
         load_builtins(&mut engine);
@@ -36,19 +285,682 @@ impl<Data: Clone> Default for Engine<Data> {
     }
 }
 
-impl<Data: Clone> Engine<Data> {
+impl<Data: Clone + 'static> Engine<Data> {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Creates an engine with only the builtins allowed by `capabilities` loaded
+    pub fn with_capabilities(capabilities: EngineCapabilities) -> Self {
+        let mut engine = Self::empty();
+        engine.capabilities = capabilities;
+        load_builtins(&mut engine);
+        engine
+    }
+}
+
+impl<Data: Clone> Engine<Data> {
     pub fn empty() -> Self {
         Self {
             ops: Default::default(),
             globals: Default::default(),
             locals: Default::default(),
+            stack: Default::default(),
+            fuel: None,
+            deadline: None,
+            lockstep: false,
+            sleep: true,
+            clock: Arc::new(SystemClock::new()),
+            started: Duration::ZERO,
+            frozen: Default::default(),
+            float_format: Default::default(),
+            color: !matches!(env::var_os("NO_COLOR"), Some(value) if !value.is_empty()),
+            custom: Default::default(),
+            prefixes: Default::default(),
+            suffixes: Default::default(),
+            transfer: Default::default(),
+            warnings: None,
+            float_warned: HashSet::new(),
+            statement_hook: None,
+            hooks: None,
+            trace: None,
+            pause: PauseToken::new(),
+            print: None,
+            input: None,
+            #[cfg(feature = "prompt")]
+            secret: None,
+            rng: Rng::default(),
+            capabilities: Default::default(),
+            cancel: CancelToken::new(),
+            recursion_limit: Some(DEFAULT_RECURSION_LIMIT),
+            output_limit: OutputLimit::default(),
+            output: OutputUsage::default(),
+            output_exceeded: false,
+            interrupt: None,
+            metrics: EngineMetrics::default(),
+            #[cfg(feature = "flamegraph")]
+            flame: None,
+            profiler: None,
+            cache: BobaCache::new(),
+        }
+    }
+
+    /// Returns the cache holding source passed to [`eval_str`](Engine::eval_str)
+    pub fn cache(&self) -> &BobaCache {
+        &self.cache
+    }
+
+    pub fn cache_mut(&mut self) -> &mut BobaCache {
+        &mut self.cache
+    }
+
+    /// Returns the operators used when evaluating expressions
+    pub fn ops(&self) -> &OpManager<Data> {
+        &self.ops
+    }
+
+    /// Applies a unary operator to a plain value, exactly as a script would
+    pub fn eval_unary(
+        &self,
+        op: UnaryOp,
+        value: Value<Data>,
+        data: &Data,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        self.ops.unary(op, value, data)
+    }
+
+    /// Applies a binary operator to plain values, exactly as a script would
+    pub fn eval_binary(
+        &self,
+        op: BinaryOp,
+        lhs: Value<Data>,
+        rhs: Value<Data>,
+        data: &Data,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        self.ops.binary(op, lhs, rhs, data)
+    }
+
+    /// Sets the policy deciding which implicit conversions operators may perform
+    ///
+    /// Hosts can use this to forbid conversions their users do not expect, like
+    /// appending numbers to strings, without replacing the operators themselves.
+    pub fn set_coercion_policy(&mut self, policy: impl CoercionPolicy + 'static) {
+        self.ops.set_policy(Some(Arc::new(policy)));
+    }
+
+    /// Removes the coercion policy, allowing every implicit conversion again
+    pub fn clear_coercion_policy(&mut self) {
+        self.ops.set_policy(None);
+    }
+
+    /// Rounds every float that operators and natives produce to `digits` significant digits
+    ///
+    /// Without a fixed precision, results keep the precision of their operands,
+    /// so the same calculation can differ depending on how its literals were written.
+    /// `None` or zero digits go back to that behavior.
+    pub fn set_float_precision(&mut self, digits: Option<usize>) {
+        self.ops.set_float_precision(digits);
+    }
+
+    pub fn float_precision(&self) -> Option<usize> {
+        self.ops.float_precision()
+    }
+
+    /// Sets the handler used to evaluate custom statements starting with `keyword`
+    pub fn set_custom_handler(
+        &mut self,
+        keyword: impl Into<String>,
+        handler: impl Fn(
+                &mut Engine<Data>,
+                &Node<Data, CustomStatement>,
+            ) -> Result<Value<Data>, RunError<Data>>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        self.custom.insert(keyword.into(), Arc::new(handler));
+    }
+
+    /// Sets the hook that re-wraps `T` user data moved into this engine by [`Value::transfer_to`]
+    ///
+    /// The hook decides what the copy shares with the original, like a fresh connection
+    /// to the same database, or a clone of the object behind its own lock.
+    pub fn set_transfer_hook<T: Any + Send + Sync>(
+        &mut self,
+        hook: impl Fn(&T) -> Result<UserData, NativeError> + Send + Sync + 'static,
+    ) {
+        let hook = move |data: &UserData| match data.downcast_ref::<T>() {
+            Some(value) => hook(value),
+            None => Err(NativeError::new(format!(
+                "expected '{}' user data",
+                type_name::<T>()
+            ))),
+        };
+        self.transfer.insert(TypeId::of::<T>(), Arc::new(hook));
+    }
+
+    pub(crate) fn transfer_hook(&self, data: &UserData) -> Option<&TransferHook> {
+        self.transfer.get(&data.type_id())
+    }
+
+    /// Sets the hook used to evaluate string literals prefixed with `prefix`, like `rgb"ff00ff"`
+    pub fn set_literal_prefix(
+        &mut self,
+        prefix: impl Into<String>,
+        hook: impl Fn(Value<Data>) -> Result<Value<Data>, NativeError> + Send + Sync + 'static,
+    ) {
+        self.prefixes.insert(prefix.into(), Arc::new(hook));
+    }
+
+    /// Sets the hook used to evaluate number literals suffixed with `suffix`, like `10px`
+    pub fn set_literal_suffix(
+        &mut self,
+        suffix: impl Into<String>,
+        hook: impl Fn(Value<Data>) -> Result<Value<Data>, NativeError> + Send + Sync + 'static,
+    ) {
+        self.suffixes.insert(suffix.into(), Arc::new(hook));
+    }
+
+    /// Sets the sink that receives warnings emitted during evaluation
+    ///
+    /// Warnings are discarded when no sink is set. Each float comparison is only reported
+    /// the first time it runs in a program or expression, even inside a function called
+    /// many times.
+    pub fn set_warning_sink(&mut self, sink: impl FnMut(Warning<Data>) + Send + Sync + 'static) {
+        self.warnings = Some(Box::new(sink));
+        self.float_warned.clear();
+    }
+
+    /// Sets the hook called before each statement is evaluated, including statements in functions
+    ///
+    /// Editors and teaching tools can use it to highlight the executing line and step through a program.
+    pub fn set_statement_hook(
+        &mut self,
+        hook: impl FnMut(&Data, usize) -> StepDecision + Send + Sync + 'static,
+    ) {
+        self.statement_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_statement_hook(&mut self) {
+        self.statement_hook = None;
+    }
+
+    /// Sets the hooks called as statements run, functions are called and variables change
+    ///
+    /// Together with the [`PauseToken`], this is enough for a host to build a breakpoint debugger.
+    /// The hooks see each statement after the [statement hook](Engine::set_statement_hook).
+    pub fn set_hooks(&mut self, hooks: impl EngineHooks<Data> + 'static) {
+        self.hooks = Some(Box::new(hooks));
+    }
+
+    /// Removes the hooks, returning them so the host can read back their state
+    pub fn take_hooks(&mut self) -> Option<Box<dyn EngineHooks<Data>>> {
+        self.hooks.take()
+    }
+
+    /// Returns the token that pauses this engine, which can be cloned and sent to other threads
+    pub fn pause_token(&self) -> &PauseToken {
+        &self.pause
+    }
+
+    pub fn set_pause_token(&mut self, token: PauseToken) {
+        self.pause = token;
+    }
+
+    /// Enables tracing, sending an event to `sink` for every statement and expression evaluated
+    ///
+    /// Tracing is meant for finding out why a script computes the wrong value, and slows
+    /// evaluation down considerably. Calls in tail position are not reported on their own,
+    /// since they replace the calling function, but everything they evaluate is.
+    pub fn set_trace_sink(&mut self, sink: impl FnMut(TraceEvent<Data>) + Send + Sync + 'static) {
+        self.trace = Some(Box::new(sink));
+    }
+
+    pub fn clear_trace_sink(&mut self) {
+        self.trace = None;
+    }
+
+    fn trace(&mut self, op: TraceOp, value: &Value<Data>, data: &Data) {
+        let depth = self.stack.len();
+        if let Some(sink) = &mut self.trace {
+            sink(TraceEvent {
+                op,
+                value: value.clone(),
+                data: data.clone(),
+                depth,
+            });
         }
     }
 
+    /// Calls `f` with the hooks and a shared borrow of the engine, if hooks are set
+    fn with_hooks<T>(
+        &mut self,
+        f: impl FnOnce(&mut dyn EngineHooks<Data>, &Self) -> T,
+    ) -> Option<T> {
+        let mut hooks = self.hooks.take()?;
+        let output = f(hooks.as_mut(), self);
+        self.hooks = Some(hooks);
+        Some(output)
+    }
+
+    /// Asks the statement hook what to do with the statement at `data`, waiting while it pauses
+    fn step(&mut self, data: &Data) -> Result<StepDecision, RunError<Data>> {
+        let depth = self.stack.len();
+        loop {
+            let decision = match &mut self.statement_hook {
+                _ if self.pause.is_paused() => StepDecision::Pause,
+                Some(hook) => hook(data, depth),
+                None => StepDecision::Continue,
+            };
+
+            let decision = match decision {
+                StepDecision::Continue => self
+                    .with_hooks(|hooks, engine| hooks.before_statement(engine, data))
+                    .unwrap_or(StepDecision::Continue),
+                decision => decision,
+            };
+
+            if decision != StepDecision::Pause {
+                if decision == StepDecision::Continue {
+                    self.metrics.statements += 1;
+                }
+                return Ok(decision);
+            }
+
+            self.check_interrupts(data)?;
+            thread::sleep(PAUSE_POLL);
+        }
+    }
+
+    fn warn(&mut self, warning: Warning<Data>) {
+        if let Some(sink) = &mut self.warnings {
+            sink(warning);
+        }
+    }
+
+    /// Sets the sink that receives lines printed by scripts
+    ///
+    /// Lines are written to stdout when no sink is set.
+    pub fn set_print_sink(
+        &mut self,
+        sink: impl FnMut(&str) -> io::Result<()> + Send + Sync + 'static,
+    ) {
+        self.print = Some(Box::new(sink));
+    }
+
+    /// Writes lines printed by scripts to `writer` instead of stdout
+    ///
+    /// The writer is dropped along with the engine, so buffered writers are flushed then.
+    pub fn set_print_writer(&mut self, mut writer: impl Write + Send + Sync + 'static) {
+        self.set_print_sink(move |line| writeln!(writer, "{line}"));
+    }
+
+    /// Prints a single line using the print sink
+    ///
+    /// Fails without printing anything if the line would exceed the output limit.
+    pub fn print(&mut self, line: &str) -> io::Result<()> {
+        let usage = OutputUsage {
+            bytes: self.output.bytes.saturating_add(line.len() + 1),
+            lines: self.output.lines.saturating_add(1),
+        };
+
+        let limit = self.output_limit;
+        if limit.bytes.is_some_and(|bytes| usage.bytes > bytes)
+            || limit.lines.is_some_and(|lines| usage.lines > lines)
+        {
+            self.output_exceeded = true;
+            let message = format!("output is limited to {limit}");
+            return Err(io::Error::other(message));
+        }
+
+        self.output = usage;
+        match &mut self.print {
+            Some(sink) => sink(line),
+            None => writeln!(stdout().lock(), "{line}"),
+        }
+    }
+
+    /// Sets the source that provides lines read by scripts
+    ///
+    /// When no source is set, prompts are written to stdout and lines are read from stdin.
+    pub fn set_input_source(
+        &mut self,
+        source: impl FnMut(&str) -> io::Result<Option<String>> + Send + Sync + 'static,
+    ) {
+        self.input = Some(Box::new(source));
+    }
+
+    /// Reads lines from `reader` instead of stdin, ignoring prompts
+    pub fn set_input_reader(&mut self, mut reader: impl BufRead + Send + Sync + 'static) {
+        self.set_input_source(move |_| read_line(&mut reader));
+    }
+
+    /// Reads a single line using the input source, without the trailing newline
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        if let Some(source) = &mut self.input {
+            return source(prompt);
+        }
+
+        let mut stdout = stdout().lock();
+        write!(stdout, "{prompt}")?;
+        stdout.flush()?;
+        read_line(&mut stdin().lock())
+    }
+
+    /// Sets the source that provides secrets like passwords, which should not be echoed
+    ///
+    /// Without a secret source, secrets are read from the input source if one is set.
+    /// Otherwise they are read from the terminal with echo turned off.
+    #[cfg(feature = "prompt")]
+    pub fn set_secret_source(
+        &mut self,
+        source: impl FnMut(&str) -> io::Result<Option<String>> + Send + Sync + 'static,
+    ) {
+        self.secret = Some(Box::new(source));
+    }
+
+    /// Reads a single line that should not be shown, see [`set_secret_source`](Engine::set_secret_source)
+    #[cfg(feature = "prompt")]
+    pub fn read_secret(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        use std::io::IsTerminal;
+
+        match (&mut self.secret, &mut self.input) {
+            (Some(source), _) | (None, Some(source)) => source(prompt),
+            (None, None) if stdin().is_terminal() => super::prompt::read_hidden(prompt),
+            (None, None) => self.read_line(prompt),
+        }
+    }
+
+    /// Returns the default format used when displaying float values
+    pub fn float_format(&self) -> &FloatFormat {
+        &self.float_format
+    }
+
+    pub fn set_float_format(&mut self, format: FloatFormat) {
+        self.float_format = format;
+    }
+
+    pub fn is_color_enabled(&self) -> bool {
+        self.color
+    }
+
+    /// Controls whether the style natives wrap text in terminal escape codes
+    ///
+    /// Color is enabled by default, unless the `NO_COLOR` environment variable
+    /// was set to a non-empty value when the engine was created.
+    pub fn set_color_enabled(&mut self, enabled: bool) {
+        self.color = enabled;
+    }
+
+    /// Prevents scripts from redefining, shadowing, or assigning to `ident`
+    ///
+    /// Attempts to do so fail with [`RunError::Frozen`].
+    /// The host may still replace the value using the engine API.
+    pub fn freeze(&mut self, ident: impl AsSymbol) {
+        self.frozen.insert(ident.to_symbol());
+    }
+
+    /// Initializes a constant function and freezes it so scripts cannot override it
+    pub fn freeze_function(&mut self, ident: impl AsSymbol, func: FuncValue<Data>) {
+        let ident = ident.to_symbol();
+        self.init_const(&ident, Value::Func(func));
+        self.freeze(ident);
+    }
+
+    pub fn unfreeze(&mut self, ident: impl AsSymbol) -> bool {
+        (ident.find_symbol()).is_some_and(|ident| self.frozen.shift_remove(&ident))
+    }
+
+    pub fn is_frozen(&self, ident: impl AsSymbol) -> bool {
+        (ident.find_symbol()).is_some_and(|ident| self.frozen.contains(&ident))
+    }
+
+    fn check_frozen(&self, ident: &Node<Data, Symbol>) -> Result<(), RunError<Data>> {
+        match self.is_frozen(ident.deref()) {
+            false => Ok(()),
+            true => Err(RunError::Frozen {
+                ident: ident.to_string(),
+                data: ident.data().clone(),
+            }),
+        }
+    }
+
+    /// Returns the remaining fuel, or `None` if evaluation is unlimited
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Sets the amount of fuel available for evaluation
+    ///
+    /// Every evaluated statement and expression consumes one unit of fuel.
+    /// Once it runs out, evaluation fails with [`RunError::OutOfFuel`].
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Sets a point in time after which evaluation fails with [`RunError::Timeout`]
+    ///
+    /// The deadline is ignored while [lockstep](Engine::set_lockstep) is enabled.
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn recursion_limit(&self) -> Option<usize> {
+        self.recursion_limit
+    }
+
+    /// Sets how many calls may be nested before evaluation fails with [`RunError::RecursionLimit`]
+    ///
+    /// Each nested call uses native stack, so raising the limit or removing it
+    /// can crash the process with a stack overflow unless the engine runs on a thread with a larger stack.
+    pub fn set_recursion_limit(&mut self, limit: Option<usize>) {
+        self.recursion_limit = limit;
+    }
+
+    /// Returns the limits on output printed by scripts
+    pub fn output_limit(&self) -> OutputLimit {
+        self.output_limit
+    }
+
+    /// Limits how much scripts may print, and resets the output counted so far
+    ///
+    /// Like fuel, output is counted across runs until the limit is set again, so hosts
+    /// that want a budget per run set it before each one. A print beyond the limit
+    /// fails with [`RunError::OutputLimit`] and writes nothing.
+    pub fn set_output_limit(&mut self, limit: OutputLimit) {
+        self.output_limit = limit;
+        self.output = OutputUsage::default();
+        self.output_exceeded = false;
+    }
+
+    /// Returns the output printed since the output limit was last set
+    pub fn output_usage(&self) -> OutputUsage {
+        self.output
+    }
+
+    pub fn metrics(&self) -> EngineMetrics {
+        self.metrics
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics = EngineMetrics::default();
+    }
+
+    /// Counts the bytes of a string the script just built
+    fn count_string(&mut self, value: &Value<Data>) {
+        if let Value::String(v) = value {
+            self.metrics.string_bytes += v.len() as u64;
+        }
+    }
+
+    /// Returns the token that cancels this engine, which can be cloned and sent to other threads
+    pub fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    /// Replaces the token that cancels this engine, so one token can stop several engines
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel = token;
+    }
+
+    /// Returns true if the host has cancelled evaluation
+    ///
+    /// Natives that block or run for a long time should check this and return early.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub fn is_lockstep(&self) -> bool {
+        self.lockstep
+    }
+
+    /// Enables lockstep mode, where evaluation only depends on the script and its inputs
+    ///
+    /// Numbers and builtins already use software arithmetic, so they give identical results
+    /// on every platform. Lockstep additionally ignores the wall clock deadline, so peers
+    /// running the same script can never disagree about whether it timed out.
+    /// Use [fuel](Engine::set_fuel) to limit evaluation instead.
+    ///
+    /// Enabling lockstep seeds the random number generator with zero,
+    /// so it no longer depends on the clock until it is seeded again,
+    /// and the `time` and `clock` natives fail instead of reading the clock.
+    /// It also fixes the [float precision](Engine::set_float_precision) to
+    /// [`LOCKSTEP_PRECISION`] digits unless the host already fixed one.
+    pub fn set_lockstep(&mut self, lockstep: bool) {
+        if lockstep && !self.lockstep {
+            self.rng.seed(0);
+            if self.float_precision().is_none() {
+                self.set_float_precision(Some(LOCKSTEP_PRECISION));
+            }
+        }
+        self.lockstep = lockstep;
+    }
+
+    /// Returns the time elapsed since the engine was created, as measured by its [`Clock`]
+    ///
+    /// Uptime restarts from zero when the clock is replaced.
+    pub fn uptime(&self) -> Duration {
+        self.clock.monotonic().saturating_sub(self.started)
+    }
+
+    /// Returns the clock read by the `time`, `clock` and `sleep` natives
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Replaces the clock read by the `time`, `clock` and `sleep` natives
+    ///
+    /// Pass a [`VirtualClock`](super::VirtualClock) to control time from tests and simulations.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.started = clock.monotonic();
+        self.clock = Arc::new(clock);
+    }
+
+    pub fn is_sleep_allowed(&self) -> bool {
+        self.sleep
+    }
+
+    /// Controls whether the `sleep` native may block the thread, which is allowed by default
+    ///
+    /// Sandboxed hosts can disable it, so calls to `sleep` fail instead.
+    pub fn set_sleep_allowed(&mut self, allowed: bool) {
+        self.sleep = allowed;
+    }
+
+    pub fn capabilities(&self) -> &EngineCapabilities {
+        &self.capabilities
+    }
+
+    /// Sets the capabilities granted to natives that [require](super::NativeFunc::requires) one
+    ///
+    /// Builtins that were already registered stay defined, but fail when called without their capability.
+    pub fn set_capabilities(&mut self, capabilities: EngineCapabilities) {
+        self.capabilities = capabilities;
+    }
+
+    /// Returns the random number generator used by the random natives
+    ///
+    /// It is seeded from the clock until the host or a script sets a seed.
+    pub fn rng_mut(&mut self) -> &mut Rng {
+        &mut self.rng
+    }
+
+    /// Returns a rough estimate of the memory used by all stored values in bytes
+    pub fn memory_usage(&self) -> usize {
+        let locals = self.locals.iter().map(|value| value.size_estimate());
+        let globals = self
+            .globals
+            .iter()
+            .map(|value| value.value().size_estimate());
+        locals.chain(globals).sum()
+    }
+
+    /// Tears down the engine, dropping all values and reporting on the userdata they held
+    ///
+    /// Locals are dropped before globals, so userdata drop callbacks run
+    /// in a deterministic order before this returns, and stdout and
+    /// the print sink are flushed so any output printed by the scripts is written.
+    pub fn shutdown(mut self) -> ShutdownReport {
+        let mut handles: Vec<WeakUserData> = Vec::new();
+        let locals = self.locals.iter();
+        let globals = self.globals.iter().map(|value| value.value());
+        for value in locals.chain(globals) {
+            if let Value::Custom(data) = value {
+                let weak = data.downgrade();
+                if !handles.iter().any(|handle| handle.ptr_eq(&weak)) {
+                    handles.push(weak);
+                }
+            }
+        }
+
+        drop(take(&mut self.locals));
+        drop(take(&mut self.globals));
+        drop(self.print.take());
+        // a failed flush has nowhere left to be reported
+        let _ = stdout().flush();
+
+        let live = handles.iter().filter(|handle| handle.is_alive()).count();
+        ShutdownReport {
+            userdata: handles.len(),
+            released: handles.len() - live,
+            live,
+        }
+    }
+
+    pub fn call_stack(&self) -> &[CallFrame<Data>] {
+        &self.stack
+    }
+
+    /// Starts counting calls and timing each function, replacing any profile in progress
+    pub fn start_profile(&mut self) {
+        self.profiler = Some(Profiler::new());
+    }
+
+    /// Stops profiling and returns the stats gathered since [`start_profile`](Engine::start_profile)
+    pub fn take_profile(&mut self) -> Option<Profiler> {
+        let mut profiler = self.profiler.take()?;
+        profiler.finish();
+        Some(profiler)
+    }
+
+    /// Starts recording the time spent in each call stack, replacing any recording in progress
+    #[cfg(feature = "flamegraph")]
+    pub fn start_flamegraph(&mut self) {
+        self.flame = Some(super::FlameRecorder::new());
+    }
+
+    /// Stops recording and returns the call stacks recorded since [`start_flamegraph`](Engine::start_flamegraph)
+    #[cfg(feature = "flamegraph")]
+    pub fn take_flamegraph(&mut self) -> Option<super::FlameRecorder> {
+        let mut flame = self.flame.take()?;
+        flame.sample(&self.stack);
+        Some(flame)
+    }
+
     pub fn push_scope(&mut self) {
         self.locals.push_scope();
         self.globals.push_scope();
@@ -69,8 +981,67 @@ impl<Data: Clone> Engine<Data> {
         self.globals.pop_scope();
     }
 
-    pub fn get_value(&self, ident: impl AsRef<str>) -> Option<&Value<Data>> {
-        match self.locals.get(ident.as_ref()) {
+    /// Iterates over the visible globals in the order they were first defined
+    pub fn globals(&self) -> impl Iterator<Item = (&str, &Value<Data>)> {
+        (self.globals.entries()).map(|(ident, value)| (ident.as_str(), value.value()))
+    }
+
+    /// Captures the visible globals and locals, so they can be diffed later
+    pub fn snapshot(&self) -> Snapshot<Data> {
+        Snapshot::capture(self)
+    }
+
+    /// Restores the globals saved in `snapshot`, renaming and converting them with `migration`
+    ///
+    /// Only static globals are restored. Functions and consts are defined by the script itself,
+    /// so saved values for them are skipped, and locals are never restored.
+    /// The restore is checked, so if any saved global has no static or const to restore into,
+    /// or a migration fails, nothing is changed. Returns the number of restored globals.
+    pub fn restore(
+        &mut self,
+        snapshot: &Snapshot<Data>,
+        migration: &Migration<Data>,
+    ) -> Result<usize, MigrationError> {
+        let mut updates = Vec::new();
+        let mut unmapped = Vec::new();
+        for (ident, value) in snapshot.globals() {
+            if let Value::Func(_) = value {
+                continue;
+            }
+
+            let Some((target, value)) = migration.apply(ident, value.clone())? else {
+                continue;
+            };
+
+            match self.globals.get(&target) {
+                Some(GlobalValue::Static(_)) => updates.push((target, value)),
+                Some(GlobalValue::Const(_)) => (),
+                None => unmapped.push(ident.clone()),
+            }
+        }
+
+        if !unmapped.is_empty() {
+            return Err(MigrationError::Unmapped(unmapped));
+        }
+
+        let count = updates.len();
+        for (target, value) in updates {
+            if let Some(GlobalValue::Static(old)) = self.globals.get_mut(target) {
+                *old = value;
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Iterates over the visible locals in the order they were first defined
+    pub fn locals(&self) -> impl Iterator<Item = (&str, &Value<Data>)> {
+        (self.locals.entries()).map(|(ident, value)| (ident.as_str(), value))
+    }
+
+    pub fn get_value(&self, ident: impl AsSymbol) -> Option<&Value<Data>> {
+        let ident = ident.find_symbol()?;
+        match self.locals.get(&ident) {
             None => Some(self.globals.get(ident)?.value()),
             Some(value) => Some(value),
         }
@@ -78,10 +1049,14 @@ impl<Data: Clone> Engine<Data> {
 
     pub fn set_value(
         &mut self,
-        ident: impl AsRef<str>,
+        ident: impl AsSymbol,
         value: Value<Data>,
     ) -> Result<Value<Data>, SetError> {
-        match self.locals.get_mut(ident.as_ref()) {
+        let Some(ident) = ident.find_symbol() else {
+            return Err(SetError::DoesNotExist);
+        };
+
+        match self.locals.get_mut(&ident) {
             Some(old_value) => Ok(replace(old_value, value)),
             None => match self.globals.get_mut(ident) {
                 Some(GlobalValue::Static(old_value)) => Ok(replace(old_value, value)),
@@ -91,169 +1066,743 @@ impl<Data: Clone> Engine<Data> {
         }
     }
 
-    pub fn init_value(&mut self, ident: impl Into<String>, value: Value<Data>) {
+    pub fn init_value(&mut self, ident: impl AsSymbol, value: Value<Data>) {
         self.locals.init(ident, value)
     }
 
-    pub fn init_static(&mut self, ident: impl Into<String>, value: Value<Data>) {
+    pub fn init_static(&mut self, ident: impl AsSymbol, value: Value<Data>) {
         self.init_global(ident, GlobalValue::Static(value))
     }
 
-    pub fn init_const(&mut self, ident: impl Into<String>, value: Value<Data>) {
+    pub fn init_const(&mut self, ident: impl AsSymbol, value: Value<Data>) {
         self.init_global(ident, GlobalValue::Const(value))
     }
 
-    fn init_global(&mut self, ident: impl Into<String>, value: GlobalValue<Data>) {
+    fn init_global(&mut self, ident: impl AsSymbol, value: GlobalValue<Data>) {
         self.globals.init(ident, value)
     }
 
+    /// Evaluates each statement in order, returning the value of the last one
+    ///
+    /// A panic while evaluating a statement is returned as [`RunError::Internal`].
+    pub fn eval_program(
+        &mut self,
+        statements: &[Node<Data, Statement<Data>>],
+    ) -> Result<Value<Data>, RunError<Data>> {
+        self.float_warned.clear();
+        let mut output = Value::None;
+        for statement in statements {
+            output =
+                self.catch_panic(statement.data(), |engine| engine.eval_statement(statement))?;
+        }
+        Ok(output)
+    }
+
+    /// Evaluates a program that other engines may be running at the same time
+    ///
+    /// Errors point into the cache the program was parsed with, not this engine's cache.
+    pub fn eval_shared(
+        &mut self,
+        program: &SharedProgram<Data>,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        self.eval_program(&program.statements)
+    }
+
+    /// Runs `f`, converting a panic into [`RunError::Internal`] located at `data`
+    ///
+    /// A panic skips the code that closes scopes and pops call frames, so everything
+    /// `f` left open is unwound here and the engine can keep being used.
+    fn catch_panic<T>(
+        &mut self,
+        data: &Data,
+        f: impl FnOnce(&mut Self) -> Result<T, RunError<Data>>,
+    ) -> Result<T, RunError<Data>> {
+        let checkpoint = self.checkpoint();
+        match panic::catch_unwind(AssertUnwindSafe(|| f(self))) {
+            Ok(output) => output,
+            Err(payload) => {
+                self.unwind_to(checkpoint);
+                Err(RunError::Internal {
+                    message: panic_message(payload),
+                    data: data.clone(),
+                })
+            }
+        }
+    }
+
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            stack: self.stack.len(),
+            locals: self.locals.depth(),
+            globals: self.globals.depth(),
+            profiler: self.profiler.as_ref().map_or(0, Profiler::depth),
+        }
+    }
+
+    fn unwind_to(&mut self, checkpoint: Checkpoint) {
+        #[cfg(feature = "flamegraph")]
+        if let Some(flame) = &mut self.flame {
+            flame.sample(&self.stack);
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.unwind(checkpoint.profiler);
+        }
+        self.stack.truncate(checkpoint.stack);
+        self.locals.restore(checkpoint.locals);
+        self.globals.restore(checkpoint.globals);
+    }
+
     pub fn eval_statement(
         &mut self,
         statement: &Node<Data, Statement<Data>>,
     ) -> Result<Value<Data>, RunError<Data>> {
-        match statement.deref() {
-            Statement::Expr(expr) => self.eval(expr),
+        if self.step(statement.data())? == StepDecision::Skip {
+            return Ok(Value::None);
+        }
+
+        // statements are charged too, so custom statements without expressions still use fuel
+        self.consume_fuel(statement.data())?;
+
+        let value = match statement.deref() {
+            Statement::Expr(expr) => self.eval_expr(expr),
             Statement::Init(init) => {
-                let value = self.eval(&init.expr)?;
-                let ident = init.ident.deref().clone();
+                self.check_frozen(&init.ident)?;
+                let value = self.eval_expr(&init.expr)?;
+                let ident = &*init.ident;
+                let hooked = self.hooks.is_some().then(|| value.clone());
                 match init.style.deref() {
                     InitStyle::Let => self.init_value(ident, value),
                     InitStyle::Static => self.init_static(ident, value),
                     InitStyle::Const => self.init_const(ident, value),
                 }
+                // hooks see the variable already defined, as they do for assignments
+                if let Some(value) = hooked {
+                    self.with_hooks(|hooks, engine| {
+                        hooks.on_assign(engine, ident.clone(), &value, init.ident.data())
+                    });
+                }
                 Ok(Value::None)
             }
+            Statement::Custom(custom) => match self.custom.get(&custom.keyword) {
+                // clone the handler so it may borrow the engine mutably
+                Some(handler) => handler.clone()(self, custom),
+                None => Err(RunError::UnknownStatement {
+                    keyword: custom.keyword.clone(),
+                    data: custom.data().clone(),
+                }),
+            },
+            Statement::With(with) => self.eval_with(with),
+        }?;
+
+        if self.trace.is_some() {
+            self.trace(TraceOp::Statement, &value, statement.data());
         }
+        Ok(value)
     }
 
-    pub fn eval(&mut self, expr: &Node<Data, Expr<Data>>) -> Result<Value<Data>, RunError<Data>> {
-        match expr.deref() {
-            Expr::None => Ok(Value::None),
-            Expr::Bool(v) => Ok(Value::Bool(v.clone())),
-            Expr::Int(v) => Ok(Value::Int(v.clone())),
-            Expr::Float(v) => Ok(Value::Float(v.clone())),
-            Expr::String(v) => Ok(Value::String(v.clone())),
-            Expr::Call(_ident, _params) => todo!("implement function calls"),
-            Expr::Neg(inner) => {
-                let inner = self.eval(inner)?;
-                self.ops.neg(inner, expr.data())
-            }
-            Expr::Not(inner) => {
-                let inner = self.eval(inner)?;
-                self.ops.not(inner, expr.data())
-            }
-            Expr::Add(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.add(lhs, rhs, expr.data())
+    /// Evaluates the body of a `with` statement, then closes its resource
+    ///
+    /// The resource is closed even if the body fails, in which case the body's error
+    /// is returned. Only user data with a close hook has anything to clean up.
+    fn eval_with(&mut self, with: &Node<Data, With<Data>>) -> Result<Value<Data>, RunError<Data>> {
+        self.check_frozen(&with.ident)?;
+        let resource = self.eval_expr(&with.resource)?;
+
+        self.push_scope();
+        self.init_value(&*with.ident, resource.clone());
+        let output = self.eval_expr(&with.body);
+        self.pop_scope();
+
+        let closed = match &resource {
+            Value::Custom(data) => data.close(),
+            _ => Ok(()),
+        };
+
+        match (output, closed) {
+            (Err(error), _) => Err(error),
+            (Ok(_), Err(error)) => Err(RunError::NativeCallError {
+                kind: error.kind,
+                message: error.message,
+                help: error.help,
+                source: error.source,
+                data: with.resource.data().clone(),
+            }),
+            (Ok(value), Ok(())) => Ok(value),
+        }
+    }
+
+    /// Calls `func` with already evaluated arguments
+    ///
+    /// A panic during the call is returned as [`RunError::Internal`] located at `data`.
+    pub fn eval_func(
+        &mut self,
+        func: &FuncValue<Data>,
+        params: Vec<Value<Data>>,
+        data: &Data,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        self.catch_panic(data, |engine| {
+            engine.call_func(func, params, &[], data, false)
+        })
+    }
+
+    /// Fails the native being called with `error`, an interruption it ran into while
+    /// calling back into the script, like [`RunError::Cancelled`]
+    ///
+    /// Natives can only return a [`NativeError`], so the returned error is swapped for
+    /// `error` once the native returns it, and the interruption reaches the host unchanged.
+    pub(crate) fn interrupt_native(&mut self, error: RunError<Data>) -> NativeError {
+        let message = format!("interrupted by error {}", error.code());
+        self.interrupt = Some(error);
+        NativeError::new(message)
+    }
+
+    /// Calls `func`, using `arg_data` to locate native errors caused by specific arguments
+    fn call_func(
+        &mut self,
+        func: &FuncValue<Data>,
+        params: Vec<Value<Data>>,
+        arg_data: &[Data],
+        data: &Data,
+        framed: bool,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        if params.len() != func.param_count() {
+            return Err(RunError::ParameterCount {
+                expected: func.param_count(),
+                found: params.len(),
+                data: data.clone(),
+            });
+        }
+
+        match func {
+            FuncValue::Native(native) => match native.capability {
+                Some(capability) if !self.capabilities.allows(capability) => {
+                    Err(RunError::NativeCallError {
+                        kind: Some("capability".to_string()),
+                        message: format!("function requires the '{capability}' capability"),
+                        help: Some("the host has not granted this capability".to_string()),
+                        source: None,
+                        data: data.clone(),
+                    })
+                }
+                _ => {
+                    // only a print during this call may turn its error into an output limit
+                    self.output_exceeded = false;
+                    self.interrupt = None;
+                    let result = (native.native)(self, params);
+                    let output_exceeded = std::mem::take(&mut self.output_exceeded);
+                    let interrupt = self.interrupt.take();
+                    match result {
+                        Ok(value) => {
+                            let value = self.ops.round(value);
+                            self.count_string(&value);
+                            Ok(value)
+                        }
+                        // natives only see the io error, which hides why printing failed
+                        Err(_) if output_exceeded => Err(RunError::OutputLimit {
+                            limit: self.output_limit,
+                            data: data.clone(),
+                        }),
+                        Err(_) if interrupt.is_some() => Err(interrupt.expect("interrupt error")),
+                        Err(error) => Err(RunError::NativeCallError {
+                            kind: error.kind,
+                            message: error.message,
+                            help: error.help,
+                            source: error.source,
+                            data: match error.arg.and_then(|index| arg_data.get(index)) {
+                                Some(arg_data) => arg_data.clone(),
+                                None => data.clone(),
+                            },
+                        }),
+                    }
+                }
+            },
+            FuncValue::Custom(custom) => self.call_custom(custom.clone(), params, framed),
+        }
+    }
+
+    /// Calls a script function, running tail calls in place of the current call
+    ///
+    /// When a body ends in a call to another script function, possibly through ternary
+    /// branches, that call replaces the current one instead of nesting inside it, so tail
+    /// recursion runs in constant space and is not counted towards the recursion limit.
+    /// `framed` is set when the caller pushed a [`CallFrame`] for this call, which is then
+    /// updated to each tail call.
+    fn call_custom(
+        &mut self,
+        mut func: Arc<Func<Data>>,
+        mut params: Vec<Value<Data>>,
+        framed: bool,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        loop {
+            // functions only have access to globals and their own parameters
+            self.stash_scope();
+            for (ident, value) in func.params.iter().zip(params) {
+                self.init_value(&**ident, value);
             }
-            Expr::Sub(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.sub(lhs, rhs, expr.data())
+
+            let output = self.eval_body(&func.body);
+            self.unstash_scope();
+
+            let (ident, next, args, data) = match output? {
+                Tail::Return(value) => return Ok(value),
+                Tail::Call(ident, next, args, data) => (ident, next, args, data),
+            };
+
+            if framed {
+                #[cfg(feature = "flamegraph")]
+                if let Some(flame) = &mut self.flame {
+                    flame.sample(&self.stack);
+                }
+
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.exit();
+                    profiler.enter(ident.clone());
+                }
+
+                if let Some(frame) = self.stack.last_mut() {
+                    frame.ident = ident.clone();
+                    frame.data = data.clone();
+                }
             }
-            Expr::Mul(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.mul(lhs, rhs, expr.data())
+
+            if args.len() != next.params.len() {
+                return Err(RunError::ParameterCount {
+                    expected: next.params.len(),
+                    found: args.len(),
+                    data,
+                });
             }
-            Expr::Div(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.div(lhs, rhs, expr.data())
+            self.metrics.calls += 1;
+            self.with_hooks(|hooks, engine| hooks.before_call(engine, ident, &args, &data));
+
+            (func, params) = (next, args);
+        }
+    }
+
+    /// Evaluates a function body, stopping short of a tail call in its last statement
+    fn eval_body(
+        &mut self,
+        body: &[Node<Data, Statement<Data>>],
+    ) -> Result<Tail<Data>, RunError<Data>> {
+        let Some((last, rest)) = body.split_last() else {
+            return Ok(Tail::Return(Value::None));
+        };
+
+        for statement in rest {
+            self.eval_statement(statement)?;
+        }
+
+        let Statement::Expr(expr) = last.deref() else {
+            return self.eval_statement(last).map(Tail::Return);
+        };
+
+        // the same checks as eval_statement, which is bypassed for the tail expression
+        if self.step(last.data())? == StepDecision::Skip {
+            return Ok(Tail::Return(Value::None));
+        }
+        self.consume_fuel(last.data())?;
+
+        let mut expr = expr;
+        loop {
+            match expr.deref() {
+                Expr::Ternary(cond, lhs, rhs) => {
+                    self.consume_fuel(expr.data())?;
+                    expr = match self.eval_expr(cond)? {
+                        Value::Bool(true) => lhs,
+                        Value::Bool(false) => rhs,
+                        value => {
+                            return Err(RunError::TypeMismatch {
+                                expected: ValueType::Bool,
+                                found: value.get_type(),
+                                data: cond.data().clone(),
+                            })
+                        }
+                    };
+                }
+                Expr::Call(ident, params) => {
+                    let Some(Value::Func(FuncValue::Custom(func))) = self.get_value(&**ident)
+                    else {
+                        return self.eval_expr(expr).map(Tail::Return);
+                    };
+
+                    let func = func.clone();
+                    self.consume_fuel(expr.data())?;
+                    let mut args = Vec::with_capacity(params.len());
+                    for param in params {
+                        args.push(self.eval_expr(param)?);
+                    }
+                    return Ok(Tail::Call(
+                        (**ident).clone(),
+                        func,
+                        args,
+                        expr.data().clone(),
+                    ));
+                }
+                _ => return self.eval_expr(expr).map(Tail::Return),
             }
-            Expr::Pow(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.pow(lhs, rhs, expr.data())
+        }
+    }
+
+    fn eval_literal(
+        &self,
+        hooks: &IndexMap<String, LiteralHook<Data>>,
+        tag: &Node<Data, String>,
+        value: Value<Data>,
+        data: &Data,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        let hook = match hooks.get(tag.deref()) {
+            Some(hook) => hook,
+            None => {
+                return Err(RunError::UnknownLiteral {
+                    tag: tag.deref().clone(),
+                    data: tag.data().clone(),
+                })
             }
-            Expr::Mod(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.modulo(lhs, rhs, expr.data())
+        };
+
+        hook(value).map_err(|error| RunError::NativeCallError {
+            kind: error.kind,
+            message: error.message,
+            help: error.help,
+            source: error.source,
+            data: data.clone(),
+        })
+    }
+
+    fn consume_fuel(&mut self, data: &Data) -> Result<(), RunError<Data>> {
+        if let Some(fuel) = &mut self.fuel {
+            match fuel.checked_sub(1) {
+                Some(remaining) => *fuel = remaining,
+                None => return Err(RunError::OutOfFuel { data: data.clone() }),
             }
-            Expr::Eq(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.eq(lhs, rhs, expr.data())
+        }
+
+        self.check_interrupts(data)
+    }
+
+    /// Fails if the host cancelled evaluation or the deadline has passed
+    fn check_interrupts(&self, data: &Data) -> Result<(), RunError<Data>> {
+        if self.cancel.is_cancelled() {
+            return Err(RunError::Cancelled { data: data.clone() });
+        }
+
+        if let Some(deadline) = self.deadline.filter(|_| !self.lockstep) {
+            if Instant::now() >= deadline {
+                return Err(RunError::Timeout { data: data.clone() });
             }
-            Expr::Lt(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.lt(lhs, rhs, expr.data())
+        }
+
+        Ok(())
+    }
+
+    /// Evaluates an expression using a work stack on the heap
+    ///
+    /// Nested operators, ternaries and arguments never recurse on the native stack,
+    /// so the depth of an expression is only limited by memory. Calling a script
+    /// function still evaluates its body recursively, bounded by the recursion limit.
+    /// A panic while evaluating is returned as [`RunError::Internal`].
+    pub fn eval(&mut self, expr: &Node<Data, Expr<Data>>) -> Result<Value<Data>, RunError<Data>> {
+        self.float_warned.clear();
+        self.catch_panic(expr.data(), |engine| engine.eval_expr(expr))
+    }
+
+    fn eval_expr(&mut self, expr: &Node<Data, Expr<Data>>) -> Result<Value<Data>, RunError<Data>> {
+        let mut tasks = vec![Task::Eval(expr)];
+        let mut values = Vec::new();
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Eval(expr) => {
+                    // runs once the value of the expression is on the stack
+                    if self.trace.is_some() {
+                        tasks.push(Task::Trace(expr));
+                    }
+
+                    if let Some(value) = self.eval_step(expr, &mut tasks)? {
+                        values.push(value);
+                    }
+                }
+                Task::Trace(expr) => {
+                    let value = values.last().expect("traced value").clone();
+                    self.trace(TraceOp::of(expr), &value, expr.data());
+                }
+                Task::Suffix(suffix, data) => {
+                    let value = values.pop().expect("suffixed operand");
+                    values.push(self.eval_literal(&self.suffixes, suffix, value, data)?);
+                }
+                Task::Unary(op, data) => {
+                    let inner = values.pop().expect("unary operand");
+                    let value = self.ops.unary(op, inner, data)?;
+                    self.count_string(&value);
+                    values.push(value);
+                }
+                Task::Binary(op, data) => {
+                    let rhs = values.pop().expect("binary rhs");
+                    let lhs = values.pop().expect("binary lhs");
+                    if self.warnings.is_some()
+                        && matches!(op, BinaryOp::Eq | BinaryOp::NEq)
+                        && (matches!(lhs, Value::Float(_)) || matches!(rhs, Value::Float(_)))
+                        && self.float_warned.insert(data as *const Data as usize)
+                    {
+                        self.warn(Warning::FloatEquality { data: data.clone() });
+                    }
+                    let value = self.ops.binary(op, lhs, rhs, data)?;
+                    self.count_string(&value);
+                    values.push(value);
+                }
+                Task::Branch(cond, lhs, rhs) => match values.pop().expect("ternary condition") {
+                    Value::Bool(true) => tasks.push(Task::Eval(lhs)),
+                    Value::Bool(false) => tasks.push(Task::Eval(rhs)),
+                    value => {
+                        return Err(RunError::TypeMismatch {
+                            expected: ValueType::Bool,
+                            found: value.get_type(),
+                            data: cond.data().clone(),
+                        })
+                    }
+                },
+                Task::Assign(ident, data, walrus) => {
+                    let new_value = values.pop().expect("assigned value");
+                    let output = match walrus {
+                        true => new_value.clone(), // return newly created value
+                        false => Value::None,      // return nothing
+                    };
+                    let hooked = self.hooks.is_some().then(|| new_value.clone());
+                    match self.set_value(ident.deref(), new_value) {
+                        Ok(_old_value) => {
+                            if let Some(value) = hooked {
+                                self.with_hooks(|hooks, engine| {
+                                    hooks.on_assign(engine, (**ident).clone(), &value, ident.data())
+                                });
+                            }
+                            values.push(output)
+                        }
+                        Err(SetError::Const) => {
+                            return Err(RunError::ConstAssignment { data: data.clone() })
+                        }
+                        Err(SetError::DoesNotExist) => {
+                            return Err(RunError::UnknownVariable {
+                                ident: ident.to_string(),
+                                data: ident.data().clone(),
+                            })
+                        }
+                    }
+                }
+                Task::Call(ident, params, data, func) => {
+                    let args = values.split_off(values.len() - params.len());
+                    let arg_data = params.iter().map(|param| param.data().clone()).collect();
+                    values.push(self.finish_call(ident, &func, args, arg_data, data)?);
+                }
             }
-            Expr::Gt(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.gt(lhs, rhs, expr.data())
+        }
+
+        Ok(values.pop().expect("expression value"))
+    }
+
+    /// Returns the value of `expr` if it is a leaf, otherwise schedules its operands and returns `None`
+    fn eval_step<'a>(
+        &mut self,
+        expr: &'a Node<Data, Expr<Data>>,
+        tasks: &mut Vec<Task<'a, Data>>,
+    ) -> Result<Option<Value<Data>>, RunError<Data>> {
+        self.consume_fuel(expr.data())?;
+        let data = expr.data();
+        let (op, lhs, rhs) = match expr.deref() {
+            Expr::None => return Ok(Some(Value::None)),
+            Expr::Bool(v) => return Ok(Some(Value::Bool(*v))),
+            Expr::Int(v) => return Ok(Some(Value::Int(v.clone()))),
+            Expr::Float(v) => return Ok(Some(Value::Float(v.clone()))),
+            Expr::String(v) => return Ok(Some(Value::String(v.clone()))),
+            Expr::Prefixed(prefix, text) => {
+                let value = Value::String(text.as_str().into());
+                return self
+                    .eval_literal(&self.prefixes, prefix, value, data)
+                    .map(Some);
             }
-            Expr::NEq(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.neq(lhs, rhs, expr.data())
+            Expr::Suffixed(number, suffix) => {
+                tasks.push(Task::Suffix(suffix, data));
+                tasks.push(Task::Eval(number));
+                return Ok(None);
             }
-            Expr::LtEq(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.lteq(lhs, rhs, expr.data())
+            Expr::Func(func) => {
+                for param in func.params.iter() {
+                    self.check_frozen(param)?;
+                }
+                return Ok(Some(Value::Func(FuncValue::Custom(func.clone()))));
             }
-            Expr::GtEq(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.gteq(lhs, rhs, expr.data())
+            Expr::Call(ident, params) => {
+                let func = self.start_call(ident, data)?;
+                tasks.push(Task::Call(ident, params, data, func));
+                tasks.extend(params.iter().rev().map(Task::Eval));
+                return Ok(None);
             }
-            Expr::And(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.and(lhs, rhs, expr.data())
+            Expr::Neg(inner) => {
+                tasks.push(Task::Unary(UnaryOp::Neg, data));
+                tasks.push(Task::Eval(inner));
+                return Ok(None);
             }
-            Expr::Or(lhs, rhs) => {
-                let lhs = self.eval(lhs)?;
-                let rhs = self.eval(rhs)?;
-                self.ops.or(lhs, rhs, expr.data())
+            Expr::Not(inner) => {
+                tasks.push(Task::Unary(UnaryOp::Not, data));
+                tasks.push(Task::Eval(inner));
+                return Ok(None);
             }
             Expr::Var(ident) => match self.get_value(ident.deref()) {
-                Some(value) => Ok(value.clone()),
-                None => Err(RunError::UnknownVariable {
-                    ident: ident.clone(),
-                    data: expr.data().clone(),
-                }),
-            },
-            Expr::Ternary(cond, lhs, rhs) => match self.eval(cond)? {
-                Value::Bool(true) => self.eval(lhs),
-                Value::Bool(false) => self.eval(rhs),
-                value => Err(RunError::TypeMismatch {
-                    expected: ValueType::Bool,
-                    found: value.get_type(),
-                    data: cond.data().clone(),
-                }),
-            },
-            Expr::Assign(ident, rhs) => {
-                let new_value = self.eval(rhs)?;
-                match self.set_value(ident.deref(), new_value) {
-                    Ok(_old_value) => Ok(Value::None), // return nothing
-                    Err(SetError::Const) => Err(RunError::ConstAssignment {
-                        data: expr.data().clone(),
-                    }),
-                    Err(SetError::DoesNotExist) => Err(RunError::UnknownVariable {
-                        ident: ident.deref().clone(),
-                        data: ident.data().clone(),
-                    }),
+                Some(value) => return Ok(Some(value.clone())),
+                None => {
+                    return Err(RunError::UnknownVariable {
+                        ident: ident.to_string(),
+                        data: data.clone(),
+                    })
                 }
+            },
+            Expr::Ternary(cond, lhs, rhs) => {
+                tasks.push(Task::Branch(cond, lhs, rhs));
+                tasks.push(Task::Eval(cond));
+                return Ok(None);
             }
-            Expr::Walrus(ident, rhs) => {
-                let new_value = self.eval(rhs)?;
-                match self.set_value(ident.deref(), new_value.clone()) {
-                    Ok(_old_value) => Ok(new_value), // return newly created value
-                    Err(SetError::Const) => Err(RunError::ConstAssignment {
-                        data: expr.data().clone(),
-                    }),
-                    Err(SetError::DoesNotExist) => Err(RunError::UnknownVariable {
-                        ident: ident.deref().clone(),
-                        data: ident.data().clone(),
-                    }),
-                }
+            Expr::Assign(ident, rhs) | Expr::Walrus(ident, rhs) => {
+                self.check_frozen(ident)?;
+                let walrus = matches!(expr.deref(), Expr::Walrus(..));
+                tasks.push(Task::Assign(ident, data, walrus));
+                tasks.push(Task::Eval(rhs));
+                return Ok(None);
             }
+            Expr::Add(lhs, rhs) => (BinaryOp::Add, lhs, rhs),
+            Expr::Sub(lhs, rhs) => (BinaryOp::Sub, lhs, rhs),
+            Expr::Mul(lhs, rhs) => (BinaryOp::Mul, lhs, rhs),
+            Expr::Div(lhs, _, rhs) => (BinaryOp::Div, lhs, rhs),
+            Expr::Pow(lhs, _, rhs) => (BinaryOp::Pow, lhs, rhs),
+            Expr::Mod(lhs, _, rhs) => (BinaryOp::Mod, lhs, rhs),
+            Expr::Eq(lhs, rhs) => (BinaryOp::Eq, lhs, rhs),
+            Expr::Lt(lhs, rhs) => (BinaryOp::Lt, lhs, rhs),
+            Expr::Gt(lhs, rhs) => (BinaryOp::Gt, lhs, rhs),
+            Expr::NEq(lhs, rhs) => (BinaryOp::NEq, lhs, rhs),
+            Expr::LtEq(lhs, rhs) => (BinaryOp::LtEq, lhs, rhs),
+            Expr::GtEq(lhs, rhs) => (BinaryOp::GtEq, lhs, rhs),
+            Expr::And(lhs, rhs) => (BinaryOp::And, lhs, rhs),
+            Expr::Or(lhs, rhs) => (BinaryOp::Or, lhs, rhs),
+        };
+
+        // errors like division by zero point at the operator when the tree has its span
+        let data = match expr.deref() {
+            Expr::Div(_, op, _) | Expr::Mod(_, op, _) | Expr::Pow(_, op, _) => op.data(),
+            _ => data,
+        };
+        tasks.push(Task::Binary(op, data));
+        tasks.push(Task::Eval(rhs));
+        tasks.push(Task::Eval(lhs));
+        Ok(None)
+    }
+
+    /// Checks the recursion limit and looks up the function a call refers to
+    fn start_call(
+        &self,
+        ident: &Node<Data, Symbol>,
+        data: &Data,
+    ) -> Result<FuncValue<Data>, RunError<Data>> {
+        if let Some(limit) = self
+            .recursion_limit
+            .filter(|limit| self.stack.len() >= *limit)
+        {
+            let data = data.clone();
+            return Err(RunError::RecursionLimit { limit, data });
         }
+
+        match self.get_value(ident.deref()) {
+            Some(Value::Func(func)) => Ok(func.clone()),
+            Some(value) => Err(RunError::InvalidCall {
+                ident: ident.to_string(),
+                found: value.get_type(),
+                data: ident.data().clone(),
+            }),
+            None => Err(RunError::UnknownFunction {
+                ident: ident.to_string(),
+                data: ident.data().clone(),
+            }),
+        }
+    }
+
+    /// Calls `func` with its already evaluated arguments inside a new stack frame
+    fn finish_call(
+        &mut self,
+        ident: &Node<Data, Symbol>,
+        func: &FuncValue<Data>,
+        values: Vec<Value<Data>>,
+        arg_data: Vec<Data>,
+        data: &Data,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        #[cfg(feature = "flamegraph")]
+        if let Some(flame) = &mut self.flame {
+            flame.sample(&self.stack);
+        }
+
+        self.stack.push(CallFrame {
+            ident: (**ident).clone(),
+            data: data.clone(),
+        });
+        self.metrics.calls += 1;
+        self.metrics.peak_depth = self.metrics.peak_depth.max(self.stack.len());
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter((**ident).clone());
+        }
+        self.with_hooks(|hooks, engine| {
+            hooks.before_call(engine, (**ident).clone(), &values, data)
+        });
+
+        // the innermost call attaches the stack trace to the error,
+        // except for recursion errors where it would only repeat the same call
+        let output = match self.call_func(func, values, &arg_data, data, true) {
+            Err(error @ (RunError::Traced { .. } | RunError::RecursionLimit { .. })) => Err(error),
+            Err(error) if matches!(func, FuncValue::Native(_)) => Err(error),
+            Err(error) => Err(RunError::Traced {
+                error: Box::new(error),
+                trace: self.stack.clone(),
+            }),
+            Ok(value) => Ok(value),
+        };
+
+        #[cfg(feature = "flamegraph")]
+        if let Some(flame) = &mut self.flame {
+            flame.sample(&self.stack);
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+        }
+
+        self.stack.pop();
+        output
+    }
+}
+
+impl Engine<CacheSpan> {
+    /// Creates an engine with the builtins and the [prelude](super::builtin::PRELUDE) loaded
+    ///
+    /// The prelude only defines statics, so hosts can override or extend it
+    /// by evaluating their own source with [`Engine::eval_str`] afterwards.
+    pub fn with_prelude() -> Self {
+        let mut engine = Self::new();
+        load_prelude(&mut engine).expect("prelude is valid");
+        engine
+    }
+
+    /// Stores `source` in the engine cache, then parses and evaluates it as a full program
+    ///
+    /// Returns the value of the last statement.
+    /// Errors can be reported using the engine [`cache`](Engine::cache_mut).
+    /// Panics while parsing or evaluating are returned as [`BobaError::Internal`].
+    pub fn eval_str(
+        &mut self,
+        label: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Result<Value<CacheSpan>, BobaError> {
+        let (label, source) = (label.into(), source.into());
+        BobaError::catch_panic(|| {
+            let data = self.cache.store(label, source)?;
+            let mut program = Program::parse(&mut Lexer::new(data))?;
+            program.fold_strings();
+            program.dedup_strings();
+            Ok(self.eval_program(&program.statements)?)
+        })
     }
 }
@@ -1,10 +1,105 @@
-use std::fmt::Debug;
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+    sync::Arc,
+};
 
-use ariadne::{Color, Label, Report, ReportKind, Span};
+use ariadne::{Color, Label, Report, ReportBuilder, ReportKind, Span};
 
 use crate::cache::CacheSpan;
 
-use super::value::ValueType;
+use super::{
+    engine::{CallFrame, OutputLimit},
+    value::ValueType,
+};
+
+/// The underlying error that caused a [`NativeError`], such as a host io error
+pub type ErrorSource = Arc<dyn Error + Send + Sync>;
+
+/// Error returned from a native function
+#[derive(Debug, Clone)]
+pub struct NativeError {
+    /// Namespaced category of the error, like `io` or `json.parse`
+    pub kind: Option<String>,
+    pub message: String,
+    pub help: Option<String>,
+    /// Index of the argument that caused the error
+    pub arg: Option<usize>,
+    /// The error that caused this one, whose own sources form the rest of the chain
+    pub source: Option<ErrorSource>,
+}
+
+impl From<String> for NativeError {
+    fn from(message: String) -> Self {
+        Self::new(message)
+    }
+}
+
+impl From<&str> for NativeError {
+    fn from(message: &str) -> Self {
+        Self::new(message)
+    }
+}
+
+impl NativeError {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            kind: None,
+            message: message.into(),
+            help: None,
+            arg: None,
+            source: None,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: impl Into<String>) -> Self {
+        self.kind = Some(kind.into());
+        self
+    }
+
+    /// Attaches the error that caused this one, keeping its chain of sources
+    pub fn with_source(mut self, source: impl Error + Send + Sync + 'static) -> Self {
+        self.source = Some(Arc::new(source));
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Points the error at the argument with the given index
+    pub fn with_arg(mut self, index: usize) -> Self {
+        self.arg = Some(index);
+        self
+    }
+}
+
+impl Display for NativeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            Some(kind) => write!(f, "{kind}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl Error for NativeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref().map(|source| source as _)
+    }
+}
+
+/// Returns the messages of `source` and every error it was caused by, outermost first
+pub fn error_chain(source: Option<&ErrorSource>) -> Vec<String> {
+    let mut chain = Vec::new();
+    let mut next: Option<&(dyn Error + 'static)> = source.map(|source| source.as_ref() as _);
+    while let Some(error) = next {
+        chain.push(error.to_string());
+        next = error.source();
+    }
+    chain
+}
 
 #[derive(Debug, Clone)]
 #[repr(u8)]
@@ -39,7 +134,10 @@ pub enum RunError<Data> {
         data: Data,
     },
     NativeCallError {
+        kind: Option<String>,
         message: String,
+        help: Option<String>,
+        source: Option<ErrorSource>,
         data: Data,
     },
     StringAllocError {
@@ -53,13 +151,104 @@ pub enum RunError<Data> {
     ConstAssignment {
         data: Data,
     },
+    DivisionByZero {
+        data: Data,
+    },
+    Frozen {
+        ident: String,
+        data: Data,
+    },
+    OutOfFuel {
+        data: Data,
+    },
+    Timeout {
+        data: Data,
+    },
+    UnknownStatement {
+        keyword: String,
+        data: Data,
+    },
+    UnknownLiteral {
+        tag: String,
+        data: Data,
+    },
+    Cancelled {
+        data: Data,
+    },
+    RecursionLimit {
+        limit: usize,
+        data: Data,
+    },
+    OutputLimit {
+        limit: OutputLimit,
+        data: Data,
+    },
+    /// Evaluation panicked here, either in boba or in a native function provided by the host
+    Internal {
+        message: String,
+        data: Data,
+    },
+    Traced {
+        error: Box<RunError<Data>>,
+        trace: Vec<CallFrame<Data>>,
+    },
+}
+
+impl<Data> RunError<Data> {
+    /// Returns the innermost error, skipping any call stack traces
+    pub fn root(&self) -> &Self {
+        match self {
+            RunError::Traced { error, .. } => error.root(),
+            error => error,
+        }
+    }
+
+    /// Returns the code shown in the error report, like `R-001`
+    pub fn code(&self) -> &'static str {
+        match self {
+            RunError::UnknownVariable { .. } => "R-001",
+            RunError::UnknownFunction { .. } => "R-002",
+            RunError::InvalidUnary { .. } => "R-003",
+            RunError::InvalidBinary { .. } => "R-004",
+            RunError::TypeMismatch { .. } => "R-005",
+            RunError::ParameterCount { .. } => "R-006",
+            RunError::NativeCallError { .. } => "R-007",
+            RunError::StringAllocError { .. } => "R-008",
+            RunError::InvalidCall { .. } => "R-009",
+            RunError::ConstAssignment { .. } => "R-010",
+            RunError::OutOfFuel { .. } => "R-011",
+            RunError::Timeout { .. } => "R-012",
+            RunError::DivisionByZero { .. } => "R-013",
+            RunError::Frozen { .. } => "R-014",
+            RunError::UnknownStatement { .. } => "R-015",
+            RunError::UnknownLiteral { .. } => "R-016",
+            RunError::Cancelled { .. } => "R-017",
+            RunError::RecursionLimit { .. } => "R-018",
+            RunError::OutputLimit { .. } => "R-019",
+            RunError::Internal { .. } => "R-020",
+            RunError::Traced { error, .. } => error.code(),
+        }
+    }
+
+    /// Returns the call stack that was active when the error occurred
+    pub fn trace(&self) -> &[CallFrame<Data>] {
+        match self {
+            RunError::Traced { trace, .. } => trace,
+            _ => &[],
+        }
+    }
 }
 
 impl RunError<CacheSpan> {
-    pub fn report(&self) -> Report<CacheSpan> {
+    /// Builds an ariadne [`Report`] labelling the span that caused the error
+    pub fn report(&self) -> Report<'_, CacheSpan> {
+        self.builder().finish()
+    }
+
+    fn builder(&self) -> ReportBuilder<'_, CacheSpan> {
         match self {
             RunError::UnknownVariable { ident, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_message("Unknown Variable")
                     .with_code("R-001")
                     .with_label(
@@ -69,7 +258,7 @@ impl RunError<CacheSpan> {
                     )
             }
             RunError::UnknownFunction { ident, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_message("Unknown Function")
                     .with_code("R-002")
                     .with_label(
@@ -79,9 +268,9 @@ impl RunError<CacheSpan> {
                     )
             }
             RunError::InvalidUnary { op, vtype, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_message("Invalid Unary Operator")
-                    .with_code(format!("R-003"))
+                    .with_code("R-003")
                     .with_label(
                         Label::new(data.clone())
                             .with_color(Color::Red)
@@ -93,9 +282,9 @@ impl RunError<CacheSpan> {
                 vtype1,
                 vtype2,
                 data,
-            } => Report::build(ReportKind::Error, data.source().clone(), data.start())
+            } => Report::build(ReportKind::Error, *data.source(), data.start())
                 .with_message("Invalid Binary Operator")
-                .with_code(format!("R-004"))
+                .with_code("R-004")
                 .with_label(
                     Label::new(data.clone())
                         .with_color(Color::Red)
@@ -107,7 +296,7 @@ impl RunError<CacheSpan> {
                 expected,
                 found,
                 data,
-            } => Report::build(ReportKind::Error, data.source().clone(), data.start())
+            } => Report::build(ReportKind::Error, *data.source(), data.start())
                 .with_message("Type Mismatch")
                 .with_code("R-005")
                 .with_label(
@@ -119,7 +308,7 @@ impl RunError<CacheSpan> {
                 expected,
                 found,
                 data,
-            } => Report::build(ReportKind::Error, data.source().clone(), data.start())
+            } => Report::build(ReportKind::Error, *data.source(), data.start())
                 .with_message("Wrong Parameter Count")
                 .with_code("R-006")
                 .with_label(
@@ -129,18 +318,43 @@ impl RunError<CacheSpan> {
                             "function expects {expected} parameters, found {found}"
                         )),
                 ),
-            RunError::NativeCallError { message, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
-                    .with_message("Native Call Error")
+            RunError::NativeCallError {
+                kind,
+                message,
+                help,
+                source,
+                data,
+            } => {
+                let title = match kind {
+                    Some(kind) => format!("Native Call Error ({kind})"),
+                    None => "Native Call Error".to_string(),
+                };
+                let mut report = Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message(title)
                     .with_code("R-007")
                     .with_label(
                         Label::new(data.clone())
                             .with_color(Color::Red)
                             .with_message(message),
-                    )
+                    );
+
+                if let Some(help) = help {
+                    report = report.with_help(help);
+                }
+
+                let chain = error_chain(source.as_ref());
+                if !chain.is_empty() {
+                    let causes: Vec<String> = chain
+                        .into_iter()
+                        .map(|cause| format!("caused by: {cause}"))
+                        .collect();
+                    report = report.with_note(causes.join("\n"));
+                }
+
+                report
             }
             RunError::StringAllocError { data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_message("String Alloc Error")
                     .with_code("R-008")
                     .with_label(
@@ -152,7 +366,7 @@ impl RunError<CacheSpan> {
                     )
             }
             RunError::InvalidCall { ident, found, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_message("Invalid Call")
                     .with_code("R-009")
                     .with_label(
@@ -164,7 +378,7 @@ impl RunError<CacheSpan> {
                     )
             }
             RunError::ConstAssignment { data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_message("Const Assignment")
                     .with_code("R-010")
                     .with_label(
@@ -173,7 +387,121 @@ impl RunError<CacheSpan> {
                             .with_message("cannot assign value to a constant"),
                     )
             }
+            RunError::DivisionByZero { data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Division by Zero")
+                    .with_code("R-013")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message("this operation divides by zero"),
+                    )
+            }
+            RunError::Frozen { ident, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Frozen Definition")
+                    .with_code("R-014")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!("'{ident}' is frozen and cannot be redefined")),
+                    )
+            }
+            RunError::OutOfFuel { data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Out of Fuel")
+                    .with_code("R-011")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message("ran out of fuel while evaluating this expression"),
+                    )
+            }
+            RunError::Timeout { data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Timeout")
+                    .with_code("R-012")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message("time limit reached while evaluating this expression"),
+                    )
+            }
+            RunError::UnknownStatement { keyword, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Unknown Statement")
+                    .with_code("R-015")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!("no handler registered for '{keyword}'")),
+                    )
+            }
+            RunError::UnknownLiteral { tag, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Unknown Literal")
+                    .with_code("R-016")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!("no literal registered for '{tag}'")),
+                    )
+            }
+            RunError::Cancelled { data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Cancelled")
+                    .with_code("R-017")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message("evaluation was cancelled by the host here"),
+                    )
+            }
+            RunError::RecursionLimit { limit, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Recursion Limit")
+                    .with_code("R-018")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!(
+                                "this call exceeds the limit of {limit} nested calls"
+                            )),
+                    )
+                    .with_help("check that the recursion has a base case that is always reached")
+            }
+            RunError::OutputLimit { limit, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Output Limit")
+                    .with_code("R-019")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!("this call exceeds the output limit of {limit}")),
+                    )
+                    .with_help("the host limits how much a script may print")
+            }
+            RunError::Internal { message, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Internal Error")
+                    .with_code("R-020")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!("evaluation panicked here: {message}")),
+                    )
+                    .with_help("panics are bugs in boba or in host code, not in the script")
+            }
+            RunError::Traced { error, trace } => {
+                // label each call site from the innermost call outwards
+                let labels = trace.iter().rev().enumerate().map(|(index, frame)| {
+                    Label::new(frame.data.clone())
+                        .with_color(Color::Yellow)
+                        .with_order(index as i32 + 1)
+                        .with_message(format!("in call to '{}'", frame.ident))
+                });
+                error.builder().with_labels(labels)
+            }
         }
-        .finish()
     }
 }
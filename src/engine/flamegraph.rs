@@ -0,0 +1,68 @@
+use std::{
+    io::{self, Write},
+    time::Instant,
+};
+
+use indexmap::IndexMap;
+
+use super::CallFrame;
+
+/// Records the time spent in each script call stack while an engine runs
+///
+/// Time is charged to the innermost frame whenever a call starts or returns,
+/// so every stack gets its self time in microseconds. The output uses the folded
+/// stack format read by `inferno-flamegraph` and `flamegraph.pl`.
+#[derive(Debug, Clone)]
+pub struct FlameRecorder {
+    stacks: IndexMap<String, u64>,
+    last: Instant,
+}
+
+impl Default for FlameRecorder {
+    fn default() -> Self {
+        Self {
+            stacks: IndexMap::new(),
+            last: Instant::now(),
+        }
+    }
+}
+
+impl FlameRecorder {
+    /// Name of the root frame, for time spent outside of any function call
+    pub const ROOT: &'static str = "script";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Charges the time since the last sample to `stack`
+    pub fn sample<Data>(&mut self, stack: &[CallFrame<Data>]) {
+        let now = Instant::now();
+        let micros = now.duration_since(self.last).as_micros() as u64;
+        self.last = now;
+
+        let mut key = Self::ROOT.to_string();
+        for frame in stack {
+            key.push(';');
+            key.push_str(&frame.ident);
+        }
+        *self.stacks.entry(key).or_default() += micros;
+    }
+
+    /// Returns each recorded stack with its self time in microseconds, in the order first seen
+    pub fn stacks(&self) -> impl Iterator<Item = (&str, u64)> {
+        self.stacks
+            .iter()
+            .map(|(stack, micros)| (stack.as_str(), *micros))
+    }
+
+    /// Writes the folded stacks, one `root;outer;inner micros` line per stack
+    ///
+    /// Stacks that took less than a microsecond are left out.
+    pub fn write_folded(&self, mut writer: impl Write) -> io::Result<()> {
+        for (stack, micros) in self.stacks().filter(|(_, micros)| *micros > 0) {
+            writeln!(writer, "{stack} {micros}")?;
+        }
+        Ok(())
+    }
+}
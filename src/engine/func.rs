@@ -1,8 +1,8 @@
-use std::sync::Arc;
+use std::{fmt::Debug, sync::Arc};
 
-use crate::parser::ast::Func;
+use crate::{parser::ast::Func, Engine};
 
-use super::Value;
+use super::{error::NativeError, Capability, Value};
 
 #[derive(Debug, Clone)]
 pub enum FuncValue<Data> {
@@ -19,6 +19,15 @@ impl<Data> FuncValue<Data> {
         Self::Native(Arc::new(func))
     }
 
+    /// Returns true if both values point to the same function
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (FuncValue::Custom(f1), FuncValue::Custom(f2)) => Arc::ptr_eq(f1, f2),
+            (FuncValue::Native(f1), FuncValue::Native(f2)) => Arc::ptr_eq(f1, f2),
+            _ => false,
+        }
+    }
+
     pub fn param_count(&self) -> usize {
         match self {
             FuncValue::Custom(func) => func.params.len(),
@@ -27,19 +36,44 @@ impl<Data> FuncValue<Data> {
     }
 }
 
-pub type NativeFuncImpl<Data> = fn(Vec<Value<Data>>) -> Result<Value<Data>, String>;
+pub type NativeFuncImpl<Data> = Box<
+    dyn Fn(&mut Engine<Data>, Vec<Value<Data>>) -> Result<Value<Data>, NativeError> + Send + Sync,
+>;
 
-#[derive(Debug, Clone)]
 pub struct NativeFunc<Data> {
     pub param_count: usize,
+    /// Capability the engine must grant for this function to be called
+    pub capability: Option<Capability>,
     pub native: NativeFuncImpl<Data>,
 }
 
+impl<Data> Debug for NativeFunc<Data> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NativeFunc")
+            .field("param_count", &self.param_count)
+            .field("capability", &self.capability)
+            .finish_non_exhaustive()
+    }
+}
+
 impl<Data> NativeFunc<Data> {
-    pub fn new(param_count: usize, native: NativeFuncImpl<Data>) -> Self {
+    pub fn new(
+        param_count: usize,
+        native: impl Fn(&mut Engine<Data>, Vec<Value<Data>>) -> Result<Value<Data>, NativeError>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
         Self {
             param_count,
-            native,
+            capability: None,
+            native: Box::new(native),
         }
     }
+
+    /// Marks the function as needing `capability`, so it fails in engines that do not grant it
+    pub fn requires(mut self, capability: Capability) -> Self {
+        self.capability = Some(capability);
+        self
+    }
 }
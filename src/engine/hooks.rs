@@ -0,0 +1,70 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::symbol::Symbol;
+
+use super::{Engine, StepDecision, Value};
+
+/// Callbacks for observing and stepping through evaluation, see [`Engine::set_hooks`]
+///
+/// Each callback receives the engine, so a debugger can read the [call stack](Engine::call_stack)
+/// or take a [snapshot](Engine::snapshot) of the visible scope at that point.
+/// Every callback does nothing by default.
+pub trait EngineHooks<Data>: Send + Sync {
+    /// Called before each statement is evaluated, including statements in functions
+    ///
+    /// Returning [`StepDecision::Pause`] holds execution on this statement,
+    /// calling the hook again until it returns something else.
+    fn before_statement(&mut self, _engine: &Engine<Data>, _data: &Data) -> StepDecision {
+        StepDecision::Continue
+    }
+
+    /// Called before a function is entered with its evaluated arguments
+    ///
+    /// Tail calls are reported too, even though they replace the current call on the stack.
+    fn before_call(
+        &mut self,
+        _engine: &Engine<Data>,
+        _ident: Symbol,
+        _args: &[Value<Data>],
+        _data: &Data,
+    ) {
+    }
+
+    /// Called after a variable is defined or assigned a new value
+    fn on_assign(
+        &mut self,
+        _engine: &Engine<Data>,
+        _ident: Symbol,
+        _value: &Value<Data>,
+        _data: &Data,
+    ) {
+    }
+}
+
+/// A shared flag that holds an engine before its next statement
+///
+/// Clones share the same flag, so a debugger can pause and resume an engine running
+/// on another thread. A paused engine still stops when it is cancelled or times out.
+#[derive(Debug, Clone, Default)]
+pub struct PauseToken(Arc<AtomicBool>);
+
+impl PauseToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn pause(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
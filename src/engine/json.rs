@@ -0,0 +1,96 @@
+use dashu::integer::IBig;
+use serde_json::{Number, Value as Json};
+
+use super::{
+    error::NativeError, serialize::exact_f64, Engine, FuncValue, IntoValue, NativeBuilder, Value,
+};
+
+/// Converts a parsed json value into a value
+///
+/// Arrays and objects are rejected, since there are no values to represent them yet.
+/// Integers outside the 64 bit range are read as floats by `serde_json`.
+impl<Data> TryFrom<Json> for Value<Data> {
+    type Error = NativeError;
+
+    fn try_from(json: Json) -> Result<Self, Self::Error> {
+        match json {
+            Json::Null => Ok(Value::None),
+            Json::Bool(v) => Ok(Value::Bool(v)),
+            Json::Number(v) => {
+                if let Some(v) = v.as_i64() {
+                    Ok(Value::Int(IBig::from(v)))
+                } else if let Some(v) = v.as_u64() {
+                    Ok(Value::Int(IBig::from(v)))
+                } else {
+                    v.as_f64().unwrap_or(f64::NAN).into_value()
+                }
+            }
+            Json::String(v) => Ok(Value::String(v.into())),
+            Json::Array(_) | Json::Object(_) => Err(NativeError::new(
+                "json arrays and objects are not supported",
+            )),
+        }
+    }
+}
+
+/// Converts a value into json, with the same limits as serializing it
+///
+/// Only none, bools, numbers and strings can be represented.
+impl<Data> TryFrom<&Value<Data>> for Json {
+    type Error = NativeError;
+
+    fn try_from(value: &Value<Data>) -> Result<Self, Self::Error> {
+        match value {
+            Value::None => Ok(Json::Null),
+            Value::Bool(v) => Ok(Json::Bool(*v)),
+            Value::Int(v) => {
+                if let Ok(v) = i64::try_from(v) {
+                    Ok(Json::from(v))
+                } else if let Ok(v) = u64::try_from(v) {
+                    Ok(Json::from(v))
+                } else {
+                    Err(NativeError::new(format!(
+                        "int '{v}' is too large to convert to json"
+                    )))
+                }
+            }
+            Value::Float(v) => match exact_f64(v).and_then(Number::from_f64) {
+                Some(v) => Ok(Json::Number(v)),
+                None => Err(NativeError::new(format!(
+                    "float '{v}' cannot be converted to json without losing precision"
+                ))),
+            },
+            Value::String(v) => Ok(Json::String(v.to_string())),
+            value => Err(NativeError::new(format!(
+                "'{}' values cannot be converted to json",
+                value.get_type()
+            ))),
+        }
+    }
+}
+
+/// Serializes a value as json
+pub fn to_json<Data>(value: &Value<Data>) -> Result<String, NativeError> {
+    Json::try_from(value).map(|json| json.to_string())
+}
+
+/// Parses a json document into a value
+pub fn from_json<Data>(json: &str) -> Result<Value<Data>, NativeError> {
+    let json: Json = serde_json::from_str(json)
+        .map_err(|error| NativeError::new(format!("invalid json: {error}")).with_arg(0))?;
+    Value::try_from(json).map_err(|error| error.with_arg(0))
+}
+
+/// Loads `json_parse` and `json_string`, which convert between values and json text
+pub fn load_json<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let json_parse = NativeBuilder::new("json_parse")
+        .arg::<String>()
+        .build(|json| from_json::<Data>(&json));
+
+    let json_string = NativeBuilder::new("json_string")
+        .arg::<Value<Data>>()
+        .build(|value| to_json(&value).map_err(|error| error.with_arg(0)));
+
+    engine.init_const("json_parse", Value::Func(FuncValue::native(json_parse)));
+    engine.init_const("json_string", Value::Func(FuncValue::native(json_string)));
+}
@@ -0,0 +1,104 @@
+use std::{fmt::Display, sync::Arc};
+
+use indexmap::IndexMap;
+
+use super::{error::NativeError, Value};
+
+type Transform<Data> = Arc<dyn Fn(Value<Data>) -> Result<Value<Data>, NativeError> + Send + Sync>;
+
+enum Rule<Data> {
+    Rename(String),
+    Map(String, Transform<Data>),
+    Drop,
+}
+
+/// Maps globals saved by an old version of a script onto the globals of a newer one
+///
+/// Globals without a rule keep their name. Used with [`Engine::restore`](super::Engine::restore).
+pub struct Migration<Data> {
+    rules: IndexMap<String, Rule<Data>>,
+}
+
+impl<Data> Default for Migration<Data> {
+    fn default() -> Self {
+        Self {
+            rules: IndexMap::new(),
+        }
+    }
+}
+
+impl<Data> Migration<Data> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restores the saved global `old` into the global `new`
+    pub fn rename(mut self, old: impl Into<String>, new: impl Into<String>) -> Self {
+        self.rules.insert(old.into(), Rule::Rename(new.into()));
+        self
+    }
+
+    /// Restores the saved global `old` into the global `new`, converting its value with `transform`
+    pub fn map(
+        mut self,
+        old: impl Into<String>,
+        new: impl Into<String>,
+        transform: impl Fn(Value<Data>) -> Result<Value<Data>, NativeError> + Send + Sync + 'static,
+    ) -> Self {
+        let rule = Rule::Map(new.into(), Arc::new(transform));
+        self.rules.insert(old.into(), rule);
+        self
+    }
+
+    /// Discards the saved global `old`, for state the newer script no longer has
+    pub fn drop(mut self, old: impl Into<String>) -> Self {
+        self.rules.insert(old.into(), Rule::Drop);
+        self
+    }
+
+    /// Returns the new name and value for a saved global, or `None` if it is dropped
+    pub fn apply(
+        &self,
+        ident: &str,
+        value: Value<Data>,
+    ) -> Result<Option<(String, Value<Data>)>, MigrationError> {
+        match self.rules.get(ident) {
+            None => Ok(Some((ident.to_string(), value))),
+            Some(Rule::Drop) => Ok(None),
+            Some(Rule::Rename(new)) => Ok(Some((new.clone(), value))),
+            Some(Rule::Map(new, transform)) => match transform(value) {
+                Ok(value) => Ok(Some((new.clone(), value))),
+                Err(error) => Err(MigrationError::Failed {
+                    ident: ident.to_string(),
+                    error: Box::new(error),
+                }),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum MigrationError {
+    /// Saved globals that have no matching global in the engine
+    Unmapped(Vec<String>),
+    /// A transform failed to convert a saved global
+    Failed {
+        ident: String,
+        error: Box<NativeError>,
+    },
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Unmapped(idents) => {
+                write!(f, "saved globals have no match: {}", idents.join(", "))
+            }
+            MigrationError::Failed { ident, error } => {
+                write!(f, "failed to migrate '{ident}': {}", error.message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
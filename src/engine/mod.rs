@@ -2,14 +2,50 @@ mod engine;
 mod scope;
 
 pub mod builtin;
+pub mod cancel;
+pub mod capabilities;
+pub mod clock;
+pub mod diff;
 pub mod error;
+#[cfg(feature = "flamegraph")]
+pub mod flamegraph;
 pub mod func;
+pub mod hooks;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod migrate;
+pub mod native;
 pub mod ops;
+pub mod profile;
+#[cfg(feature = "prompt")]
+pub mod prompt;
+pub mod random;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod stream;
+pub mod trace;
+pub mod transfer;
+pub mod userdata;
 pub mod value;
 
 pub use engine::*;
 
 pub use builtin::load_builtins;
+pub use cancel::CancelToken;
+pub use capabilities::{Capability, EngineCapabilities};
+pub use clock::{Clock, SystemClock, VirtualClock};
+pub use diff::{value_diff, Diff, Snapshot};
+#[cfg(feature = "flamegraph")]
+pub use flamegraph::FlameRecorder;
 pub use func::{FuncValue, NativeFunc};
-pub use ops::OpManager;
-pub use value::Value;
+pub use hooks::{EngineHooks, PauseToken};
+pub use migrate::{Migration, MigrationError};
+pub use native::{FromValue, IntoValue, NativeBuilder};
+pub use ops::{BinaryOp, Coercion, CoercionPolicy, OpManager, UnaryOp};
+pub use profile::{FunctionStats, Profiler};
+pub use random::Rng;
+pub use stream::{load_sink, load_stream, SinkFlow};
+pub use trace::{TraceEvent, TraceOp};
+pub use transfer::{TransferError, TransferHook};
+pub use userdata::{UserData, WeakUserData};
+pub use value::{FloatFormat, Value};
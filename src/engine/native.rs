@@ -0,0 +1,342 @@
+use std::{marker::PhantomData, sync::Arc};
+
+use dashu::{float::DBig, integer::IBig};
+
+use crate::Engine;
+
+use super::{error::NativeError, FuncValue, NativeFunc, UserData, Value, WeakUserData};
+
+/// Converts a script [`Value`] into a rust type for use as a native function argument
+pub trait FromValue<Data>: Sized {
+    /// Describes the value types that are accepted, used in error messages
+    const EXPECTED: &'static str;
+
+    fn from_value(value: Value<Data>) -> Option<Self>;
+}
+
+/// Converts a rust type into a script [`Value`] when returned from a native function
+pub trait IntoValue<Data> {
+    fn into_value(self) -> Result<Value<Data>, NativeError>;
+}
+
+impl<Data> FromValue<Data> for Value<Data> {
+    const EXPECTED: &'static str = "any";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        Some(value)
+    }
+}
+
+impl<Data> FromValue<Data> for bool {
+    const EXPECTED: &'static str = "bool";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for IBig {
+    const EXPECTED: &'static str = "int";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Int(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for i64 {
+    const EXPECTED: &'static str = "int";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Int(v) => v.try_into().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for DBig {
+    const EXPECTED: &'static str = "float";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Int(v) => Some(DBig::from(v)),
+            Value::Float(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for f64 {
+    const EXPECTED: &'static str = "float";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Int(v) => Some(v.to_f64().value()),
+            Value::Float(v) => Some(v.to_f64().value()),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for String {
+    const EXPECTED: &'static str = "string";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::String(v) => Some(v.to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for Arc<str> {
+    const EXPECTED: &'static str = "string";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::String(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for FuncValue<Data> {
+    const EXPECTED: &'static str = "fn";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Func(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for UserData {
+    const EXPECTED: &'static str = "userdata";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Custom(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> FromValue<Data> for WeakUserData {
+    const EXPECTED: &'static str = "weak userdata";
+
+    fn from_value(value: Value<Data>) -> Option<Self> {
+        match value {
+            Value::Weak(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<Data> IntoValue<Data> for Value<Data> {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(self)
+    }
+}
+
+impl<Data> IntoValue<Data> for () {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::None)
+    }
+}
+
+impl<Data> IntoValue<Data> for bool {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::Bool(self))
+    }
+}
+
+impl<Data> IntoValue<Data> for IBig {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::Int(self))
+    }
+}
+
+impl<Data> IntoValue<Data> for i64 {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::Int(self.into()))
+    }
+}
+
+impl<Data> IntoValue<Data> for DBig {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::Float(self))
+    }
+}
+
+impl<Data> IntoValue<Data> for f64 {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        // the shortest round trip representation keeps values like 0.1 exact
+        match self.to_string().parse() {
+            Ok(value) => Ok(Value::Float(value)),
+            Err(_) => Err(format!("'{self}' is not a valid float").into()),
+        }
+    }
+}
+
+impl<Data> IntoValue<Data> for String {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::String(self.into()))
+    }
+}
+
+impl<Data> IntoValue<Data> for Arc<str> {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::String(self))
+    }
+}
+
+impl<Data> IntoValue<Data> for &str {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::String(self.into()))
+    }
+}
+
+impl<Data> IntoValue<Data> for FuncValue<Data> {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::Func(self))
+    }
+}
+
+impl<Data> IntoValue<Data> for UserData {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::Custom(self))
+    }
+}
+
+impl<Data> IntoValue<Data> for WeakUserData {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        Ok(Value::Weak(self))
+    }
+}
+
+impl<Data, T: IntoValue<Data>> IntoValue<Data> for Option<T> {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        match self {
+            Some(value) => value.into_value(),
+            None => Ok(Value::None),
+        }
+    }
+}
+
+impl<Data, T: IntoValue<Data>> IntoValue<Data> for Result<T, String> {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        self?.into_value()
+    }
+}
+
+impl<Data, T: IntoValue<Data>> IntoValue<Data> for Result<T, NativeError> {
+    fn into_value(self) -> Result<Value<Data>, NativeError> {
+        self?.into_value()
+    }
+}
+
+/// Builds a [`NativeFunc`] with typed arguments
+///
+/// Arguments are converted using [`FromValue`], and a descriptive error
+/// is returned to the script when a value has the wrong type.
+pub struct NativeBuilder<Data, Args> {
+    name: String,
+    _types: PhantomData<fn(Data) -> Args>,
+}
+
+impl<Data> NativeBuilder<Data, ()> {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            _types: PhantomData,
+        }
+    }
+}
+
+fn convert<Data, T: FromValue<Data>>(
+    name: &str,
+    index: usize,
+    value: Value<Data>,
+) -> Result<T, NativeError> {
+    let found = value.get_type();
+    match T::from_value(value) {
+        Some(value) => Ok(value),
+        None => Err(NativeError::new(format!(
+            "'{name}' expected '{}' for parameter {}, found '{found}'",
+            T::EXPECTED,
+            index + 1,
+        ))
+        .with_arg(index)),
+    }
+}
+
+macro_rules! impl_arg {
+    ($($ty:ident),*) => {
+        impl<Data, $($ty),*> NativeBuilder<Data, ($($ty,)*)> {
+            pub fn arg<Next: FromValue<Data>>(self) -> NativeBuilder<Data, ($($ty,)* Next,)> {
+                NativeBuilder {
+                    name: self.name,
+                    _types: PhantomData,
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_build {
+    ($($ty:ident $var:ident),*) => {
+        impl<Data: 'static, $($ty: FromValue<Data> + 'static),*> NativeBuilder<Data, ($($ty,)*)> {
+            pub fn build<Out: IntoValue<Data>>(
+                self,
+                func: impl Fn($($ty),*) -> Out + Send + Sync + 'static,
+            ) -> NativeFunc<Data> {
+                self.build_with_engine(move |_, $($var),*| func($($var),*))
+            }
+
+            /// Builds the native function, also giving it access to the calling [`Engine`]
+            #[allow(unused_mut, unused_variables)]
+            pub fn build_with_engine<Out: IntoValue<Data>>(
+                self,
+                func: impl Fn(&mut Engine<Data>, $($ty),*) -> Out + Send + Sync + 'static,
+            ) -> NativeFunc<Data> {
+                let name = self.name;
+                let count = <[&str]>::len(&[$(stringify!($var)),*]);
+                NativeFunc::new(count, move |engine, values| {
+                    let mut values = values.into_iter().enumerate();
+                    $(
+                        let $var = match values.next() {
+                            Some((index, value)) => convert::<Data, $ty>(&name, index, value)?,
+                            None => {
+                                let message = format!("'{name}' expects {count} parameters");
+                                return Err(NativeError::new(message));
+                            }
+                        };
+                    )*
+                    func(engine, $($var),*).into_value()
+                })
+            }
+        }
+    };
+}
+
+impl_arg!();
+impl_arg!(A);
+impl_arg!(A, B);
+impl_arg!(A, B, C);
+impl_arg!(A, B, C, D);
+impl_arg!(A, B, C, D, E);
+
+impl_build!();
+impl_build!(A a);
+impl_build!(A a, B b);
+impl_build!(A a, B b, C c);
+impl_build!(A a, B b, C c, D d);
+impl_build!(A a, B b, C c, D d, E e);
+impl_build!(A a, B b, C c, D d, E e, F f);
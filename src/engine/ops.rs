@@ -1,16 +1,141 @@
-use std::marker::PhantomData;
+use std::{marker::PhantomData, sync::Arc};
 
-use dashu::{base::Sign, float::DBig};
+use dashu::{base::Sign, float::DBig, integer::IBig};
 
-use super::{error::RunError, Value};
+use super::{error::RunError, value::ValueType, Value};
 
-pub struct OpManager<Data: Clone> {
+fn is_zero<Data>(v: &Value<Data>) -> bool {
+    match v {
+        Value::Int(v) => *v == IBig::ZERO,
+        Value::Float(v) => *v == DBig::ZERO,
+        _ => false,
+    }
+}
+
+fn is_negative<Data>(v: &Value<Data>) -> bool {
+    match v {
+        Value::Int(v) => *v < IBig::ZERO,
+        Value::Float(v) => *v < DBig::ZERO,
+        _ => false,
+    }
+}
+
+/// An operator that takes a single value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Neg => "-",
+            Self::Not => "!",
+        }
+    }
+}
+
+/// An operator that takes two values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    And,
+    Or,
+    Eq,
+    Lt,
+    Gt,
+    NEq,
+    LtEq,
+    GtEq,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::Mod => "%",
+            Self::Pow => "**",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Eq => "==",
+            Self::Lt => "<",
+            Self::Gt => ">",
+            Self::NEq => "!=",
+            Self::LtEq => "<=",
+            Self::GtEq => ">=",
+        }
+    }
+}
+
+/// An implicit conversion that a binary operator performs to combine its operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coercion {
+    /// An int is widened to a float to combine it with a float
+    IntToFloat,
+    /// A value of this type is turned into text to append it to a string
+    Concat(ValueType),
+    /// A string is repeated by a count of this type, either an int or a bool
+    Repeat(ValueType),
+    /// Bools are ordered with false before true
+    BoolOrder,
+}
+
+impl Coercion {
+    /// Returns the conversion `op` needs for the given operands, if any
+    pub fn find<Data>(op: BinaryOp, v1: &Value<Data>, v2: &Value<Data>) -> Option<Self> {
+        use BinaryOp::*;
+        match (op, v1, v2) {
+            (And | Or, _, _) => None,
+            (_, Value::Int(_), Value::Float(_)) | (_, Value::Float(_), Value::Int(_)) => {
+                Some(Self::IntToFloat)
+            }
+            (Add, Value::String(_), Value::Bool(_) | Value::Int(_) | Value::Float(_)) => {
+                Some(Self::Concat(v2.get_type()))
+            }
+            (Mul, Value::String(_), Value::Bool(_) | Value::Int(_)) => {
+                Some(Self::Repeat(v2.get_type()))
+            }
+            (Lt | Gt | LtEq | GtEq, Value::Bool(_), Value::Bool(_)) => Some(Self::BoolOrder),
+            _ => None,
+        }
+    }
+}
+
+/// Decides which implicit conversions binary operators may perform
+///
+/// Operators that need a conversion the policy refuses fail with
+/// [`RunError::InvalidBinary`], as if they did not support those types at all.
+/// Closures taking the operator and the conversion can be used as policies.
+pub trait CoercionPolicy: Send + Sync {
+    fn allows(&self, op: BinaryOp, coercion: Coercion) -> bool;
+}
+
+impl<F: Fn(BinaryOp, Coercion) -> bool + Send + Sync> CoercionPolicy for F {
+    fn allows(&self, op: BinaryOp, coercion: Coercion) -> bool {
+        self(op, coercion)
+    }
+}
+
+pub struct OpManager<Data> {
+    policy: Option<Arc<dyn CoercionPolicy>>,
+    float_precision: Option<usize>,
     _data: PhantomData<*const Data>,
 }
 
 impl<Data: Clone> Default for OpManager<Data> {
     fn default() -> Self {
         Self {
+            policy: None,
+            float_precision: None,
             _data: Default::default(),
         }
     }
@@ -21,6 +146,106 @@ impl<Data: Clone> OpManager<Data> {
         Self::default()
     }
 
+    /// Restricts the implicit conversions performed by [`binary`](OpManager::binary)
+    ///
+    /// Without a policy, every conversion is allowed.
+    pub fn set_policy(&mut self, policy: Option<Arc<dyn CoercionPolicy>>) {
+        self.policy = policy;
+    }
+
+    pub fn policy(&self) -> Option<&Arc<dyn CoercionPolicy>> {
+        self.policy.as_ref()
+    }
+
+    /// Fixes the number of significant digits of every float that
+    /// [`unary`](OpManager::unary) and [`binary`](OpManager::binary) take and return
+    ///
+    /// Without a fixed precision, results keep the precision of their operands,
+    /// so `1.0 / 3.0` is `0.33` while `1.000 / 3` is `0.3333`.
+    pub fn set_float_precision(&mut self, digits: Option<usize>) {
+        self.float_precision = digits.filter(|digits| *digits > 0);
+    }
+
+    pub fn float_precision(&self) -> Option<usize> {
+        self.float_precision
+    }
+
+    /// Converts an int operand to a float with the fixed precision, if there is one
+    fn float(&self, v: IBig) -> DBig {
+        let v = DBig::from(v);
+        match self.float_precision {
+            Some(digits) => v.with_precision(digits).value(),
+            None => v,
+        }
+    }
+
+    /// Rounds `v` to the fixed float precision, if there is one
+    pub fn round(&self, v: Value<Data>) -> Value<Data> {
+        match (self.float_precision, v) {
+            (Some(digits), Value::Float(v)) => Value::Float(v.with_precision(digits).value()),
+            (_, v) => v,
+        }
+    }
+
+    /// Applies `op` to `v` with the same semantics as the script operator
+    pub fn unary(
+        &self,
+        op: UnaryOp,
+        v: Value<Data>,
+        data: &Data,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        let v = self.round(v);
+        let value = match op {
+            UnaryOp::Neg => self.neg(v, data),
+            UnaryOp::Not => self.not(v, data),
+        }?;
+        Ok(self.round(value))
+    }
+
+    /// Applies `op` to `v1` and `v2` with the same semantics as the script operator
+    ///
+    /// Unlike the methods for each operator, this checks the coercion policy
+    /// and applies the fixed [float precision](OpManager::set_float_precision).
+    pub fn binary(
+        &self,
+        op: BinaryOp,
+        v1: Value<Data>,
+        v2: Value<Data>,
+        data: &Data,
+    ) -> Result<Value<Data>, RunError<Data>> {
+        if let Some(policy) = &self.policy {
+            if let Some(coercion) = Coercion::find(op, &v1, &v2) {
+                if !policy.allows(op, coercion) {
+                    return Err(RunError::InvalidBinary {
+                        op: op.symbol().to_string(),
+                        vtype1: v1.get_type(),
+                        vtype2: v2.get_type(),
+                        data: data.clone(),
+                    });
+                }
+            }
+        }
+
+        let (v1, v2) = (self.round(v1), self.round(v2));
+        let value = match op {
+            BinaryOp::Add => self.add(v1, v2, data),
+            BinaryOp::Sub => self.sub(v1, v2, data),
+            BinaryOp::Mul => self.mul(v1, v2, data),
+            BinaryOp::Div => self.div(v1, v2, data),
+            BinaryOp::Mod => self.modulo(v1, v2, data),
+            BinaryOp::Pow => self.pow(v1, v2, data),
+            BinaryOp::And => self.and(v1, v2, data),
+            BinaryOp::Or => self.or(v1, v2, data),
+            BinaryOp::Eq => self.eq(v1, v2, data),
+            BinaryOp::Lt => self.lt(v1, v2, data),
+            BinaryOp::Gt => self.gt(v1, v2, data),
+            BinaryOp::NEq => self.neq(v1, v2, data),
+            BinaryOp::LtEq => self.lteq(v1, v2, data),
+            BinaryOp::GtEq => self.gteq(v1, v2, data),
+        }?;
+        Ok(self.round(value))
+    }
+
     pub fn not(&self, v: Value<Data>, data: &Data) -> Result<Value<Data>, RunError<Data>> {
         match v {
             Value::Bool(v) => Ok(Value::Bool(!v)),
@@ -60,10 +285,10 @@ impl<Data: Clone> OpManager<Data> {
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Float(v1 + v2)),
 
             // STRING
-            (Value::String(v1), Value::String(v2)) => Ok(Value::String(format!("{v1}{v2}"))),
-            (Value::String(v1), Value::Bool(v2)) => Ok(Value::String(format!("{v1}{v2}"))),
-            (Value::String(v1), Value::Int(v2)) => Ok(Value::String(format!("{v1}{v2}"))),
-            (Value::String(v1), Value::Float(v2)) => Ok(Value::String(format!("{v1}{v2}"))),
+            (Value::String(v1), Value::String(v2)) => Ok(Value::String(format!("{v1}{v2}").into())),
+            (Value::String(v1), Value::Bool(v2)) => Ok(Value::String(format!("{v1}{v2}").into())),
+            (Value::String(v1), Value::Int(v2)) => Ok(Value::String(format!("{v1}{v2}").into())),
+            (Value::String(v1), Value::Float(v2)) => Ok(Value::String(format!("{v1}{v2}").into())),
 
             // FAIL
             (v1, v2) => Err(RunError::InvalidBinary {
@@ -126,7 +351,7 @@ impl<Data: Clone> OpManager<Data> {
                     return Ok(Value::String("".into()));
                 }
                 match TryInto::<isize>::try_into(ubig).map(|i| i as usize) {
-                    Ok(count) => Ok(Value::String(v1.repeat(count))),
+                    Ok(count) => Ok(Value::String(v1.repeat(count).into())),
                     Err(_) => Err(RunError::StringAllocError { data: data.clone() }),
                 }
             }
@@ -148,8 +373,13 @@ impl<Data: Clone> OpManager<Data> {
         data: &Data,
     ) -> Result<Value<Data>, RunError<Data>> {
         match (v1, v2) {
+            // ZERO
+            (Value::Int(_) | Value::Float(_), v2) if is_zero(&v2) => {
+                Err(RunError::DivisionByZero { data: data.clone() })
+            }
+
             // INT
-            (Value::Int(v1), Value::Int(v2)) => Ok(Value::Float(DBig::from(v1) / v2)),
+            (Value::Int(v1), Value::Int(v2)) => Ok(Value::Float(self.float(v1) / v2)),
             (Value::Int(v1), Value::Float(v2)) => Ok(Value::Float(v1 / v2)),
 
             // FLOAT
@@ -173,12 +403,17 @@ impl<Data: Clone> OpManager<Data> {
         data: &Data,
     ) -> Result<Value<Data>, RunError<Data>> {
         match (v1, v2) {
+            // ZERO
+            (Value::Int(_) | Value::Float(_), v2) if is_zero(&v2) => {
+                Err(RunError::DivisionByZero { data: data.clone() })
+            }
+
             // INT
             (Value::Int(v1), Value::Int(v2)) => Ok(Value::Int(v1 % v2)),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Float(DBig::from(v1) % v2)),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Float(self.float(v1) % v2)),
 
             // FLOAT
-            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Float(v1 % DBig::from(v2))),
+            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Float(v1 % self.float(v2))),
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Float(v1 % v2)),
 
             // FAIL
@@ -198,9 +433,14 @@ impl<Data: Clone> OpManager<Data> {
         data: &Data,
     ) -> Result<Value<Data>, RunError<Data>> {
         match (v1, v2) {
+            // ZERO
+            (v1, v2) if is_zero(&v1) && is_negative(&v2) => {
+                Err(RunError::DivisionByZero { data: data.clone() })
+            }
+
             // INT
-            (Value::Int(v1), Value::Int(v2)) => Ok(Value::Float(DBig::from(v1).powi(v2))),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Float(DBig::from(v1).powf(&v2))),
+            (Value::Int(v1), Value::Int(v2)) => Ok(Value::Float(self.float(v1).powi(v2))),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Float(self.float(v1).powf(&v2))),
 
             // FLOAT
             (Value::Float(v1), Value::Int(v2)) => Ok(Value::Float(v1.powi(v2))),
@@ -225,15 +465,19 @@ impl<Data: Clone> OpManager<Data> {
         match (v1, v2) {
             // INT
             (Value::Int(v1), Value::Int(v2)) => Ok(Value::Bool(v1 == v2)),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(DBig::from(v1) == v2)),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(self.float(v1) == v2)),
 
             // FLOAT
-            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 == DBig::from(v2))),
+            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 == self.float(v2))),
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Bool(v1 == v2)),
 
             // STRING
             (Value::String(v1), Value::String(v2)) => Ok(Value::Bool(v1 == v2)),
 
+            // USERDATA
+            (Value::Custom(v1), Value::Custom(v2)) => Ok(Value::Bool(v1.ptr_eq(&v2))),
+            (Value::Weak(v1), Value::Weak(v2)) => Ok(Value::Bool(v1.ptr_eq(&v2))),
+
             // BOOLEAN
             (Value::Bool(v1), Value::Bool(v2)) => Ok(Value::Bool(v1 == v2)),
 
@@ -256,10 +500,10 @@ impl<Data: Clone> OpManager<Data> {
         match (v1, v2) {
             // INT
             (Value::Int(v1), Value::Int(v2)) => Ok(Value::Bool(v1 < v2)),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(DBig::from(v1) < v2)),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(self.float(v1) < v2)),
 
             // FLOAT
-            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 < DBig::from(v2))),
+            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 < self.float(v2))),
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Bool(v1 < v2)),
 
             // STRING
@@ -287,10 +531,10 @@ impl<Data: Clone> OpManager<Data> {
         match (v1, v2) {
             // INT
             (Value::Int(v1), Value::Int(v2)) => Ok(Value::Bool(v1 > v2)),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(DBig::from(v1) > v2)),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(self.float(v1) > v2)),
 
             // FLOAT
-            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 > DBig::from(v2))),
+            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 > self.float(v2))),
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Bool(v1 > v2)),
 
             // STRING
@@ -318,15 +562,19 @@ impl<Data: Clone> OpManager<Data> {
         match (v1, v2) {
             // INT
             (Value::Int(v1), Value::Int(v2)) => Ok(Value::Bool(v1 != v2)),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(DBig::from(v1) != v2)),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(self.float(v1) != v2)),
 
             // FLOAT
-            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 != DBig::from(v2))),
+            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 != self.float(v2))),
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Bool(v1 != v2)),
 
             // STRING
             (Value::String(v1), Value::String(v2)) => Ok(Value::Bool(v1 != v2)),
 
+            // USERDATA
+            (Value::Custom(v1), Value::Custom(v2)) => Ok(Value::Bool(!v1.ptr_eq(&v2))),
+            (Value::Weak(v1), Value::Weak(v2)) => Ok(Value::Bool(!v1.ptr_eq(&v2))),
+
             // BOOLEAN
             (Value::Bool(v1), Value::Bool(v2)) => Ok(Value::Bool(v1 != v2)),
 
@@ -349,10 +597,10 @@ impl<Data: Clone> OpManager<Data> {
         match (v1, v2) {
             // INT
             (Value::Int(v1), Value::Int(v2)) => Ok(Value::Bool(v1 <= v2)),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(DBig::from(v1) <= v2)),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(self.float(v1) <= v2)),
 
             // FLOAT
-            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 <= DBig::from(v2))),
+            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 <= self.float(v2))),
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Bool(v1 <= v2)),
 
             // STRING
@@ -380,10 +628,10 @@ impl<Data: Clone> OpManager<Data> {
         match (v1, v2) {
             // INT
             (Value::Int(v1), Value::Int(v2)) => Ok(Value::Bool(v1 >= v2)),
-            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(DBig::from(v1) >= v2)),
+            (Value::Int(v1), Value::Float(v2)) => Ok(Value::Bool(self.float(v1) >= v2)),
 
             // FLOAT
-            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 >= DBig::from(v2))),
+            (Value::Float(v1), Value::Int(v2)) => Ok(Value::Bool(v1 >= self.float(v2))),
             (Value::Float(v1), Value::Float(v2)) => Ok(Value::Bool(v1 >= v2)),
 
             // STRING
@@ -0,0 +1,115 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use indexmap::IndexMap;
+
+use crate::symbol::{AsSymbol, Symbol};
+
+/// Call count and time spent in one function, see [`Profiler`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    /// Time from entering the function until it returned, including the calls it made
+    pub total: Duration,
+}
+
+/// Counts the calls to each function and the time spent in them while an engine runs
+///
+/// Functions are keyed by the name they were called with, so one function value stored
+/// under two names is counted twice. Recursive calls are counted, but their time is only
+/// charged once to the outermost call so the totals never exceed the time the script ran.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    functions: IndexMap<Symbol, FunctionStats>,
+    open: Vec<(Symbol, Instant)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts timing a call to `ident`
+    pub fn enter(&mut self, ident: Symbol) {
+        self.functions.entry(ident.clone()).or_default().calls += 1;
+        self.open.push((ident, Instant::now()));
+    }
+
+    /// Stops timing the innermost call
+    pub fn exit(&mut self) {
+        let Some((ident, start)) = self.open.pop() else {
+            return;
+        };
+
+        // an outer call to the same function already covers this time
+        if self.open.iter().any(|(open, _)| *open == ident) {
+            return;
+        }
+
+        if let Some(stats) = self.functions.get_mut(&ident) {
+            stats.total += start.elapsed();
+        }
+    }
+
+    /// Stops timing calls that are still running, charging them up to now
+    pub fn finish(&mut self) {
+        self.unwind(0);
+    }
+
+    /// Returns the number of calls that are still being timed
+    pub(crate) fn depth(&self) -> usize {
+        self.open.len()
+    }
+
+    /// Stops timing calls until only `depth` are still running
+    pub(crate) fn unwind(&mut self, depth: usize) {
+        while self.open.len() > depth {
+            self.exit();
+        }
+    }
+
+    /// Returns the stats for `ident`, if it was called
+    pub fn stats(&self, ident: impl AsSymbol) -> Option<FunctionStats> {
+        self.functions.get(&ident.find_symbol()?).copied()
+    }
+
+    /// Returns each called function with its stats, slowest first
+    pub fn functions(&self) -> Vec<(Symbol, FunctionStats)> {
+        let mut functions: Vec<_> = self
+            .functions
+            .iter()
+            .map(|(ident, stats)| (ident.clone(), *stats))
+            .collect();
+        functions.sort_by(|(_, a), (_, b)| b.total.cmp(&a.total).then(b.calls.cmp(&a.calls)));
+        functions
+    }
+
+    /// Writes the stats as aligned `function calls total` columns, slowest first
+    pub fn write_report(&self, mut writer: impl Write) -> io::Result<()> {
+        let functions = self.functions();
+        let width = functions
+            .iter()
+            .map(|(ident, _)| ident.len())
+            .chain(["function".len()])
+            .max()
+            .unwrap_or_default();
+
+        writeln!(
+            writer,
+            "{:<width$}  {:>10}  {:>12}",
+            "function", "calls", "total ms"
+        )?;
+        for (ident, stats) in functions {
+            let millis = stats.total.as_secs_f64() * 1000.0;
+            writeln!(
+                writer,
+                "{:<width$}  {:>10}  {millis:>12.3}",
+                ident.as_str(),
+                stats.calls
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+//! Interactive prompts for command line scripts
+//!
+//! Prompts read and print through the engine's input source and print sink,
+//! so hosts that replace them also control every prompt a script shows.
+
+use std::io::{self, stdout, Write};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    terminal,
+};
+
+use super::{
+    builtin::io_error, error::NativeError, Capability, Engine, FuncValue, NativeBuilder, Value,
+};
+
+/// Reads a line from the terminal without echoing it
+pub(crate) fn read_hidden(prompt: &str) -> io::Result<Option<String>> {
+    let mut stdout = stdout().lock();
+    write!(stdout, "{prompt}")?;
+    stdout.flush()?;
+
+    terminal::enable_raw_mode()?;
+    let line = read_keys();
+    terminal::disable_raw_mode()?;
+
+    // the enter key was not echoed either
+    writeln!(stdout)?;
+    line
+}
+
+fn read_keys() -> io::Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind == KeyEventKind::Release {
+            continue;
+        }
+
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Enter => return Ok(Some(line)),
+            KeyCode::Backspace => {
+                line.pop();
+            }
+            KeyCode::Char('c') if control => {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "interrupted"));
+            }
+            KeyCode::Char('d') if control && line.is_empty() => return Ok(None),
+            KeyCode::Char(c) => line.push(c),
+            _ => (),
+        }
+    }
+}
+
+/// Reads an answer for a prompt, failing once the input has ended
+fn answer<Data: Clone>(engine: &mut Engine<Data>, prompt: &str) -> Result<String, NativeError> {
+    match engine.read_line(prompt) {
+        Ok(Some(line)) => Ok(line),
+        Ok(None) => Err(NativeError::new("input ended before an answer was given")),
+        Err(error) => Err(io_error("failed to read input", error)),
+    }
+}
+
+fn print<Data: Clone>(engine: &mut Engine<Data>, line: &str) -> Result<(), NativeError> {
+    engine
+        .print(line)
+        .map_err(|error| io_error("failed to print", error))
+}
+
+/// Asks a yes or no question until one of them is answered
+fn confirm<Data: Clone>(engine: &mut Engine<Data>, question: String) -> Result<bool, NativeError> {
+    let prompt = format!("{question} [y/n] ");
+    while !engine.is_cancelled() {
+        match answer(engine, &prompt)?.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => print(engine, "please answer 'y' or 'n'")?,
+        }
+    }
+    Err(NativeError::new("cancelled while waiting for an answer"))
+}
+
+/// Lists the comma separated `options` and returns the one that was picked
+///
+/// Options can be picked by their number or by typing them out.
+fn select<Data: Clone>(
+    engine: &mut Engine<Data>,
+    question: String,
+    options: String,
+) -> Result<String, NativeError> {
+    let options: Vec<&str> = options
+        .split(',')
+        .map(str::trim)
+        .filter(|option| !option.is_empty())
+        .collect();
+    if options.is_empty() {
+        return Err(NativeError::new("there are no options to select from")
+            .with_help("separate the options with commas, like 'dev, prod'")
+            .with_arg(1));
+    }
+
+    print(engine, &question)?;
+    for (index, option) in options.iter().enumerate() {
+        print(engine, &format!("  {}) {option}", index + 1))?;
+    }
+
+    while !engine.is_cancelled() {
+        let line = answer(engine, "> ")?;
+        let line = line.trim();
+        let picked = match line.parse::<usize>() {
+            Ok(number) => options.get(number.wrapping_sub(1)),
+            Err(_) => options.iter().find(|option| **option == line),
+        };
+
+        match picked {
+            Some(option) => return Ok(option.to_string()),
+            None => print(engine, &format!("please pick 1 to {}", options.len()))?,
+        }
+    }
+    Err(NativeError::new("cancelled while waiting for an answer"))
+}
+
+/// Reads a password or other secret without showing it
+fn password<Data: Clone>(engine: &mut Engine<Data>, prompt: String) -> Result<String, NativeError> {
+    match engine.read_secret(&prompt) {
+        Ok(Some(line)) => Ok(line),
+        Ok(None) => Err(NativeError::new("input ended before an answer was given")),
+        Err(error) => Err(io_error("failed to read input", error)),
+    }
+}
+
+/// Loads the `confirm`, `select` and `password` natives
+pub fn load_prompt<Data: Clone + 'static>(engine: &mut Engine<Data>) {
+    let confirm = NativeBuilder::new("confirm")
+        .arg::<String>()
+        .build_with_engine(confirm);
+
+    let select = NativeBuilder::new("select")
+        .arg::<String>()
+        .arg::<String>()
+        .build_with_engine(select);
+
+    let password = NativeBuilder::new("password")
+        .arg::<String>()
+        .build_with_engine(password);
+
+    for (ident, native) in [
+        ("confirm", confirm),
+        ("select", select),
+        ("password", password),
+    ] {
+        let native = native.requires(Capability::Io);
+        engine.init_const(ident, Value::Func(FuncValue::native(native)));
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A small seedable random number generator using the splitmix64 algorithm
+///
+/// The sequence for a seed is part of the engine's behavior, so seeded
+/// scripts produce the same numbers on every platform and version.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Default for Rng {
+    /// Creates a generator seeded from the system clock
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::new(nanos)
+    }
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a float in the range `0.0..1.0`
+    pub fn next_f64(&mut self) -> f64 {
+        // the top 53 bits fill the float mantissa exactly
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns an integer in the range `low..=high`, or `None` if the range is empty
+    pub fn range(&mut self, low: i64, high: i64) -> Option<i64> {
+        if low > high {
+            return None;
+        }
+
+        let span = high.abs_diff(low);
+        if span == u64::MAX {
+            return Some(self.next_u64() as i64);
+        }
+
+        // reject values from the incomplete final block so every value is equally likely
+        let count = span + 1;
+        let zone = u64::MAX - u64::MAX % count;
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return Some(low.wrapping_add((value % count) as i64));
+            }
+        }
+    }
+}
@@ -6,11 +6,13 @@ use std::{
 
 use indexmap::IndexMap;
 
+use crate::symbol::{AsSymbol, Symbol};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Handle(usize);
 
 pub struct ShadowStore<T> {
-    values: IndexMap<String, Vec<T>>,
+    values: IndexMap<Symbol, Vec<T>>,
 }
 
 impl<V> Default for ShadowStore<V> {
@@ -40,12 +42,21 @@ impl<T> ShadowStore<T> {
         Self::default()
     }
 
-    pub fn get(&self, key: impl AsRef<str>) -> Option<&T> {
-        self.values.get(key.as_ref())?.last()
+    pub fn get(&self, key: impl AsSymbol) -> Option<&T> {
+        self.values.get(&key.find_symbol()?)?.last()
+    }
+
+    pub fn get_mut(&mut self, key: impl AsSymbol) -> Option<&mut T> {
+        self.values.get_mut(&key.find_symbol()?)?.last_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.values.values().flatten()
     }
 
-    pub fn get_mut(&mut self, key: impl AsRef<str>) -> Option<&mut T> {
-        self.values.get_mut(key.as_ref())?.last_mut()
+    /// Iterates over the visible value for each key, in the order keys were first inserted
+    pub fn entries(&self) -> impl Iterator<Item = (&Symbol, &T)> {
+        (self.values.iter()).filter_map(|(key, values)| Some((key, values.last()?)))
     }
 
     pub fn drop_one(&mut self, handle: Handle) {
@@ -54,9 +65,9 @@ impl<T> ShadowStore<T> {
         }
     }
 
-    pub fn insert(&mut self, key: impl Into<String>, value: T) -> Handle {
+    pub fn insert(&mut self, key: impl AsSymbol, value: T) -> Handle {
         use indexmap::map::Entry as E;
-        match self.values.entry(key.into()) {
+        match self.values.entry(key.to_symbol()) {
             E::Occupied(e) => {
                 let handle = Handle(e.index());
                 e.into_mut().push(value);
@@ -71,9 +82,17 @@ impl<T> ShadowStore<T> {
     }
 }
 
+/// How many scopes and stashes a [`Scope`] has open, see [`Scope::depth`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScopeDepth {
+    stash: usize,
+    scopes: usize,
+}
+
 pub struct Scope<T> {
     values: ShadowStore<T>,
-    stash: Vec<ShadowStore<T>>,
+    /// Stashed values, with the number of scopes that were open when they were stashed
+    stash: Vec<(ShadowStore<T>, usize)>,
     scopes: Vec<Vec<Handle>>,
 }
 
@@ -116,27 +135,66 @@ impl<T> Scope<T> {
 
     pub fn stash(&mut self) {
         let stash_values = replace(&mut self.values, ShadowStore::new());
-        self.stash.push(stash_values);
+        self.stash.push((stash_values, self.scopes.len()));
         self.push_scope();
     }
 
     pub fn unstash(&mut self) {
         self.pop_scope();
-        if let Some(stash_values) = self.stash.pop() {
+        if let Some((stash_values, _)) = self.stash.pop() {
+            self.values = stash_values;
+        }
+    }
+
+    pub fn depth(&self) -> ScopeDepth {
+        ScopeDepth {
+            stash: self.stash.len(),
+            scopes: self.scopes.len(),
+        }
+    }
+
+    /// Closes every scope and stash opened since `depth` was taken
+    ///
+    /// Used to recover when evaluation stops without closing the scopes it opened.
+    pub fn restore(&mut self, depth: ScopeDepth) {
+        while self.stash.len() > depth.stash {
+            let Some((stash_values, scopes)) = self.stash.pop() else {
+                break;
+            };
+            // scopes opened after the stash only hold values that are dropped here
+            self.scopes.truncate(scopes);
             self.values = stash_values;
         }
+
+        while self.scopes.len() > depth.scopes {
+            self.pop_scope();
+        }
     }
 
-    pub fn get(&self, ident: impl AsRef<str>) -> Option<&T> {
+    pub fn get(&self, ident: impl AsSymbol) -> Option<&T> {
         self.values.get(ident)
     }
 
-    pub fn get_mut(&mut self, ident: impl AsRef<str>) -> Option<&mut T> {
+    /// Iterates over every stored value, including shadowed and stashed values
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let stashed = self.stash.iter().flat_map(|(store, _)| store.iter());
+        self.values.iter().chain(stashed)
+    }
+
+    pub fn get_mut(&mut self, ident: impl AsSymbol) -> Option<&mut T> {
         self.values.get_mut(ident)
     }
 
-    pub fn init(&mut self, ident: impl Into<String>, value: T) {
-        let handle = self.values.insert(ident.into(), value);
+    /// Iterates over every visible identifier and its value
+    ///
+    /// Identifiers are ordered by when they were first defined, so the
+    /// order is the same on every run no matter how values are hashed.
+    pub fn entries(&self) -> impl Iterator<Item = (&Symbol, &T)> {
+        self.values.entries()
+    }
+
+    pub fn init(&mut self, ident: impl AsSymbol, value: T) {
+        let handle = self.values.insert(ident, value);
         let Some(scope) = self.scopes.last_mut() else {
             let scope = vec![handle];
             self.scopes.push(scope);
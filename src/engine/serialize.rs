@@ -0,0 +1,121 @@
+use std::fmt;
+
+use dashu::{float::DBig, integer::IBig};
+use serde::{
+    de::{self, Visitor},
+    ser, Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{IntoValue, Value};
+
+/// Serializes none, bools, numbers and strings
+///
+/// Floats are written as `f64` and integers as the smallest native integer that fits,
+/// so numbers outside those ranges fail to serialize instead of silently losing precision.
+/// A float fits if it deserializes back to the same value, which is true for `0.1`
+/// but not for `sqrt(2)`, since that has more digits than an `f64` holds.
+/// Functions and user data cannot be serialized.
+impl<Data> Serialize for Value<Data> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::None => serializer.serialize_none(),
+            Value::Bool(v) => serializer.serialize_bool(*v),
+            Value::Int(v) => {
+                if let Ok(v) = i64::try_from(v) {
+                    serializer.serialize_i64(v)
+                } else if let Ok(v) = u64::try_from(v) {
+                    serializer.serialize_u64(v)
+                } else {
+                    Err(ser::Error::custom(format!(
+                        "int '{v}' is too large to serialize"
+                    )))
+                }
+            }
+            Value::Float(v) => match exact_f64(v) {
+                Some(v) => serializer.serialize_f64(v),
+                None => Err(ser::Error::custom(format!(
+                    "float '{v}' cannot be serialized without losing precision"
+                ))),
+            },
+            Value::String(v) => serializer.serialize_str(v),
+            value => Err(ser::Error::custom(format!(
+                "'{}' values cannot be serialized",
+                value.get_type()
+            ))),
+        }
+    }
+}
+
+/// Converts `v` to an `f64`, if reading that back gives exactly `v` again
+///
+/// Floats are read back from the shortest representation of the `f64`, like `0.1`.
+pub(super) fn exact_f64(v: &DBig) -> Option<f64> {
+    let float = v.to_f64().value();
+    match float.to_string().parse::<DBig>() {
+        Ok(parsed) if parsed == *v => Some(float),
+        _ => None,
+    }
+}
+
+/// Deserializes none, bools, numbers and strings
+///
+/// Sequences and maps are rejected, since there are no values to represent them yet.
+impl<'de, Data> Deserialize<'de> for Value<Data> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor(std::marker::PhantomData))
+    }
+}
+
+struct ValueVisitor<Data>(std::marker::PhantomData<Data>);
+
+impl<'de, Data> Visitor<'de> for ValueVisitor<Data> {
+    type Value = Value<Data>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "none, a bool, a number or a string")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Value::None)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Value::None)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(Value::Bool(v))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(Value::Int(IBig::from(v)))
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<Self::Value, E> {
+        Ok(Value::Int(IBig::from(v)))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(Value::Int(IBig::from(v)))
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<Self::Value, E> {
+        Ok(Value::Int(IBig::from(v)))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+        v.into_value().map_err(|error| E::custom(error.message))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+        Ok(Value::String(v.into()))
+    }
+}
@@ -0,0 +1,93 @@
+//! Host data streamed into and out of scripts one item at a time
+//!
+//! There are no list values or loops in the language, so a stream is exposed as a native
+//! that returns the next item each time it is called, and none once the items run out.
+//! Scripts walk it with a tail recursive function, which runs in constant stack space:
+//!
+//! ```text
+//! static total = fn(sum, record): (type(record) == 'none') ? sum : total(sum + record, records())
+//! total(0, records())
+//! ```
+//!
+//! Sinks go the other way, handing each item a script pushes to the host,
+//! which answers whether it wants any more:
+//!
+//! ```text
+//! static produce = fn(n): results(n * n) ? produce(n + 1) : n
+//! produce(0)
+//! ```
+
+use std::sync::Mutex;
+
+use super::{Engine, FuncValue, IntoValue, NativeBuilder, Value};
+
+/// What a sink wants after taking an item, see [`load_sink`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SinkFlow {
+    /// Asks the script for more items
+    Continue,
+    /// Tells the script to stop producing items
+    ///
+    /// The sink is not called again, so later pushes are dropped.
+    Stop,
+}
+
+/// Defines `ident` as a native that pulls the next item from `items` on every call
+///
+/// Items are only converted to values as they are pulled, so the iterator can page
+/// through a dataset far larger than memory. Iterators that can yield none themselves
+/// should wrap their items, since a script cannot tell that apart from the end.
+pub fn load_stream<Data, T>(
+    engine: &mut Engine<Data>,
+    ident: &str,
+    items: impl Iterator<Item = T> + Send + 'static,
+) where
+    Data: Clone + 'static,
+    T: IntoValue<Data>,
+{
+    let items = Mutex::new(items.fuse());
+    let stream = NativeBuilder::new(ident).build(move || {
+        // a poisoned lock means a previous pull panicked, which ends the stream
+        match items.lock() {
+            Ok(mut items) => items.next(),
+            Err(_) => None,
+        }
+    });
+
+    engine.init_const(ident, Value::Func(FuncValue::native(stream)));
+}
+
+/// Defines `ident` as a native that hands the item it is called with to `consume`
+///
+/// The native returns true while the host wants more items and false once `consume`
+/// returned [`SinkFlow::Stop`], so scripts can end early without the engine being cancelled.
+pub fn load_sink<Data>(
+    engine: &mut Engine<Data>,
+    ident: &str,
+    consume: impl FnMut(Value<Data>) -> SinkFlow + Send + 'static,
+) where
+    Data: Clone + 'static,
+{
+    let consume = Mutex::new(Some(consume));
+    let sink = NativeBuilder::new(ident)
+        .arg::<Value<Data>>()
+        .build(move |item| {
+            let Ok(mut consume) = consume.lock() else {
+                return false;
+            };
+            let Some(f) = consume.as_mut() else {
+                return false;
+            };
+
+            match f(item) {
+                SinkFlow::Continue => true,
+                SinkFlow::Stop => {
+                    // dropping the consumer lets the host see the stream has ended
+                    *consume = None;
+                    false
+                }
+            }
+        });
+
+    engine.init_const(ident, Value::Func(FuncValue::native(sink)));
+}
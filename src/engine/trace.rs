@@ -0,0 +1,70 @@
+use crate::{parser::ast::Expr, symbol::Symbol};
+
+use super::{BinaryOp, UnaryOp, Value};
+
+/// What produced the value in a [`TraceEvent`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceOp {
+    /// A whole statement finished, with the value it evaluates to
+    Statement,
+    /// A literal value, including prefixed and suffixed literals
+    Literal,
+    /// A function literal
+    Func,
+    Var(Symbol),
+    Call(Symbol),
+    Unary(UnaryOp),
+    Binary(BinaryOp),
+    Ternary,
+    /// An `=` or `:=` assignment, with the value the expression evaluates to
+    Assign(Symbol),
+}
+
+impl TraceOp {
+    pub fn of<Data>(expr: &Expr<Data>) -> Self {
+        match expr {
+            Expr::None
+            | Expr::Bool(_)
+            | Expr::Int(_)
+            | Expr::Float(_)
+            | Expr::String(_)
+            | Expr::Prefixed(..)
+            | Expr::Suffixed(..) => Self::Literal,
+            Expr::Func(_) => Self::Func,
+            Expr::Var(ident) => Self::Var(ident.clone()),
+            Expr::Call(ident, _) => Self::Call((**ident).clone()),
+            Expr::Neg(_) => Self::Unary(UnaryOp::Neg),
+            Expr::Not(_) => Self::Unary(UnaryOp::Not),
+            Expr::Add(..) => Self::Binary(BinaryOp::Add),
+            Expr::Sub(..) => Self::Binary(BinaryOp::Sub),
+            Expr::Mul(..) => Self::Binary(BinaryOp::Mul),
+            Expr::Div(..) => Self::Binary(BinaryOp::Div),
+            Expr::Mod(..) => Self::Binary(BinaryOp::Mod),
+            Expr::Pow(..) => Self::Binary(BinaryOp::Pow),
+            Expr::And(..) => Self::Binary(BinaryOp::And),
+            Expr::Or(..) => Self::Binary(BinaryOp::Or),
+            Expr::Eq(..) => Self::Binary(BinaryOp::Eq),
+            Expr::Lt(..) => Self::Binary(BinaryOp::Lt),
+            Expr::Gt(..) => Self::Binary(BinaryOp::Gt),
+            Expr::NEq(..) => Self::Binary(BinaryOp::NEq),
+            Expr::LtEq(..) => Self::Binary(BinaryOp::LtEq),
+            Expr::GtEq(..) => Self::Binary(BinaryOp::GtEq),
+            Expr::Ternary(..) => Self::Ternary,
+            Expr::Assign(ident, _) | Expr::Walrus(ident, _) => Self::Assign((**ident).clone()),
+        }
+    }
+}
+
+/// A value produced while evaluating, sent to the sink set with
+/// [`Engine::set_trace_sink`](super::Engine::set_trace_sink)
+///
+/// Operands are reported before the expression that combines them,
+/// so reading the events in order replays the evaluation step by step.
+#[derive(Debug, Clone)]
+pub struct TraceEvent<Data> {
+    pub op: TraceOp,
+    pub value: Value<Data>,
+    pub data: Data,
+    /// Number of function calls being evaluated when the value was produced
+    pub depth: usize,
+}
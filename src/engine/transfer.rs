@@ -0,0 +1,75 @@
+use std::{fmt::Display, sync::Arc};
+
+use super::{error::NativeError, Engine, UserData, Value};
+
+/// Re-wraps user data arriving from another engine, see [`Engine::set_transfer_hook`]
+pub type TransferHook = Arc<dyn Fn(&UserData) -> Result<UserData, NativeError> + Send + Sync>;
+
+/// Why a value could not be moved into another engine with [`Value::transfer_to`]
+#[derive(Debug, Clone)]
+pub enum TransferError {
+    /// Functions look up globals by name wherever they are called,
+    /// and natives may hold on to state from the engine that defined them
+    Func,
+    /// Weak references only stay valid while the engine holding the user data keeps it
+    Weak(&'static str),
+    /// The target engine has no transfer hook for this user data type
+    Unhandled(&'static str),
+    /// A transfer hook failed to re-wrap the user data
+    Failed {
+        type_name: &'static str,
+        error: Box<NativeError>,
+    },
+}
+
+impl Display for TransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferError::Func => write!(f, "functions cannot be moved between engines"),
+            TransferError::Weak(name) => {
+                write!(f, "weak '{name}' values cannot be moved between engines")
+            }
+            TransferError::Unhandled(name) => {
+                write!(f, "the target engine has no transfer hook for '{name}'")
+            }
+            TransferError::Failed { type_name, error } => {
+                write!(f, "failed to transfer '{type_name}': {}", error.message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+impl<Data: Clone> Value<Data> {
+    /// Copies this value so it can be used by `engine`, sharing nothing with its source
+    ///
+    /// Strings are copied into new allocations and user data is handed to the
+    /// [transfer hook](Engine::set_transfer_hook) `engine` has for its type,
+    /// so one script's output can feed another without the two engines sharing state.
+    pub fn transfer_to(&self, engine: &Engine<Data>) -> Result<Value<Data>, TransferError> {
+        match self {
+            Value::None => Ok(Value::None),
+            Value::Bool(v) => Ok(Value::Bool(*v)),
+            Value::Int(v) => Ok(Value::Int(v.clone())),
+            Value::Float(v) => Ok(Value::Float(v.clone())),
+            Value::String(v) => Ok(Value::String(Arc::from(&**v))),
+            Value::Func(_) => Err(TransferError::Func),
+            Value::Weak(v) => Err(TransferError::Weak(v.type_name())),
+            Value::Custom(v) => {
+                let type_name = v.type_name();
+                let Some(hook) = engine.transfer_hook(v) else {
+                    return Err(TransferError::Unhandled(type_name));
+                };
+
+                match hook(v) {
+                    Ok(data) => Ok(Value::Custom(data)),
+                    Err(error) => Err(TransferError::Failed {
+                        type_name,
+                        error: Box::new(error),
+                    }),
+                }
+            }
+        }
+    }
+}
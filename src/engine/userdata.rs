@@ -0,0 +1,217 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    fmt::Debug,
+    sync::{Arc, Weak},
+};
+
+use super::error::NativeError;
+
+/// Cleanup run by `with` statements, receiving the wrapped host object
+type CloseHook = Arc<dyn Fn(&(dyn Any + Send + Sync)) -> Result<(), NativeError> + Send + Sync>;
+
+/// A host object that can be passed through scripts
+///
+/// Scripts cannot inspect user data, they can only hold it and
+/// pass it back to native functions that know its concrete type.
+#[derive(Clone)]
+pub struct UserData {
+    value: Arc<dyn Any + Send + Sync>,
+    guard: Option<Arc<DropGuard>>,
+    close: Option<CloseHook>,
+    name: &'static str,
+}
+
+/// Runs a callback when the last [`UserData`] sharing it is dropped
+struct DropGuard(Option<Box<dyn FnOnce() + Send + Sync>>);
+
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        if let Some(on_drop) = self.0.take() {
+            on_drop();
+        }
+    }
+}
+
+impl Debug for UserData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("UserData").field(&self.name).finish()
+    }
+}
+
+impl UserData {
+    pub fn new<T: Any + Send + Sync>(value: T) -> Self {
+        Self::from_arc(Arc::new(value))
+    }
+
+    /// Wraps a shared host object, so the host can keep its own strong reference
+    pub fn from_arc<T: Any + Send + Sync>(value: Arc<T>) -> Self {
+        Self {
+            value,
+            guard: None,
+            close: None,
+            name: short_name::<T>(),
+        }
+    }
+
+    /// Wraps a host object, calling `on_drop` once the last reference to this user data is dropped
+    ///
+    /// Hosts can use this to promptly release resources tied to the object,
+    /// like GPU handles or file descriptors, once scripts no longer hold it.
+    pub fn with_drop<T: Any + Send + Sync>(
+        value: T,
+        on_drop: impl FnOnce(&T) + Send + Sync + 'static,
+    ) -> Self {
+        Self::from_arc_with_drop(Arc::new(value), on_drop)
+    }
+
+    /// Wraps a shared host object like [`UserData::with_drop`]
+    ///
+    /// The callback only tracks [`UserData`] references, so it will still
+    /// run if the host holds its own `Arc` to the object.
+    pub fn from_arc_with_drop<T: Any + Send + Sync>(
+        value: Arc<T>,
+        on_drop: impl FnOnce(&T) + Send + Sync + 'static,
+    ) -> Self {
+        let target = value.clone();
+        let guard = DropGuard(Some(Box::new(move || on_drop(&target))));
+        Self {
+            value,
+            guard: Some(Arc::new(guard)),
+            close: None,
+            name: short_name::<T>(),
+        }
+    }
+
+    /// Sets the cleanup that `with` statements run once they are done with this object
+    ///
+    /// Unlike [`UserData::with_drop`], the cleanup runs as soon as the statement ends,
+    /// even if the body failed or the script still holds other references.
+    /// `close` is never called if `T` is not the wrapped type.
+    pub fn on_close<T: Any + Send + Sync>(
+        mut self,
+        close: impl Fn(&T) -> Result<(), NativeError> + Send + Sync + 'static,
+    ) -> Self {
+        self.close = Some(Arc::new(move |value| match value.downcast_ref::<T>() {
+            Some(value) => close(value),
+            None => Ok(()),
+        }));
+        self
+    }
+
+    /// Returns true if [`close`](UserData::close) runs a cleanup hook
+    pub fn is_closeable(&self) -> bool {
+        self.close.is_some()
+    }
+
+    /// Runs the cleanup set by [`UserData::on_close`], if any
+    pub fn close(&self) -> Result<(), NativeError> {
+        match &self.close {
+            Some(close) => close(&*self.value),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the name of the wrapped type
+    pub fn type_name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Returns the [`TypeId`] of the wrapped type
+    pub fn type_id(&self) -> TypeId {
+        (*self.value).type_id()
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.value.downcast_ref()
+    }
+
+    pub fn downcast_arc<T: Any + Send + Sync>(&self) -> Option<Arc<T>> {
+        self.value.clone().downcast().ok()
+    }
+
+    /// Returns true if both values point to the same host object
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.value, &other.value)
+    }
+
+    pub fn downgrade(&self) -> WeakUserData {
+        WeakUserData {
+            value: Arc::downgrade(&self.value),
+            guard: self.guard.as_ref().map(Arc::downgrade),
+            close: self.close.clone(),
+            name: self.name,
+        }
+    }
+}
+
+/// A handle to [`UserData`] that does not keep the host object alive
+///
+/// Hosts can give scripts weak handles to objects they may destroy at any time,
+/// and scripts use `is_alive` and `upgrade` to check if the object still exists.
+#[derive(Clone)]
+pub struct WeakUserData {
+    value: Weak<dyn Any + Send + Sync>,
+    guard: Option<Weak<DropGuard>>,
+    close: Option<CloseHook>,
+    name: &'static str,
+}
+
+impl Debug for WeakUserData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("WeakUserData").field(&self.name).finish()
+    }
+}
+
+impl WeakUserData {
+    /// Creates a weak handle directly from a host owned object
+    pub fn from_arc<T: Any + Send + Sync>(value: &Arc<T>) -> Self {
+        let value: Arc<dyn Any + Send + Sync> = value.clone();
+        Self {
+            value: Arc::downgrade(&value),
+            guard: None,
+            close: None,
+            name: short_name::<T>(),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn is_alive(&self) -> bool {
+        match &self.guard {
+            Some(guard) => guard.strong_count() > 0,
+            None => self.value.strong_count() > 0,
+        }
+    }
+
+    /// Returns the user data if it has not been dropped
+    pub fn upgrade(&self) -> Option<UserData> {
+        let guard = match &self.guard {
+            Some(guard) => Some(guard.upgrade()?),
+            None => None,
+        };
+
+        Some(UserData {
+            value: self.value.upgrade()?,
+            guard,
+            close: self.close.clone(),
+            name: self.name,
+        })
+    }
+
+    /// Returns true if both handles point to the same host object
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Weak::ptr_eq(&self.value, &other.value)
+    }
+}
+
+/// Strips the module path from a type name, so `game::Entity` is displayed as `Entity`
+fn short_name<T>() -> &'static str {
+    let name = type_name::<T>();
+    let base = name.split('<').next().unwrap_or(name);
+    match base.rfind("::") {
+        Some(index) => &name[index + 2..],
+        None => name,
+    }
+}
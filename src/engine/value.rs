@@ -1,8 +1,12 @@
-use std::fmt::Display;
+use std::{fmt::Display, mem::size_of, sync::Arc};
 
-use dashu::{float::DBig, integer::IBig};
+use dashu::{
+    base::{BitTest, UnsignedAbs},
+    float::DBig,
+    integer::IBig,
+};
 
-use super::FuncValue;
+use super::{FuncValue, UserData, WeakUserData};
 
 #[derive(Debug, Clone)]
 pub enum Value<Data> {
@@ -10,11 +14,13 @@ pub enum Value<Data> {
     Bool(bool),
     Int(IBig),
     Float(DBig),
-    String(String),
+    String(Arc<str>),
     Func(FuncValue<Data>),
+    Custom(UserData),
+    Weak(WeakUserData),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ValueType {
     None,
     Bool,
@@ -22,6 +28,8 @@ pub enum ValueType {
     Float,
     String,
     Func(usize),
+    Custom(&'static str),
+    Weak(&'static str),
 }
 
 impl Display for ValueType {
@@ -33,24 +41,99 @@ impl Display for ValueType {
             ValueType::Float => write!(f, "float"),
             ValueType::String => write!(f, "string"),
             ValueType::Func(params) => write!(f, "fn({})", params),
+            ValueType::Custom(name) => write!(f, "{name}"),
+            ValueType::Weak(name) => write!(f, "weak {name}"),
         }
     }
 }
 
-impl<Data> Display for Value<Data> {
+/// Controls how float values are displayed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FloatFormat {
+    /// Maximum number of significant digits to display
+    pub max_digits: Option<usize>,
+    /// Decimal exponent magnitude at which scientific notation is used
+    pub sci_threshold: Option<usize>,
+}
+
+impl Default for FloatFormat {
+    fn default() -> Self {
+        Self {
+            max_digits: Some(16),
+            sci_threshold: Some(21),
+        }
+    }
+}
+
+impl FloatFormat {
+    pub fn format(&self, v: &DBig) -> String {
+        let v = match self.max_digits {
+            Some(digits) if digits > 0 && v.digits() > digits => {
+                v.clone().with_precision(digits).value()
+            }
+            _ => v.clone(),
+        };
+
+        let significand = v.repr().significand();
+        if *significand == IBig::ZERO {
+            return v.to_string();
+        }
+
+        // decimal exponent of the most significant digit
+        let digits = significand.unsigned_abs().to_string();
+        let magnitude = v.repr().exponent() + digits.len() as isize - 1;
+        match self.sci_threshold {
+            Some(threshold) if magnitude.unsigned_abs() >= threshold => {
+                let sign = if *significand < IBig::ZERO { "-" } else { "" };
+                let (first, rest) = digits.split_at(1);
+                match rest.trim_end_matches('0') {
+                    "" => format!("{sign}{first}e{magnitude}"),
+                    rest => format!("{sign}{first}.{rest}e{magnitude}"),
+                }
+            }
+            _ => v.to_string(),
+        }
+    }
+}
+
+/// Displays a [`Value`] using a specific [`FloatFormat`]
+pub struct ValueDisplay<'a, Data> {
+    value: &'a Value<Data>,
+    format: &'a FloatFormat,
+}
+
+impl<'a, Data> Display for ValueDisplay<'a, Data> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        match self.value {
             Value::None => write!(f, "none"),
             Value::Bool(v) => write!(f, "{v}"),
             Value::Int(v) => write!(f, "{v}"),
-            Value::Float(v) => write!(f, "{v}"),
+            Value::Float(v) => write!(f, "{}", self.format.format(v)),
             Value::String(v) => write!(f, "'{v}'"),
             Value::Func(v) => write!(f, "fn({})", v.param_count()),
+            Value::Custom(v) => write!(f, "<{}>", v.type_name()),
+            Value::Weak(v) => match v.is_alive() {
+                true => write!(f, "<weak {}>", v.type_name()),
+                false => write!(f, "<dropped {}>", v.type_name()),
+            },
         }
     }
 }
 
+impl<Data> Display for Value<Data> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display(&FloatFormat::default()))
+    }
+}
+
 impl<Data> Value<Data> {
+    pub fn display<'a>(&'a self, format: &'a FloatFormat) -> ValueDisplay<'a, Data> {
+        ValueDisplay {
+            value: self,
+            format,
+        }
+    }
+
     pub fn get_type(&self) -> ValueType {
         match self {
             Value::None => ValueType::None,
@@ -59,6 +142,20 @@ impl<Data> Value<Data> {
             Value::Float(_) => ValueType::Float,
             Value::String(_) => ValueType::String,
             Value::Func(f) => ValueType::Func(f.param_count()),
+            Value::Custom(v) => ValueType::Custom(v.type_name()),
+            Value::Weak(v) => ValueType::Weak(v.type_name()),
         }
     }
+
+    /// Returns a rough estimate of the memory used by this value in bytes
+    pub fn size_estimate(&self) -> usize {
+        size_of::<Self>()
+            + match self {
+                Value::None | Value::Bool(_) | Value::Func(_) => 0,
+                Value::Custom(_) | Value::Weak(_) => 0,
+                Value::Int(v) => v.bit_len() / 8,
+                Value::Float(v) => v.repr().significand().bit_len() / 8,
+                Value::String(v) => v.len(),
+            }
+    }
 }
@@ -0,0 +1,106 @@
+use std::{
+    any::Any,
+    panic::{self, AssertUnwindSafe},
+};
+
+use ariadne::Report;
+
+use crate::{
+    cache::{CacheError, CacheSpan},
+    engine::error::RunError,
+    parser::PError,
+    BobaCache,
+};
+
+/// Any error that can occur while parsing and evaluating source code
+#[derive(Debug, Clone)]
+pub enum BobaError {
+    Parse(PError<CacheSpan>),
+    Run(RunError<CacheSpan>),
+    Cache(CacheError),
+    /// The engine panicked while handling the input, which is always a bug in boba
+    Internal(String),
+}
+
+impl From<PError<CacheSpan>> for BobaError {
+    fn from(error: PError<CacheSpan>) -> Self {
+        Self::Parse(error)
+    }
+}
+
+impl From<RunError<CacheSpan>> for BobaError {
+    fn from(error: RunError<CacheSpan>) -> Self {
+        Self::Run(error)
+    }
+}
+
+impl From<CacheError> for BobaError {
+    fn from(error: CacheError) -> Self {
+        Self::Cache(error)
+    }
+}
+
+impl BobaError {
+    /// Returns the panic message if the error is a bug in boba rather than a problem with the input
+    ///
+    /// Panics caught while parsing or evaluating keep their location as
+    /// [`PError::Internal`] or [`RunError::Internal`], so they are checked too.
+    pub fn internal_message(&self) -> Option<&str> {
+        match self {
+            BobaError::Parse(PError::Internal { message, .. }) => Some(message),
+            BobaError::Run(error) => match error.root() {
+                RunError::Internal { message, .. } => Some(message),
+                _ => None,
+            },
+            BobaError::Internal(message) => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Builds an ariadne [`Report`] for the error, or `None` if it has no source location
+    pub fn report(&self) -> Option<Report<'_, CacheSpan>> {
+        match self {
+            BobaError::Parse(error) => Some(error.report()),
+            BobaError::Run(error) => Some(error.report()),
+            BobaError::Cache(_) | BobaError::Internal(_) => None,
+        }
+    }
+
+    /// Prints the error to stderr, using ariadne reports where a span is available
+    ///
+    /// Falls back to the error code if the source of the span is no longer in `cache`.
+    pub fn eprint(&self, cache: &mut BobaCache) {
+        let (report, code) = match self {
+            BobaError::Parse(error) => (error.report(), error.code()),
+            BobaError::Run(error) => (error.report(), error.code()),
+            BobaError::Cache(error) => return eprintln!("Cache Error: {error}"),
+            BobaError::Internal(message) => return eprintln!("Internal Error: {message}"),
+        };
+
+        if report.eprint(cache).is_err() {
+            eprintln!("Error [{code}]: source is missing from the cache");
+        }
+    }
+
+    /// Runs `f`, converting a panic into [`BobaError::Internal`]
+    ///
+    /// Used by the public entry points so malformed input can never take down the host.
+    /// State touched by `f` may be left partially updated when a panic is caught.
+    pub(crate) fn catch_panic<T>(f: impl FnOnce() -> Result<T, Self>) -> Result<T, Self> {
+        match panic::catch_unwind(AssertUnwindSafe(f)) {
+            Ok(result) => result,
+            Err(payload) => Err(BobaError::Internal(panic_message(payload))),
+        }
+    }
+}
+
+/// Returns the message a caught panic was raised with
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "unknown panic".to_string(),
+        },
+    }
+}
@@ -1,7 +1,17 @@
+pub mod analysis;
 pub mod cache;
+pub mod conformance;
+pub mod diagnostic;
 pub mod engine;
+pub mod error;
+#[cfg(feature = "lsp")]
+pub mod lsp;
 pub mod parser;
+pub mod plugins;
 pub mod shell;
+pub mod symbol;
 
 pub use cache::BobaCache;
 pub use engine::Engine;
+pub use error::BobaError;
+pub use symbol::Symbol;
@@ -0,0 +1,504 @@
+//! A language server for editors, speaking the Language Server Protocol over stdio
+//!
+//! Documents are kept in a [`BobaCache`] and fully resynced on every change.
+//! The server publishes parse errors and lint warnings whenever a document changes,
+//! and answers go-to-definition and hover requests using the [`ResolutionMap`].
+
+use std::{
+    io::{self, BufRead, Write},
+    ops::{Deref, Range},
+};
+
+use dashu::{float::DBig, integer::IBig};
+use indexmap::IndexMap;
+use serde_json::{json, Value as Json};
+
+use crate::{
+    analysis::{Linter, ResolutionMap},
+    cache::{CacheData, CacheId, CacheSpan},
+    diagnostic::{Severity, Warning},
+    engine::{value::ValueType, BinaryOp, OpManager, UnaryOp, Value},
+    parser::{
+        ast::{Expr, Node, Program, Statement},
+        Lexer, PError,
+    },
+    BobaCache,
+};
+
+/// Error code for messages whose body is not valid JSON
+const PARSE_ERROR: i64 = -32700;
+/// Error code for requests the server does not implement
+const METHOD_NOT_FOUND: i64 = -32601;
+/// Error code for requests sent after the client asked the server to shut down
+const INVALID_REQUEST: i64 = -32600;
+
+/// How deep type inference follows variables before giving up
+const INFER_DEPTH: usize = 32;
+
+/// Serves a single editor connection
+pub struct Server {
+    cache: BobaCache,
+    documents: IndexMap<String, CacheId>,
+    shutdown: bool,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            cache: BobaCache::new(),
+            documents: IndexMap::new(),
+            shutdown: false,
+        }
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cache holding the text of every open document
+    pub fn cache(&self) -> &BobaCache {
+        &self.cache
+    }
+
+    /// Handles messages from `input` until the client sends `exit` or the input ends
+    ///
+    /// Bodies that are not valid JSON are answered with a parse error, but messages
+    /// without a content length end the connection since the next one cannot be found.
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            let message = match message {
+                Ok(message) => message,
+                Err(error) => {
+                    let error = json!({ "code": PARSE_ERROR, "message": error.to_string() });
+                    let reply = json!({ "jsonrpc": "2.0", "id": null, "error": error });
+                    write_message(&mut output, &reply)?;
+                    continue;
+                }
+            };
+
+            if message["method"] == "exit" {
+                break;
+            }
+
+            for reply in self.handle(&message) {
+                write_message(&mut output, &reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles a single message, returning the responses and notifications to send back
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        if self.shutdown {
+            let Some(id) = message.get("id") else {
+                return Vec::new();
+            };
+            let error = json!({ "code": INVALID_REQUEST, "message": "server is shut down" });
+            return vec![json!({ "jsonrpc": "2.0", "id": id, "error": error })];
+        }
+
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match message["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                },
+                "serverInfo": {
+                    "name": "bobarista",
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                return self.open(uri, text).into_iter().collect();
+            }
+            "textDocument/didChange" => {
+                // full sync sends the whole text as the last change
+                let changes = params["contentChanges"].as_array();
+                let text = changes.and_then(|changes| changes.last());
+                let text = text.and_then(|change| change["text"].as_str());
+                return self
+                    .open(uri, text.unwrap_or_default())
+                    .into_iter()
+                    .collect();
+            }
+            "textDocument/didClose" => {
+                if let Some(id) = self.documents.shift_remove(uri) {
+                    self.cache.remove(id);
+                }
+                return vec![publish(uri, Vec::new())];
+            }
+            "textDocument/definition" => self.definition(uri, &params["position"]),
+            "textDocument/hover" => self.hover(uri, &params["position"]),
+            method => match message.get("id") {
+                // unknown notifications are ignored
+                None => return Vec::new(),
+                Some(id) => {
+                    let message = format!("unsupported method '{method}'");
+                    let error = json!({ "code": METHOD_NOT_FOUND, "message": message });
+                    return vec![json!({ "jsonrpc": "2.0", "id": id, "error": error })];
+                }
+            },
+        };
+
+        match message.get("id") {
+            Some(id) => vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })],
+            None => Vec::new(),
+        }
+    }
+
+    /// Stores the text of a document, returning its diagnostics
+    fn open(&mut self, uri: &str, text: &str) -> Option<Json> {
+        let stored = match self.documents.get(uri) {
+            Some(id) => self.cache.replace(*id, text).ok().flatten(),
+            None => self.cache.store(uri, text).ok(),
+        };
+
+        let data = stored?;
+        self.documents.insert(uri.to_string(), data.id());
+        Some(publish(uri, diagnostics(data)))
+    }
+
+    fn document(&self, uri: &str) -> Option<&CacheData> {
+        self.cache.load(*self.documents.get(uri)?)
+    }
+
+    fn definition(&self, uri: &str, position: &Json) -> Json {
+        let Some(data) = self.document(uri) else {
+            return Json::Null;
+        };
+
+        let offset = offset(data.text(), position);
+        let (program, _) = Program::parse_recover(&mut Lexer::new(data));
+        let map = ResolutionMap::build(&program.statements);
+        let definition = match map.use_at(offset) {
+            Some(resolution) => map.binding(resolution),
+            None => map.definition_at(offset),
+        };
+
+        match definition {
+            Some(definition) => json!({
+                "uri": uri,
+                "range": range(data.text(), definition.data.range()),
+            }),
+            None => Json::Null,
+        }
+    }
+
+    fn hover(&self, uri: &str, position: &Json) -> Json {
+        let Some(data) = self.document(uri) else {
+            return Json::Null;
+        };
+
+        let offset = offset(data.text(), position);
+        let (program, _) = Program::parse_recover(&mut Lexer::new(data));
+        let map = ResolutionMap::build(&program.statements);
+        let (ident, span, definition) = match map.use_at(offset) {
+            Some(resolution) => (&resolution.ident, &resolution.data, map.binding(resolution)),
+            None => match map.definition_at(offset) {
+                Some(definition) => (&definition.ident, &definition.data, Some(definition)),
+                None => return Json::Null,
+            },
+        };
+
+        let types = Types::new(&program.statements, &map);
+        let found = definition.and_then(|definition| types.definition(&definition.data, 0));
+        let kind = match (definition, found) {
+            (None, _) => "host value".to_string(),
+            (Some(_), None) => "unknown".to_string(),
+            (Some(_), Some(found)) => found.to_string(),
+        };
+
+        json!({
+            "contents": {
+                "kind": "markdown",
+                "value": format!("```boba\n{ident}: {kind}\n```"),
+            },
+            "range": range(data.text(), span.range()),
+        })
+    }
+}
+
+/// Reads a message framed by a `Content-Length` header, or `None` once the input ends
+///
+/// Only broken framing is an io error, a body that fails to parse is returned as is.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<serde_json::Result<Json>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message has no content length",
+        ));
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// Converts an LSP position, counted in UTF-16 code units, into a byte offset
+fn offset(text: &str, position: &Json) -> usize {
+    let line = position["line"].as_u64().unwrap_or_default() as usize;
+    let character = position["character"].as_u64().unwrap_or_default() as usize;
+
+    let mut start = 0;
+    for _ in 0..line {
+        match text[start..].find('\n') {
+            Some(index) => start += index + 1,
+            None => return text.len(),
+        }
+    }
+
+    let mut units = 0;
+    for (index, char) in text[start..].char_indices() {
+        if units >= character || char == '\n' {
+            return start + index;
+        }
+        units += char.len_utf16();
+    }
+    text.len()
+}
+
+/// Converts a byte offset into an LSP position
+fn position(text: &str, offset: usize) -> Json {
+    let before = &text[..offset.min(text.len())];
+    let start = before.rfind('\n').map_or(0, |index| index + 1);
+    let line = before.matches('\n').count();
+    let character: usize = before[start..].chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+fn range(text: &str, range: &Range<usize>) -> Json {
+    json!({ "start": position(text, range.start), "end": position(text, range.end) })
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// Parses a document, returning its parse errors and lint warnings
+fn diagnostics(data: &CacheData) -> Vec<Json> {
+    let text = data.text();
+    let (program, errors) = Program::parse_recover(&mut Lexer::new(data));
+    let errors = errors.iter().map(|error| {
+        json!({
+            "range": range(text, error.data().range()),
+            "severity": 1,
+            "code": error.code(),
+            "source": "boba",
+            "message": error_message(error),
+        })
+    });
+
+    let warnings = Linter::new().lint(&program.statements);
+    let warnings = warnings.iter().map(|warning| {
+        let severity = match warning.severity() {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+            Severity::Hint => 4,
+        };
+
+        json!({
+            "range": range(text, warning.data().range()),
+            "severity": severity,
+            "code": warning.rule().map(|rule| rule.name()),
+            "source": "boba",
+            "message": warning_message(warning),
+        })
+    });
+
+    errors.chain(warnings).collect()
+}
+
+fn error_message(error: &PError<CacheSpan>) -> String {
+    match error {
+        PError::UnexpectedEnd { expected, .. } => {
+            format!("expected {expected}, found end of input")
+        }
+        PError::InvalidToken { part, .. } => format!("invalid token {part}"),
+        PError::UnclosedString { .. } => "string has no closing quote".to_string(),
+        PError::ParseNumError { error, .. } => format!("error parsing number: {error}"),
+        PError::UnexpectedToken {
+            expected, found, ..
+        } => format!("expected {expected}, found {found}"),
+        PError::UnclosedBrace { found, .. } => {
+            format!("opening brace has no closing brace, found {found}")
+        }
+        PError::InvalidAssignment { .. } => {
+            "cannot assign expression to another expression".to_string()
+        }
+        PError::MixedTabsAndSpaces { tab: true, .. } => {
+            "tab found here when a space was expected".to_string()
+        }
+        PError::MixedTabsAndSpaces { tab: false, .. } => {
+            "space found here when a tab was expected".to_string()
+        }
+        PError::Internal { message, .. } => format!("parsing panicked: {message}"),
+        PError::TooDeep { limit, .. } => {
+            format!("expression nests more than {limit} levels deep")
+        }
+    }
+}
+
+fn warning_message(warning: &Warning<CacheSpan>) -> String {
+    match warning {
+        Warning::UnusedVariable { ident, .. } => format!("'{ident}' is never used"),
+        Warning::FloatEquality { .. } => "floats are compared for exact equality".to_string(),
+        Warning::ShadowedBinding { ident, .. } => {
+            format!("'{ident}' shadows an earlier definition")
+        }
+        Warning::UnusedFunction { ident, .. } => format!("'{ident}' can never be called"),
+        Warning::ShadowedParameter { ident, .. } => {
+            format!("parameter '{ident}' hides an earlier definition")
+        }
+        Warning::ConstantCondition { .. } => "this condition is the same on every run".to_string(),
+    }
+}
+
+type ExprNode = Node<CacheSpan, Expr<CacheSpan>>;
+
+/// Infers the types of values from the expressions that create them, without running the script
+///
+/// Operators are applied to sample values with the engine's own [`OpManager`],
+/// so the inferred types always follow the real operator rules.
+struct Types<'a> {
+    map: &'a ResolutionMap<CacheSpan>,
+    inits: Vec<(Range<usize>, &'a ExprNode)>,
+    ops: OpManager<()>,
+}
+
+impl<'a> Types<'a> {
+    fn new(
+        statements: &'a [Node<CacheSpan, Statement<CacheSpan>>],
+        map: &'a ResolutionMap<CacheSpan>,
+    ) -> Self {
+        let mut types = Self {
+            map,
+            inits: Vec::new(),
+            ops: OpManager::new(),
+        };
+        types.collect(statements);
+        types
+    }
+
+    /// Records the expression each definition is initialized with
+    fn collect(&mut self, statements: &'a [Node<CacheSpan, Statement<CacheSpan>>]) {
+        for statement in statements {
+            if let Statement::Init(init) = statement.deref() {
+                let range = init.ident.data().range().clone();
+                self.inits.push((range, &init.expr));
+                if let Expr::Func(func) = init.expr.deref() {
+                    self.collect(&func.body);
+                }
+            }
+        }
+    }
+
+    /// Returns the type of the value the definition at `data` is created with
+    fn definition(&self, data: &CacheSpan, depth: usize) -> Option<ValueType> {
+        let (_, expr) = self.inits.iter().find(|(range, _)| range == data.range())?;
+        self.expr(expr, depth)
+    }
+
+    fn expr(&self, expr: &ExprNode, depth: usize) -> Option<ValueType> {
+        if depth > INFER_DEPTH {
+            return None;
+        }
+
+        let (op, lhs, rhs) = match expr.deref() {
+            Expr::None => return Some(ValueType::None),
+            Expr::Bool(_) => return Some(ValueType::Bool),
+            Expr::Int(_) => return Some(ValueType::Int),
+            Expr::Float(_) => return Some(ValueType::Float),
+            Expr::String(_) => return Some(ValueType::String),
+            Expr::Func(func) => return Some(ValueType::Func(func.params.len())),
+            Expr::Assign(..) => return Some(ValueType::None),
+            Expr::Walrus(_, rhs) => return self.expr(rhs, depth + 1),
+            Expr::Var(_) => {
+                let resolution = self.map.use_at(expr.data().range().start)?;
+                let definition = self.map.binding(resolution)?;
+                return self.definition(&definition.data, depth + 1);
+            }
+            Expr::Neg(inner) | Expr::Not(inner) => {
+                let op = match expr.deref() {
+                    Expr::Neg(_) => UnaryOp::Neg,
+                    _ => UnaryOp::Not,
+                };
+                let value = sample(self.expr(inner, depth + 1)?)?;
+                let value = self.ops.unary(op, value, &()).ok()?;
+                return Some(value.get_type());
+            }
+            Expr::Ternary(_, lhs, rhs) => {
+                let lhs = self.expr(lhs, depth + 1)?;
+                return (Some(lhs) == self.expr(rhs, depth + 1)).then_some(lhs);
+            }
+            // host hooks and calls can return anything
+            Expr::Prefixed(..) | Expr::Suffixed(..) | Expr::Call(..) => return None,
+            Expr::Add(lhs, rhs) => (BinaryOp::Add, lhs, rhs),
+            Expr::Sub(lhs, rhs) => (BinaryOp::Sub, lhs, rhs),
+            Expr::Mul(lhs, rhs) => (BinaryOp::Mul, lhs, rhs),
+            Expr::Div(lhs, _, rhs) => (BinaryOp::Div, lhs, rhs),
+            Expr::Mod(lhs, _, rhs) => (BinaryOp::Mod, lhs, rhs),
+            Expr::Pow(lhs, _, rhs) => (BinaryOp::Pow, lhs, rhs),
+            Expr::And(lhs, rhs) => (BinaryOp::And, lhs, rhs),
+            Expr::Or(lhs, rhs) => (BinaryOp::Or, lhs, rhs),
+            Expr::Eq(lhs, rhs) => (BinaryOp::Eq, lhs, rhs),
+            Expr::Lt(lhs, rhs) => (BinaryOp::Lt, lhs, rhs),
+            Expr::Gt(lhs, rhs) => (BinaryOp::Gt, lhs, rhs),
+            Expr::NEq(lhs, rhs) => (BinaryOp::NEq, lhs, rhs),
+            Expr::LtEq(lhs, rhs) => (BinaryOp::LtEq, lhs, rhs),
+            Expr::GtEq(lhs, rhs) => (BinaryOp::GtEq, lhs, rhs),
+        };
+
+        let lhs = sample(self.expr(lhs, depth + 1)?)?;
+        let rhs = sample(self.expr(rhs, depth + 1)?)?;
+        let value = self.ops.binary(op, lhs, rhs, &()).ok()?;
+        Some(value.get_type())
+    }
+}
+
+/// Returns a value of type `kind` that no operator rejects for its contents, like a zero divisor
+fn sample(kind: ValueType) -> Option<Value<()>> {
+    Some(match kind {
+        ValueType::None => Value::None,
+        ValueType::Bool => Value::Bool(true),
+        ValueType::Int => Value::Int(IBig::ONE),
+        ValueType::Float => Value::Float(DBig::ONE),
+        ValueType::String => Value::String("a".into()),
+        _ => return None,
+    })
+}
@@ -1,19 +1,351 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
-use boba::shell;
-use clap::Parser;
+use boba::{
+    analysis::{metrics::Measure, Linter, Metrics, Rule},
+    cache::{CacheData, CacheSpan},
+    conformance,
+    parser::{self, ast::Program, Lexer},
+    shell, BobaCache, BobaError, Engine,
+};
+use clap::{Parser, Subcommand};
+
+/// Exit code used when the script file could not be read
+const EXIT_IO: u8 = 1;
+/// Exit code used when the script failed to parse
+const EXIT_PARSE: u8 = 2;
+/// Exit code used when the script failed while running
+const EXIT_RUN: u8 = 3;
+/// Exit code used when `fmt --check` finds files that are not formatted
+const EXIT_UNFORMATTED: u8 = 4;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
+#[command(args_conflicts_with_subcommands = true)]
 struct BobaCli {
+    #[command(subcommand)]
+    command: Option<Command>,
     file: Option<PathBuf>,
+    /// Prints the call count and time spent in each function once the script finishes
+    #[arg(long)]
+    profile: bool,
+    /// Writes folded call stacks for the script run to this path, for flamegraph tools
+    #[cfg(feature = "flamegraph")]
+    #[arg(long)]
+    flamegraph: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Parses script files without running them, reporting every diagnostic
+    Check {
+        files: Vec<PathBuf>,
+        /// Name of a function the host calls directly, so it is never reported as unused
+        #[arg(long = "entry")]
+        entries: Vec<String>,
+        /// Name of a lint rule to stop reporting, like 'unused-variable'
+        #[arg(long = "allow")]
+        allowed: Vec<Rule>,
+    },
+    /// Prints statement counts, nesting depth and complexity for each function in the files
+    Metrics { files: Vec<PathBuf> },
+    /// Rewrites script files in the canonical style
+    Fmt {
+        files: Vec<PathBuf>,
+        /// Only lists the files that are not formatted, without changing them
+        #[arg(long)]
+        check: bool,
+    },
+    /// Serves the language server protocol over stdio, for editor integration
+    #[cfg(feature = "lsp")]
+    Lsp,
+    /// Runs the language conformance suite against the default engine
+    Conformance {
+        /// Also run this many random mutations of each case, checking that none of them panic
+        #[arg(long)]
+        fuzz: Option<usize>,
+        /// Seed for the mutations, so failures can be reproduced
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
 }
 
-fn main() {
+fn main() -> ExitCode {
     let cli = BobaCli::parse();
-    match cli.file {
-        None => shell::Session::new().start_console(),
-        Some(path) => println!("TODO: run file {}", path.display()),
+    match (cli.command, cli.file) {
+        (
+            Some(Command::Check {
+                files,
+                entries,
+                allowed,
+            }),
+            _,
+        ) => check_files(files, entries, allowed),
+        (Some(Command::Metrics { files }), _) => print_metrics(files),
+        (Some(Command::Fmt { files, check }), _) => format_files(files, check),
+        (Some(Command::Conformance { fuzz, seed }), _) => run_conformance(fuzz, seed),
+        #[cfg(feature = "lsp")]
+        (Some(Command::Lsp), _) => run_lsp(),
+        #[cfg(feature = "flamegraph")]
+        (None, Some(path)) => run_file(path, cli.profile, cli.flamegraph),
+        #[cfg(not(feature = "flamegraph"))]
+        (None, Some(path)) => run_file(path, cli.profile),
+        (None, None) => {
+            shell::Session::new().start_console();
+            ExitCode::SUCCESS
+        }
+    }
+}
+
+fn load_file<'a>(cache: &'a mut BobaCache, path: &Path) -> Result<&'a CacheData, ExitCode> {
+    match cache.load_file(path) {
+        Ok(data) => Ok(data),
+        Err(e) => {
+            eprintln!("Failed to load '{}': {e}", path.display());
+            Err(ExitCode::from(EXIT_IO))
+        }
+    }
+}
+
+#[cfg(feature = "flamegraph")]
+fn run_file(path: PathBuf, profile: bool, flamegraph: Option<PathBuf>) -> ExitCode {
+    let mut engine = Engine::with_prelude();
+    if profile {
+        engine.start_profile();
+    }
+    if flamegraph.is_some() {
+        engine.start_flamegraph();
+    }
+
+    let code = eval_file(&mut engine, &path);
+    print_profile(&mut engine);
+    let (Some(output), Some(flame)) = (flamegraph, engine.take_flamegraph()) else {
+        return code;
+    };
+
+    let written = std::fs::File::create(&output)
+        .and_then(|file| flame.write_folded(std::io::BufWriter::new(file)));
+    match written {
+        Ok(()) => code,
+        Err(e) => {
+            eprintln!("Failed to write '{}': {e}", output.display());
+            ExitCode::from(EXIT_IO)
+        }
+    }
+}
+
+#[cfg(not(feature = "flamegraph"))]
+fn run_file(path: PathBuf, profile: bool) -> ExitCode {
+    let mut engine = Engine::with_prelude();
+    if profile {
+        engine.start_profile();
+    }
+
+    let code = eval_file(&mut engine, &path);
+    print_profile(&mut engine);
+    code
+}
+
+/// Prints the profile to stderr, so it stays apart from what the script printed
+fn print_profile(engine: &mut Engine<CacheSpan>) {
+    let Some(profile) = engine.take_profile() else {
+        return;
+    };
+
+    if let Err(e) = profile.write_report(std::io::stderr().lock()) {
+        eprintln!("Failed to print the profile: {e}");
+    }
+}
+
+fn eval_file(engine: &mut Engine<CacheSpan>, path: &Path) -> ExitCode {
+    let data = match load_file(engine.cache_mut(), path) {
+        Ok(data) => data,
+        Err(code) => return code,
+    };
+
+    let error = match Program::parse(&mut Lexer::new(data)) {
+        Err(error) => BobaError::Parse(error),
+        Ok(program) => match engine.eval_program(&program.statements) {
+            Ok(_) => return ExitCode::SUCCESS,
+            Err(error) => BobaError::Run(error),
+        },
+    };
+
+    error.eprint(engine.cache_mut());
+    match error {
+        BobaError::Parse(_) => ExitCode::from(EXIT_PARSE),
+        BobaError::Run(_) | BobaError::Internal(_) => ExitCode::from(EXIT_RUN),
+        BobaError::Cache(_) => ExitCode::from(EXIT_IO),
+    }
+}
+
+fn check_files(paths: Vec<PathBuf>, entries: Vec<String>, allowed: Vec<Rule>) -> ExitCode {
+    let linter = entries.into_iter().fold(Linter::new(), Linter::entry);
+    let linter = allowed.into_iter().fold(linter, Linter::allow);
+    let mut cache = BobaCache::new();
+    let mut code = ExitCode::SUCCESS;
+    for path in paths {
+        let data = match load_file(&mut cache, &path) {
+            Ok(data) => data,
+            Err(error) => {
+                code = error;
+                continue;
+            }
+        };
+
+        let (program, errors) = Program::parse_recover(&mut Lexer::new(data));
+        if !errors.is_empty() && code == ExitCode::SUCCESS {
+            code = ExitCode::from(EXIT_PARSE);
+        }
+
+        for error in errors {
+            error.report().eprint(&mut cache).unwrap();
+        }
+
+        // warnings never change the exit code
+        for warning in linter.lint(&program.statements) {
+            warning.report().eprint(&mut cache).unwrap();
+        }
+    }
+
+    code
+}
+
+fn format_files(paths: Vec<PathBuf>, check: bool) -> ExitCode {
+    let mut cache = BobaCache::new();
+    let mut code = ExitCode::SUCCESS;
+    for path in paths {
+        let data = match load_file(&mut cache, &path) {
+            Ok(data) => data,
+            Err(error) => {
+                code = error;
+                continue;
+            }
+        };
+
+        let formatted = match parser::format_data(data) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for error in errors {
+                    error.report().eprint(&mut cache).unwrap();
+                }
+                code = ExitCode::from(EXIT_PARSE);
+                continue;
+            }
+        };
+
+        if formatted == data.text() {
+            continue;
+        }
+
+        if check {
+            println!("{}", path.display());
+            if code == ExitCode::SUCCESS {
+                code = ExitCode::from(EXIT_UNFORMATTED);
+            }
+        } else if let Err(e) = std::fs::write(&path, formatted) {
+            eprintln!("Failed to write '{}': {e}", path.display());
+            code = ExitCode::from(EXIT_IO);
+        }
+    }
+
+    code
+}
+
+fn print_metrics(paths: Vec<PathBuf>) -> ExitCode {
+    let mut cache = BobaCache::new();
+    let mut code = ExitCode::SUCCESS;
+    for path in paths {
+        let data = match load_file(&mut cache, &path) {
+            Ok(data) => data,
+            Err(error) => {
+                code = error;
+                continue;
+            }
+        };
+
+        let program = match Program::parse(&mut Lexer::new(data)) {
+            Ok(program) => program,
+            Err(error) => {
+                error.report().eprint(&mut cache).unwrap();
+                code = ExitCode::from(EXIT_PARSE);
+                continue;
+            }
+        };
+
+        let format = |measure: &Measure| {
+            let Measure {
+                statements,
+                max_depth,
+                complexity,
+            } = measure;
+            format!("statements={statements} depth={max_depth} complexity={complexity}")
+        };
+
+        let metrics = Metrics::build(&program.statements);
+        println!("{} <script> {}", path.display(), format(metrics.script()));
+        for function in metrics.functions() {
+            let (line, col) = data
+                .line_col(function.data.range().start)
+                .unwrap_or_default();
+            let name = function.name.as_deref().unwrap_or("<anonymous>");
+            let measure = format(&function.measure);
+            println!(
+                "{}:{}:{} {name} {measure}",
+                path.display(),
+                line + 1,
+                col + 1
+            );
+        }
+    }
+
+    code
+}
+
+fn run_conformance(fuzz: Option<usize>, seed: u64) -> ExitCode {
+    let report = conformance::run_conformance(Engine::with_prelude);
+    for result in &report.results {
+        println!("{result}");
+    }
+
+    let passed = report.passed().count();
+    println!("{passed}/{} cases passed", report.results.len());
+    let mut success = report.is_success();
+
+    let failures = conformance::run_panic_recovery(Engine::with_prelude);
+    for failure in &failures {
+        println!("FAIL panic recovery: {failure}");
+    }
+    success &= failures.is_empty();
+
+    if let Some(rounds) = fuzz {
+        let panics = conformance::run_panic_freedom(Engine::with_prelude, rounds, seed);
+        for panic in &panics {
+            println!("PANIC {}\n{}", panic.message, panic.source);
+        }
+
+        println!("{} mutated programs panicked", panics.len());
+        success &= panics.is_empty();
+    }
+
+    match success {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(EXIT_RUN),
+    }
+}
+
+#[cfg(feature = "lsp")]
+fn run_lsp() -> ExitCode {
+    let stdin = std::io::stdin().lock();
+    let stdout = std::io::stdout().lock();
+    match boba::lsp::Server::new().run(stdin, stdout) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Language server failed: {e}");
+            ExitCode::from(EXIT_IO)
+        }
     }
 }
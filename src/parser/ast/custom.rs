@@ -0,0 +1,83 @@
+use std::{any::Any, fmt::Debug, sync::Arc};
+
+use indexmap::IndexMap;
+
+use crate::{
+    cache::CacheSpan,
+    parser::{PResult, TokenSource},
+};
+
+/// The parser for a custom statement keyword
+///
+/// It is called after the keyword has been consumed, and must parse the rest of the
+/// statement up to (but not including) the end of the line.
+pub type CustomParser = Box<
+    dyn for<'source> Fn(
+            &mut dyn TokenSource<'source>,
+        ) -> PResult<CacheSpan, Arc<dyn Any + Send + Sync>>
+        + Send
+        + Sync,
+>;
+
+/// A statement introduced by a host registered keyword
+///
+/// The parsed node is opaque to boba, and is handed back to the host
+/// when the statement is evaluated by the [`Engine`](crate::Engine).
+#[derive(Clone)]
+pub struct CustomStatement {
+    pub keyword: String,
+    pub node: Arc<dyn Any + Send + Sync>,
+}
+
+impl Debug for CustomStatement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomStatement")
+            .field("keyword", &self.keyword)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CustomStatement {
+    /// Returns the host node if it is of type `T`
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.node.downcast_ref()
+    }
+}
+
+/// A set of keyword parsers used to extend the statement syntax
+///
+/// Keywords shadow identifiers of the same name at the start of a statement.
+#[derive(Default)]
+pub struct CustomParsers {
+    parsers: IndexMap<String, CustomParser>,
+}
+
+impl CustomParsers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a parser for statements starting with `keyword`
+    pub fn insert<T: Any + Send + Sync>(
+        &mut self,
+        keyword: impl Into<String>,
+        parser: impl for<'source> Fn(&mut dyn TokenSource<'source>) -> PResult<CacheSpan, T>
+            + Send
+            + Sync
+            + 'static,
+    ) {
+        let parser: CustomParser = Box::new(move |tokens| {
+            let node: Arc<dyn Any + Send + Sync> = Arc::new(parser(tokens)?);
+            Ok(node)
+        });
+        self.parsers.insert(keyword.into(), parser);
+    }
+
+    pub fn get(&self, keyword: &str) -> Option<&CustomParser> {
+        self.parsers.get(keyword)
+    }
+
+    pub fn contains(&self, keyword: &str) -> bool {
+        self.parsers.contains_key(keyword)
+    }
+}
@@ -1,31 +1,43 @@
+use std::sync::Arc;
+
 use dashu::{float::DBig, integer::IBig};
 
 use crate::{
     cache::CacheSpan,
-    parser::{ast::Node, Lexer, PError, PResult, Token},
+    parser::{
+        ast::{Func, Node},
+        PError, PResult, Token, TokenSource,
+    },
+    symbol::Symbol,
 };
 
 #[derive(Debug, Clone)]
 pub enum Expr<Data> {
     // values
     None,
-    Var(String),
+    Var(Symbol),
     Bool(bool),
     Int(IBig),
     Float(DBig),
-    String(String),
+    String(Arc<str>),
+
+    // host literals
+    Prefixed(Node<Data, String>, String),
+    Suffixed(Box<Node<Data, Self>>, Node<Data, String>),
 
     // function
-    Call(Node<Data, String>, Vec<Node<Data, Self>>),
+    Func(Arc<Func<Data>>),
+    Call(Node<Data, Symbol>, Vec<Node<Data, Self>>),
 
     // math operations
     Neg(Box<Node<Data, Self>>),
     Add(Box<Node<Data, Self>>, Box<Node<Data, Self>>),
     Sub(Box<Node<Data, Self>>, Box<Node<Data, Self>>),
     Mul(Box<Node<Data, Self>>, Box<Node<Data, Self>>),
-    Div(Box<Node<Data, Self>>, Box<Node<Data, Self>>),
-    Mod(Box<Node<Data, Self>>, Box<Node<Data, Self>>),
-    Pow(Box<Node<Data, Self>>, Box<Node<Data, Self>>),
+    // operators that can divide by zero keep the operator span for their errors
+    Div(Box<Node<Data, Self>>, Node<Data, ()>, Box<Node<Data, Self>>),
+    Mod(Box<Node<Data, Self>>, Node<Data, ()>, Box<Node<Data, Self>>),
+    Pow(Box<Node<Data, Self>>, Node<Data, ()>, Box<Node<Data, Self>>),
 
     // boolean operations
     Not(Box<Node<Data, Self>>),
@@ -39,8 +51,8 @@ pub enum Expr<Data> {
     GtEq(Box<Node<Data, Self>>, Box<Node<Data, Self>>),
 
     // assign
-    Assign(Node<Data, String>, Box<Node<Data, Self>>),
-    Walrus(Node<Data, String>, Box<Node<Data, Self>>),
+    Assign(Node<Data, Symbol>, Box<Node<Data, Self>>),
+    Walrus(Node<Data, Symbol>, Box<Node<Data, Self>>),
 
     // ternary
     Ternary(
@@ -50,6 +62,80 @@ pub enum Expr<Data> {
     ),
 }
 
+impl<Data: Clone> Expr<Data> {
+    /// Converts the data of every node in this expression with `f`, parents before children
+    pub fn map_data<New>(self, f: &mut impl FnMut(Data) -> New) -> Expr<New> {
+        match self {
+            Expr::None => Expr::None,
+            Expr::Var(ident) => Expr::Var(ident),
+            Expr::Bool(v) => Expr::Bool(v),
+            Expr::Int(v) => Expr::Int(v),
+            Expr::Float(v) => Expr::Float(v),
+            Expr::String(v) => Expr::String(v),
+            Expr::Prefixed(prefix, text) => Expr::Prefixed(prefix.map_data(f), text),
+            Expr::Suffixed(number, suffix) => {
+                let number = map_boxed(*number, f);
+                Expr::Suffixed(number, suffix.map_data(f))
+            }
+            Expr::Func(func) => Expr::Func(Arc::new(Arc::unwrap_or_clone(func).map_data(f))),
+            Expr::Call(ident, params) => {
+                let ident = ident.map_data(&mut *f);
+                let params = params.into_iter().map(|p| map_node(p, f)).collect();
+                Expr::Call(ident, params)
+            }
+            Expr::Neg(inner) => Expr::Neg(map_boxed(*inner, f)),
+            Expr::Not(inner) => Expr::Not(map_boxed(*inner, f)),
+            Expr::Add(lhs, rhs) => Expr::Add(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Sub(lhs, rhs) => Expr::Sub(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Mul(lhs, rhs) => Expr::Mul(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Div(lhs, op, rhs) => {
+                let lhs = map_boxed(*lhs, f);
+                let op = op.map_data(&mut *f);
+                Expr::Div(lhs, op, map_boxed(*rhs, f))
+            }
+            Expr::Mod(lhs, op, rhs) => {
+                let lhs = map_boxed(*lhs, f);
+                let op = op.map_data(&mut *f);
+                Expr::Mod(lhs, op, map_boxed(*rhs, f))
+            }
+            Expr::Pow(lhs, op, rhs) => {
+                let lhs = map_boxed(*lhs, f);
+                let op = op.map_data(&mut *f);
+                Expr::Pow(lhs, op, map_boxed(*rhs, f))
+            }
+            Expr::And(lhs, rhs) => Expr::And(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Or(lhs, rhs) => Expr::Or(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Eq(lhs, rhs) => Expr::Eq(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Lt(lhs, rhs) => Expr::Lt(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Gt(lhs, rhs) => Expr::Gt(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::NEq(lhs, rhs) => Expr::NEq(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::LtEq(lhs, rhs) => Expr::LtEq(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::GtEq(lhs, rhs) => Expr::GtEq(map_boxed(*lhs, f), map_boxed(*rhs, f)),
+            Expr::Assign(ident, rhs) => Expr::Assign(ident.map_data(&mut *f), map_boxed(*rhs, f)),
+            Expr::Walrus(ident, rhs) => Expr::Walrus(ident.map_data(&mut *f), map_boxed(*rhs, f)),
+            Expr::Ternary(cond, lhs, rhs) => {
+                Expr::Ternary(map_boxed(*cond, f), map_boxed(*lhs, f), map_boxed(*rhs, f))
+            }
+        }
+    }
+}
+
+/// Converts the data of an expression node and every node inside it
+pub(crate) fn map_node<Data: Clone, New>(
+    node: Node<Data, Expr<Data>>,
+    f: &mut impl FnMut(Data) -> New,
+) -> Node<New, Expr<New>> {
+    let (data, expr) = node.into_parts();
+    Node::new(f(data), expr.map_data(f))
+}
+
+fn map_boxed<Data: Clone, New>(
+    node: Node<Data, Expr<Data>>,
+    f: &mut impl FnMut(Data) -> New,
+) -> Box<Node<New, Expr<New>>> {
+    Box::new(map_node(node, f))
+}
+
 impl Expr<CacheSpan> {
     fn parse_int(
         span: CacheSpan,
@@ -71,25 +157,27 @@ impl Expr<CacheSpan> {
         }
     }
 
-    pub fn parse(tokens: &mut Lexer) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+    pub fn parse<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
         let lhs = Self::parse_atom(tokens)?;
         Self::parse_with_lhs(lhs, tokens)
     }
 
-    pub fn parse_with_lhs(
+    pub fn parse_with_lhs<'source>(
         lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
         Self::parse_assign(lhs, tokens) // start parsing at lowest precedence operator
     }
 
-    pub fn parse_var_or_fn(
-        lhs: Node<CacheSpan, String>,
-        tokens: &mut Lexer,
+    pub fn parse_var_or_fn<'source>(
+        lhs: Node<CacheSpan, Symbol>,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
         match tokens.peek() {
             Some(Err(error)) => Err(error),
-            Some(Ok((Token::OpenParen, _))) => {
+            Some(Ok((Token::OpenParen, open_span))) => {
                 tokens.next(); // consume open paren
 
                 // check for close paren or parameters
@@ -112,16 +200,10 @@ impl Expr<CacheSpan> {
                 }
 
                 // capture close paren
-                let end = match tokens.expect_next("')'")? {
-                    (Token::CloseParen, span) => span.range().end,
-                    (token, span) => {
-                        return Err(PError::UnexpectedToken {
-                            expected: format!("')'"),
-                            found: format!("'{token}'"),
-                            data: span,
-                        })
-                    }
-                };
+                let end = tokens
+                    .expect_close(Token::CloseParen, &open_span)?
+                    .range()
+                    .end;
 
                 let span = tokens.span(lhs.data().range().start..end);
                 Ok(Node::new(span, Self::Call(lhs, params)))
@@ -130,20 +212,52 @@ impl Expr<CacheSpan> {
         }
     }
 
-    pub fn parse_atom(tokens: &mut Lexer) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+    pub fn parse_atom<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        // every nested expression passes through here, so depth is tracked per atom
+        tokens.enter()?;
+        let atom = Self::parse_atom_inner(tokens);
+        tokens.exit();
+        atom
+    }
+
+    fn parse_atom_inner<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        // function definitions capture their own 'fn' token
+        if let (Token::Fn, _) = tokens.expect_peek("expression")? {
+            let (span, func) = Func::parse(tokens)?.into_parts();
+            return Ok(Node::new(span, Expr::Func(Arc::new(func))));
+        }
+
         match tokens.expect_next("expression")? {
             // values
             (Token::None, span) => Ok(Node::new(span, Expr::None)),
-            (Token::Int(str), span) => Ok(Self::parse_int(span, str)?),
-            (Token::UFloat(str), span) => Ok(Self::parse_float(span, str)?),
+            (Token::Int(str), span) => {
+                let number = Self::parse_int(span, str)?;
+                Self::parse_suffix(number, tokens)
+            }
+            (Token::UFloat(str), span) => {
+                let number = Self::parse_float(span, str)?;
+                Self::parse_suffix(number, tokens)
+            }
             (Token::Bool(bool), span) => Ok(Node::new(span.clone(), Expr::Bool(bool))),
-            (Token::String(str), span) => Ok(Node::new(span.clone(), Expr::String(str.into()))),
+            (Token::String(str), span) => Self::parse_strings(span, str, tokens),
 
             // variables and functions
-            (Token::Ident(str), span) => {
-                let ident = Node::new(span, str.to_string());
-                Self::parse_var_or_fn(ident, tokens)
-            }
+            (Token::Ident(str), span) => match tokens.peek() {
+                Some(Ok((Token::String(text), text_span)))
+                    if text_span.range().start == span.range().end =>
+                {
+                    tokens.next(); // consume string
+                    let range = span.range().start..text_span.range().end;
+                    let prefixed =
+                        Expr::Prefixed(Node::new(span, str.to_string()), text.to_string());
+                    Ok(Node::new(tokens.span(range), prefixed))
+                }
+                _ => Self::parse_var_or_fn(Node::new(span, Symbol::intern(str)), tokens),
+            },
 
             // prefix expressions
             (Token::Not, span) => {
@@ -162,14 +276,11 @@ impl Expr<CacheSpan> {
             // braces
             (Token::OpenParen, open_span) => {
                 let inner = Self::parse(tokens)?;
-                match tokens.next() {
-                    Some(Err(error)) => Err(error),
-                    Some(Ok((Token::CloseParen, close_span))) => Ok(Node::new(
-                        tokens.span(open_span.range().start..close_span.range().end),
-                        inner.into_item(),
-                    )),
-                    Some(Ok((_, _))) | None => Err(PError::UnclosedBrace { data: open_span }),
-                }
+                let close_span = tokens.expect_close(Token::CloseParen, &open_span)?;
+                Ok(Node::new(
+                    tokens.span(open_span.range().start..close_span.range().end),
+                    inner.into_item(),
+                ))
             }
 
             // error case
@@ -181,201 +292,327 @@ impl Expr<CacheSpan> {
         }
     }
 
-    pub fn parse_powers(
-        lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+    /// Joins a string literal with any string literals directly following it
+    fn parse_strings<'source>(
+        span: CacheSpan,
+        str: &'source str,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        match tokens.peek() {
-            Some(Ok((Token::Pow, _))) => (),
-            Some(Err(error)) => return Err(error),
-            _ => return Ok(lhs),
+        let mut joined = String::from(str);
+        let mut end = span.range().end;
+        while let Some(Ok((Token::String(next), next_span))) = tokens.peek() {
+            joined.push_str(next);
+            end = next_span.range().end;
+            tokens.next(); // consume string
         }
-        tokens.next();
 
-        let rhs = Expr::parse_atom(tokens)?;
-        let rhs = Self::parse_powers(rhs, tokens)?; // parse right to left
+        let span = tokens.span(span.range().start..end);
+        Ok(Node::new(span, Expr::String(joined.into())))
+    }
 
-        Ok(Node::new(
-            tokens.span(lhs.data().range().start..rhs.data().range().end),
-            Expr::Pow(Box::new(lhs), Box::new(rhs)),
-        ))
+    /// Wraps `number` in a suffixed literal if an identifier directly follows it
+    fn parse_suffix<'source>(
+        number: Node<CacheSpan, Self>,
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        match tokens.peek() {
+            Some(Ok((Token::Ident(suffix), span)))
+                if span.range().start == number.data().range().end =>
+            {
+                tokens.next(); // consume suffix
+                let range = number.data().range().start..span.range().end;
+                let suffix = Node::new(span, suffix.to_string());
+                let suffixed = Expr::Suffixed(Box::new(number), suffix);
+                Ok(Node::new(tokens.span(range), suffixed))
+            }
+            Some(Err(error)) => Err(error),
+            _ => Ok(number),
+        }
     }
 
-    pub fn parse_products(
-        lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+    /// Runs an operator `chain`, then leaves every nesting level it entered
+    ///
+    /// Each operator in a chain nests the tree one level deeper,
+    /// so the chain calls [`TokenSource::enter`] per operator and counts it in `levels`.
+    fn parse_chain<'source, T: TokenSource<'source>>(
+        tokens: &mut T,
+        chain: impl FnOnce(&mut T, &mut usize) -> PResult<CacheSpan, Node<CacheSpan, Self>>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        let op = match tokens.peek() {
-            Some(Ok((Token::Mul, _))) => Expr::Mul,
-            Some(Ok((Token::Div, _))) => Expr::Div,
-            Some(Ok((Token::Mod, _))) => Expr::Mod,
-            Some(Err(error)) => return Err(error),
-            _ => return Self::parse_powers(lhs, tokens), // try next level
-        };
-        tokens.next();
-
-        let rhs = Expr::parse_atom(tokens)?;
-        let rhs = Self::parse_powers(rhs, tokens)?; // parse higher precedence
-
-        let new_lhs = Node::new(
-            tokens.span(lhs.data().range().start..rhs.data().range().end),
-            op(Box::new(lhs), Box::new(rhs)),
-        );
-        Self::parse_with_lhs(new_lhs, tokens) // keep parsing
+        let mut levels = 0;
+        let output = chain(tokens, &mut levels);
+        for _ in 0..levels {
+            tokens.exit();
+        }
+        output
     }
 
-    pub fn parse_sums(
+    pub fn parse_powers<'source>(
         lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        let op = match tokens.peek() {
-            Some(Ok((Token::Add, _))) => Expr::Add,
-            Some(Ok((Token::Sub, _))) => Expr::Sub,
-            Some(Err(error)) => return Err(error),
-            _ => return Self::parse_products(lhs, tokens), // try next level
-        };
-        tokens.next();
-
-        let rhs = Expr::parse_atom(tokens)?;
-        let rhs = Self::parse_products(rhs, tokens)?; // parse higher precedence
-
-        let new_lhs = Node::new(
-            tokens.span(lhs.data().range().start..rhs.data().range().end),
-            op(Box::new(lhs), Box::new(rhs)),
-        );
-        Self::parse_with_lhs(new_lhs, tokens) // keep parsing
+        Self::parse_chain(tokens, |tokens, levels| {
+            let mut operands = Vec::new();
+            let mut rhs = lhs;
+            loop {
+                let op_span = match tokens.peek() {
+                    Some(Ok((Token::Pow, span))) => span,
+                    Some(Err(error)) => return Err(error),
+                    _ => break,
+                };
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+                operands.push((rhs, Node::new(op_span, ())));
+                rhs = Self::parse_atom(tokens)?;
+            }
+
+            // powers group right to left
+            while let Some((lhs, op)) = operands.pop() {
+                rhs = Node::new(
+                    tokens.span(lhs.data().range().start..rhs.data().range().end),
+                    Expr::Pow(Box::new(lhs), op, Box::new(rhs)),
+                );
+            }
+            Ok(rhs)
+        })
     }
 
-    pub fn parse_comparisons(
+    pub fn parse_products<'source>(
         lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        let op = match tokens.peek() {
-            Some(Ok((Token::Eq, _))) => Expr::Eq,
-            Some(Ok((Token::Lt, _))) => Expr::Lt,
-            Some(Ok((Token::Gt, _))) => Expr::Gt,
-            Some(Ok((Token::NEq, _))) => Expr::NEq,
-            Some(Ok((Token::LtEq, _))) => Expr::LtEq,
-            Some(Ok((Token::GtEq, _))) => Expr::GtEq,
-            Some(Err(error)) => return Err(error),
-            _ => return Self::parse_sums(lhs, tokens), // try next level
-        };
-        tokens.next();
-
-        let rhs = Expr::parse_atom(tokens)?;
-        let rhs = Self::parse_sums(rhs, tokens)?; // parse higher precedence
-
-        let new_lhs = Node::new(
-            tokens.span(lhs.data().range().start..rhs.data().range().end),
-            op(Box::new(lhs), Box::new(rhs)),
-        );
-        Self::parse_comparisons(new_lhs, tokens) // keep parsing
+        Self::parse_chain(tokens, |tokens, levels| {
+            let mut lhs = Self::parse_powers(lhs, tokens)?;
+            loop {
+                let (token, op_span) = match tokens.peek() {
+                    Some(Ok((token @ (Token::Mul | Token::Div | Token::Mod), span))) => {
+                        (token, span)
+                    }
+                    Some(Err(error)) => return Err(error),
+                    _ => return Ok(lhs),
+                };
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+
+                let rhs = Self::parse_atom(tokens)?;
+                let rhs = Box::new(Self::parse_powers(rhs, tokens)?); // parse higher precedence
+                let span = tokens.span(lhs.data().range().start..rhs.data().range().end);
+                let op = Node::new(op_span, ());
+                let expr = match token {
+                    Token::Mul => Expr::Mul(Box::new(lhs), rhs),
+                    Token::Div => Expr::Div(Box::new(lhs), op, rhs),
+                    _ => Expr::Mod(Box::new(lhs), op, rhs),
+                };
+                lhs = Node::new(span, expr);
+            }
+        })
     }
 
-    pub fn parse_ands(
+    pub fn parse_sums<'source>(
         lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        match tokens.peek() {
-            Some(Ok((Token::And, _))) => (),
-            Some(Err(error)) => return Err(error),
-            _ => return Self::parse_comparisons(lhs, tokens), // try next level
-        }
-        tokens.next();
-
-        let rhs = Expr::parse_atom(tokens)?;
-        let rhs = Self::parse_comparisons(rhs, tokens)?; // parse higher precedence
-
-        let new_lhs = Node::new(
-            tokens.span(lhs.data().range().start..rhs.data().range().end),
-            Expr::And(Box::new(lhs), Box::new(rhs)),
-        );
-        Self::parse_with_lhs(new_lhs, tokens) // keep parsing
+        Self::parse_chain(tokens, |tokens, levels| {
+            let mut lhs = Self::parse_products(lhs, tokens)?;
+            loop {
+                let op = match tokens.peek() {
+                    Some(Ok((Token::Add, _))) => Expr::Add,
+                    Some(Ok((Token::Sub, _))) => Expr::Sub,
+                    Some(Err(error)) => return Err(error),
+                    _ => return Ok(lhs),
+                };
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+
+                let rhs = Self::parse_atom(tokens)?;
+                let rhs = Self::parse_products(rhs, tokens)?; // parse higher precedence
+                lhs = Node::new(
+                    tokens.span(lhs.data().range().start..rhs.data().range().end),
+                    op(Box::new(lhs), Box::new(rhs)),
+                );
+            }
+        })
     }
 
-    pub fn parse_ors(
+    pub fn parse_comparisons<'source>(
         lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        match tokens.peek() {
-            Some(Ok((Token::And, _))) => (),
-            Some(Err(error)) => return Err(error),
-            _ => return Self::parse_ands(lhs, tokens), // try next level
-        }
-        tokens.next();
-
-        let rhs = Expr::parse_atom(tokens)?;
-        let rhs = Self::parse_ands(rhs, tokens)?; // parse higher precedence
+        Self::parse_chain(tokens, |tokens, levels| {
+            let mut lhs = Self::parse_sums(lhs, tokens)?;
+            loop {
+                let op = match tokens.peek() {
+                    Some(Ok((Token::Eq, _))) => Expr::Eq,
+                    Some(Ok((Token::Lt, _))) => Expr::Lt,
+                    Some(Ok((Token::Gt, _))) => Expr::Gt,
+                    Some(Ok((Token::NEq, _))) => Expr::NEq,
+                    Some(Ok((Token::LtEq, _))) => Expr::LtEq,
+                    Some(Ok((Token::GtEq, _))) => Expr::GtEq,
+                    Some(Err(error)) => return Err(error),
+                    _ => return Ok(lhs),
+                };
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+
+                let rhs = Self::parse_atom(tokens)?;
+                let rhs = Self::parse_sums(rhs, tokens)?; // parse higher precedence
+                lhs = Node::new(
+                    tokens.span(lhs.data().range().start..rhs.data().range().end),
+                    op(Box::new(lhs), Box::new(rhs)),
+                );
+            }
+        })
+    }
 
-        let new_lhs = Node::new(
-            tokens.span(lhs.data().range().start..rhs.data().range().end),
-            Expr::And(Box::new(lhs), Box::new(rhs)),
-        );
-        Self::parse_with_lhs(new_lhs, tokens) // keep parsing
+    pub fn parse_ands<'source>(
+        lhs: Node<CacheSpan, Self>,
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        Self::parse_chain(tokens, |tokens, levels| {
+            let mut lhs = Self::parse_comparisons(lhs, tokens)?;
+            loop {
+                match tokens.peek() {
+                    Some(Ok((Token::And, _))) => (),
+                    Some(Err(error)) => return Err(error),
+                    _ => return Ok(lhs),
+                }
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+
+                let rhs = Self::parse_atom(tokens)?;
+                let rhs = Self::parse_comparisons(rhs, tokens)?; // parse higher precedence
+                lhs = Node::new(
+                    tokens.span(lhs.data().range().start..rhs.data().range().end),
+                    Expr::And(Box::new(lhs), Box::new(rhs)),
+                );
+            }
+        })
     }
 
-    pub fn parse_ternaries(
+    pub fn parse_ors<'source>(
         lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        // check for question delimiter
-        match tokens.peek() {
-            Some(Ok((Token::Question, _))) => (),
-            Some(Err(error)) => return Err(error),
-            _ => return Self::parse_ors(lhs, tokens), // try next level
-        }
-        tokens.next();
-
-        // parse true clause
-        let true_clause = Expr::parse_atom(tokens)?;
-        let true_clause = Self::parse_ternaries(true_clause, tokens)?; // parse right to left
-
-        // check for colon delimiter
-        match tokens.expect_next("ternary delimiter ':'")? {
-            (Token::Colon, _) => (),
-            (token, span) => {
-                return Err(PError::UnexpectedToken {
-                    expected: format!("ternary delimiter ':'"),
-                    found: format!("'{token}'"),
-                    data: span,
-                })
+        Self::parse_chain(tokens, |tokens, levels| {
+            let mut lhs = Self::parse_ands(lhs, tokens)?;
+            loop {
+                match tokens.peek() {
+                    Some(Ok((Token::Or, _))) => (),
+                    Some(Err(error)) => return Err(error),
+                    _ => return Ok(lhs),
+                }
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+
+                let rhs = Self::parse_atom(tokens)?;
+                let rhs = Self::parse_ands(rhs, tokens)?; // parse higher precedence
+                lhs = Node::new(
+                    tokens.span(lhs.data().range().start..rhs.data().range().end),
+                    Expr::Or(Box::new(lhs), Box::new(rhs)),
+                );
             }
-        }
+        })
+    }
 
-        // parse false clause
-        let false_clause = Expr::parse_atom(tokens)?;
-        let false_clause = Self::parse_ternaries(false_clause, tokens)?; // parse right to left
+    pub fn parse_ternaries<'source>(
+        lhs: Node<CacheSpan, Self>,
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        Self::parse_chain(tokens, |tokens, levels| {
+            // a chain of ternaries in false clauses is collected, then grouped right to left
+            let mut branches = Vec::new();
+            let mut cond = Self::parse_ors(lhs, tokens)?;
+            loop {
+                // check for question delimiter
+                match tokens.peek() {
+                    Some(Ok((Token::Question, _))) => (),
+                    Some(Err(error)) => return Err(error),
+                    _ => break,
+                }
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+
+                // parse true clause, which may nest another ternary
+                tokens.enter()?;
+                let true_clause = Expr::parse_atom(tokens)
+                    .and_then(|true_clause| Self::parse_ternaries(true_clause, tokens));
+                tokens.exit();
+                let true_clause = true_clause?;
+
+                // check for colon delimiter
+                match tokens.expect_next("ternary delimiter ':'")? {
+                    (Token::Colon, _) => (),
+                    (token, span) => {
+                        return Err(PError::UnexpectedToken {
+                            expected: format!("ternary delimiter ':'"),
+                            found: format!("'{token}'"),
+                            data: span,
+                        })
+                    }
+                }
+
+                // parse false clause, which continues the chain
+                branches.push((cond, true_clause));
+                cond = Expr::parse_atom(tokens)?;
+                cond = Self::parse_ors(cond, tokens)?;
+            }
 
-        // build node
-        Ok(Node::new(
-            tokens.span(lhs.data().range().start..false_clause.data().range().end),
-            Expr::Ternary(Box::new(lhs), Box::new(true_clause), Box::new(false_clause)),
-        ))
+            // build nodes, with the last false clause innermost
+            let mut false_clause = cond;
+            while let Some((cond, true_clause)) = branches.pop() {
+                false_clause = Node::new(
+                    tokens.span(cond.data().range().start..false_clause.data().range().end),
+                    Expr::Ternary(
+                        Box::new(cond),
+                        Box::new(true_clause),
+                        Box::new(false_clause),
+                    ),
+                );
+            }
+            Ok(false_clause)
+        })
     }
 
-    pub fn parse_assign(
+    pub fn parse_assign<'source>(
         lhs: Node<CacheSpan, Self>,
-        tokens: &mut Lexer,
+        tokens: &mut impl TokenSource<'source>,
     ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
-        let (op, assign_span) = match tokens.peek() {
-            Some(Ok((Token::Assign, span))) => (Expr::Assign as fn(_, _) -> _, span),
-            Some(Ok((Token::Walrus, span))) => (Expr::Walrus as fn(_, _) -> _, span),
-            Some(Err(error)) => return Err(error),
-            _ => return Self::parse_ternaries(lhs, tokens), // try next level
-        };
-        tokens.next();
-
-        let lhs = match lhs.into_parts() {
-            (span, Expr::Var(var)) => Node::new(span, var),
-            (_, _) => return Err(PError::InvalidAssignment { data: assign_span }),
-        };
-
-        let rhs = Self::parse_atom(tokens)?;
-        let rhs = Self::parse_assign(rhs, tokens)?; // parse right to left
-
-        Ok(Node::new(
-            tokens.span(lhs.data().range().start..rhs.data().range().end),
-            op(lhs, Box::new(rhs)),
-        ))
+        Self::parse_chain(tokens, |tokens, levels| {
+            // a chain of assignments is collected, then grouped right to left
+            let mut targets = Vec::new();
+            let mut rhs = lhs;
+            loop {
+                let (op, assign_span) = match tokens.peek() {
+                    Some(Ok((Token::Assign, span))) => (Expr::Assign as fn(_, _) -> _, span),
+                    Some(Ok((Token::Walrus, span))) => (Expr::Walrus as fn(_, _) -> _, span),
+                    Some(Err(error)) => return Err(error),
+                    _ => break,
+                };
+                tokens.next();
+                tokens.enter()?;
+                *levels += 1;
+
+                match rhs.into_parts() {
+                    (span, Expr::Var(var)) => targets.push((op, Node::new(span, var))),
+                    (_, _) => return Err(PError::InvalidAssignment { data: assign_span }),
+                };
+                rhs = Self::parse_atom(tokens)?;
+            }
+
+            let mut rhs = Self::parse_ternaries(rhs, tokens)?; // try next level
+            while let Some((op, lhs)) = targets.pop() {
+                rhs = Node::new(
+                    tokens.span(lhs.data().range().start..rhs.data().range().end),
+                    op(lhs, Box::new(rhs)),
+                );
+            }
+            Ok(rhs)
+        })
     }
 }
@@ -1,18 +1,38 @@
 use crate::{
     cache::CacheSpan,
-    parser::{Lexer, PError, PResult, Token},
+    parser::{PError, PResult, Token, TokenSource},
+    symbol::Symbol,
 };
 
-use super::{Node, Statement};
+use super::{statement::map_statement, Expr, Node, Statement};
 
 #[derive(Debug, Clone)]
 pub struct Func<Data> {
-    pub params: Vec<Node<Data, String>>,
+    pub params: Vec<Node<Data, Symbol>>,
     pub body: Vec<Node<Data, Statement<Data>>>,
 }
 
+impl<Data: Clone> Func<Data> {
+    fn new(params: Vec<Node<Data, Symbol>>) -> Self {
+        Self {
+            params,
+            body: Vec::new(),
+        }
+    }
+
+    /// Converts the data of every node in this function with `f`, parents before children
+    pub fn map_data<New>(self, f: &mut impl FnMut(Data) -> New) -> Func<New> {
+        let params = self.params.into_iter().map(|p| p.map_data(&mut *f));
+        let params = params.collect();
+        let body = self.body.into_iter().map(|s| map_statement(s, f)).collect();
+        Func { params, body }
+    }
+}
+
 impl Func<CacheSpan> {
-    pub fn parse(tokens: &mut Lexer) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+    pub fn parse<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
         // capture fn token
         let start = match tokens.expect_next("'fn'")? {
             (Token::Fn, span) => span.range().start,
@@ -26,8 +46,8 @@ impl Func<CacheSpan> {
         };
 
         // capture open paren
-        match tokens.expect_next("'('")? {
-            (Token::OpenParen, _) => (),
+        let open_span = match tokens.expect_next("'('")? {
+            (Token::OpenParen, span) => span,
             (token, span) => {
                 return Err(PError::UnexpectedToken {
                     expected: format!("'('"),
@@ -41,7 +61,7 @@ impl Func<CacheSpan> {
         let mut params = Vec::new();
         while let (Token::Ident(param), span) = tokens.expect_peek("parameter or ')'")? {
             // push parameter
-            params.push(Node::new(span.clone(), param.to_string()));
+            params.push(Node::new(span.clone(), Symbol::intern(param)));
             tokens.next(); // consume ident
 
             // capture comma
@@ -55,16 +75,7 @@ impl Func<CacheSpan> {
         }
 
         // capture close paren
-        match tokens.expect_next("')'")? {
-            (Token::CloseParen, _) => (),
-            (token, span) => {
-                return Err(PError::UnexpectedToken {
-                    expected: format!("')'"),
-                    found: format!("'{token}'"),
-                    data: span,
-                })
-            }
-        };
+        tokens.expect_close(Token::CloseParen, &open_span)?;
 
         // capture colon token
         let end = match tokens.expect_next("':'")? {
@@ -79,13 +90,7 @@ impl Func<CacheSpan> {
         };
 
         // create output
-        let mut output = Node::new(
-            tokens.span(start..end),
-            Self {
-                params,
-                body: Vec::new(),
-            },
-        );
+        let mut output = Node::new(tokens.span(start..end), Self::new(params));
 
         // return early if end of line is found
         match tokens.peek() {
@@ -97,10 +102,13 @@ impl Func<CacheSpan> {
             },
         }
 
-        // capture single statement
-        let statement = Statement::parse(tokens)?;
-        tokens.expect_line_end()?;
-        output.body.push(statement);
+        // capture single expression body
+        // the line end is left for the enclosing statement to consume
+        let expr = Expr::parse(tokens)?;
+        *output.data_mut() = tokens.span(start..expr.data().range().end);
+        output
+            .body
+            .push(Node::new(expr.data().clone(), Statement::Expr(expr)));
         Ok(output)
     }
 }
@@ -2,10 +2,11 @@ use ariadne::Span;
 
 use crate::{
     cache::CacheSpan,
-    parser::{Lexer, PError, PResult, Token},
+    parser::{PError, PResult, Token, TokenSource},
+    symbol::Symbol,
 };
 
-use super::{Expr, Node};
+use super::{expr::map_node, Expr, Node};
 
 #[derive(Debug, Clone, Copy)]
 pub enum InitStyle {
@@ -17,12 +18,25 @@ pub enum InitStyle {
 #[derive(Debug, Clone)]
 pub struct Init<Data> {
     pub style: Node<Data, InitStyle>,
-    pub ident: Node<Data, String>,
+    pub ident: Node<Data, Symbol>,
     pub expr: Node<Data, Expr<Data>>,
 }
 
+impl<Data: Clone> Init<Data> {
+    /// Converts the data of every node in this statement with `f`, parents before children
+    pub fn map_data<New>(self, f: &mut impl FnMut(Data) -> New) -> Init<New> {
+        Init {
+            style: self.style.map_data(&mut *f),
+            ident: self.ident.map_data(&mut *f),
+            expr: map_node(self.expr, f),
+        }
+    }
+}
+
 impl Init<CacheSpan> {
-    pub fn parse(tokens: &mut Lexer) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+    pub fn parse<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
         let style = match tokens.expect_next("'let', 'const', or 'static'")? {
             (Token::Let, span) => Node::new(span, InitStyle::Let),
             (Token::Const, span) => Node::new(span, InitStyle::Const),
@@ -37,7 +51,7 @@ impl Init<CacheSpan> {
         };
 
         let ident = match tokens.expect_next("identifier")? {
-            (Token::Ident(ident), span) => Node::new(span, ident.to_string()),
+            (Token::Ident(ident), span) => Node::new(span, Symbol::intern(ident)),
             (token, span) => {
                 return Err(PError::UnexpectedToken {
                     expected: format!("identifier"),
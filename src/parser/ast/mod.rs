@@ -1,11 +1,19 @@
+pub mod custom;
 pub mod expr;
 pub mod func;
 pub mod init;
 pub mod node;
+pub mod program;
 pub mod statement;
+pub mod visit;
+pub mod with;
 
+pub use custom::{CustomParsers, CustomStatement};
 pub use expr::Expr;
 pub use func::Func;
 pub use init::Init;
 pub use node::Node;
+pub use program::{Program, SharedProgram};
 pub use statement::Statement;
+pub use visit::{Visit, VisitMut};
+pub use with::With;
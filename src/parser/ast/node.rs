@@ -44,4 +44,17 @@ impl<Data, Item> Node<Data, Item> {
     pub fn into_parts(self) -> (Data, Item) {
         (self.data, self.item)
     }
+
+    /// Replaces the data of this node, leaving the item untouched
+    ///
+    /// To convert the data of a whole tree, use the `map_data` method of the item,
+    /// like [`Program::map_data`](super::Program::map_data).
+    pub fn map_data<New>(self, f: impl FnOnce(Data) -> New) -> Node<New, Item> {
+        Node::new(f(self.data), self.item)
+    }
+
+    /// Replaces the item of this node, keeping its data
+    pub fn map_item<New>(self, f: impl FnOnce(Item) -> New) -> Node<Data, New> {
+        Node::new(self.data, f(self.item))
+    }
 }
@@ -0,0 +1,303 @@
+use std::{
+    marker::PhantomData,
+    ops::{Deref, Range},
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+};
+
+use hashbrown::HashSet;
+
+use crate::{
+    cache::CacheSpan,
+    error::panic_message,
+    parser::{Lexer, PError, PResult, Token, TokenSource},
+    BobaCache, BobaError,
+};
+
+use super::{
+    statement::map_statement,
+    visit::{walk_expr_mut, VisitMut},
+    CustomParsers, Expr, Node, Statement,
+};
+
+/// A full source file made up of statements
+#[derive(Debug, Clone)]
+pub struct Program<Data> {
+    pub statements: Vec<Node<Data, Statement<Data>>>,
+}
+
+impl<Data: Clone> Program<Data> {
+    /// Converts the data of every node in the program with `f`, parents before children
+    ///
+    /// Hosts with their own tooling can use this to attach their own metadata to each node.
+    pub fn map_data<New>(self, f: &mut impl FnMut(Data) -> New) -> Program<New> {
+        let statements = self.statements.into_iter();
+        let statements = statements.map(|s| map_statement(s, f)).collect();
+        Program { statements }
+    }
+
+    /// Drops the data of every node, for hosts that do not need diagnostics
+    ///
+    /// The resulting program can be run with an `Engine<()>`,
+    /// at the cost of errors no longer pointing at the source.
+    pub fn strip_data(self) -> Program<()> {
+        self.map_data(&mut |_| ())
+    }
+
+    /// Makes identical string literals share a single allocation
+    ///
+    /// Returns the number of literals that now share storage with an earlier literal.
+    pub fn dedup_strings(&mut self) -> usize {
+        let mut pool = StringPool::default();
+        for statement in self.statements.iter_mut() {
+            pool.visit_statement_mut(statement);
+        }
+        pool.shared
+    }
+
+    /// Replaces additions of two string literals with the joined literal
+    ///
+    /// Chains like `'a' + 'b' + 'c'` fold into a single literal, since addition groups
+    /// to the left. Returns the number of additions that were removed.
+    pub fn fold_strings(&mut self) -> usize {
+        let mut folder = StringFolder::default();
+        for statement in self.statements.iter_mut() {
+            folder.visit_statement_mut(statement);
+        }
+        folder.folded
+    }
+}
+
+impl Program<CacheSpan> {
+    /// Parses statements until the end of input, skipping blank lines
+    ///
+    /// A panic while parsing a statement is returned as [`PError::Internal`].
+    pub fn parse<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        Self::parse_custom(tokens, &CustomParsers::new())
+    }
+
+    /// Parses statements like [`Program::parse`], using `parsers` for custom statements
+    pub fn parse_custom<'source>(
+        tokens: &mut impl TokenSource<'source>,
+        parsers: &CustomParsers,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        let start = tokens.pos();
+        let mut statements = Vec::new();
+        loop {
+            match tokens.peek() {
+                None => break,
+                Some(Err(error)) => return Err(error),
+                Some(Ok((Token::Newline, _))) => {
+                    tokens.next(); // consume blank line
+                }
+                Some(Ok(_)) => statements.push(parse_statement(tokens, parsers)?),
+            }
+        }
+
+        let span = tokens.span(start..tokens.pos());
+        Ok(Node::new(span, Self { statements }))
+    }
+
+    /// Parses statements like [`Program::parse`], but recovers from errors
+    ///
+    /// When a statement fails to parse, the rest of its line is skipped and parsing
+    /// continues with the next statement. The returned program only holds the
+    /// statements that parsed successfully, alongside every error that was found.
+    pub fn parse_recover<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> (Node<CacheSpan, Self>, Vec<PError<CacheSpan>>) {
+        Self::parse_custom_recover(tokens, &CustomParsers::new())
+    }
+
+    /// Parses statements like [`Program::parse_recover`], using `parsers` for custom statements
+    pub fn parse_custom_recover<'source>(
+        tokens: &mut impl TokenSource<'source>,
+        parsers: &CustomParsers,
+    ) -> (Node<CacheSpan, Self>, Vec<PError<CacheSpan>>) {
+        let mut tokens = LineTracker {
+            tokens,
+            line_end: true,
+            _source: PhantomData,
+        };
+
+        let start = tokens.pos();
+        let mut statements = Vec::new();
+        let mut errors = Vec::new();
+        loop {
+            match tokens.peek() {
+                None => break,
+                Some(Ok((Token::Newline, _))) => {
+                    tokens.next(); // consume blank line
+                }
+                Some(Ok(_)) => match parse_statement(&mut tokens, parsers) {
+                    Ok(statement) => statements.push(statement),
+                    Err(error) => {
+                        errors.push(error);
+                        tokens.skip_line();
+                    }
+                },
+                Some(Err(error)) => {
+                    tokens.next(); // consume invalid token
+                    errors.push(error);
+                    tokens.skip_line();
+                }
+            }
+        }
+
+        let span = tokens.span(start..tokens.pos());
+        (Node::new(span, Self { statements }), errors)
+    }
+
+    /// Parses the source without evaluating it, returning every diagnostic found
+    pub fn check<'source>(tokens: &mut impl TokenSource<'source>) -> Vec<PError<CacheSpan>> {
+        Self::parse_recover(tokens).1
+    }
+}
+
+/// A parsed program that many engines can run at the same time
+///
+/// Cloning only bumps a reference count, so a server can parse a script once and hand it
+/// to an engine per request with [`Engine::eval_shared`](crate::Engine::eval_shared).
+/// Spans still point into the cache the program was parsed with, so errors from every
+/// engine running it are reported against that cache rather than the engine's own.
+#[derive(Debug, Clone)]
+pub struct SharedProgram<Data>(Arc<Program<Data>>);
+
+impl<Data> Deref for SharedProgram<Data> {
+    type Target = Program<Data>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<Data> From<Program<Data>> for SharedProgram<Data> {
+    fn from(program: Program<Data>) -> Self {
+        Self(Arc::new(program))
+    }
+}
+
+impl SharedProgram<CacheSpan> {
+    /// Stores `source` in `cache` and parses it, folding and sharing string literals
+    /// the same way [`Engine::eval_str`](crate::Engine::eval_str) does
+    pub fn parse(
+        cache: &mut BobaCache,
+        label: impl Into<String>,
+        source: impl Into<String>,
+    ) -> Result<Self, BobaError> {
+        let data = cache.store(label, source)?;
+        let mut program = Program::parse(&mut Lexer::new(data))?.into_item();
+        program.fold_strings();
+        program.dedup_strings();
+        Ok(program.into())
+    }
+}
+
+/// Parses a single statement, turning a panic in the parser into [`PError::Internal`]
+fn parse_statement<'source>(
+    tokens: &mut impl TokenSource<'source>,
+    parsers: &CustomParsers,
+) -> PResult<CacheSpan, Node<CacheSpan, Statement<CacheSpan>>> {
+    let start = tokens.pos();
+    let parse = AssertUnwindSafe(|| Statement::parse_custom(tokens, parsers));
+    match panic::catch_unwind(parse) {
+        Ok(result) => result,
+        Err(payload) => Err(PError::Internal {
+            message: panic_message(payload),
+            data: tokens.span(start..tokens.pos()),
+        }),
+    }
+}
+
+#[derive(Default)]
+struct StringPool {
+    strings: HashSet<Arc<str>>,
+    shared: usize,
+}
+
+impl<Data: Clone> VisitMut<Data> for StringPool {
+    fn visit_expr_mut(&mut self, expr: &mut Node<Data, Expr<Data>>) {
+        let Expr::String(string) = &mut **expr else {
+            return walk_expr_mut(self, expr);
+        };
+
+        match self.strings.get(string) {
+            Some(shared) => {
+                *string = shared.clone();
+                self.shared += 1;
+            }
+            None => {
+                self.strings.insert(string.clone());
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct StringFolder {
+    folded: usize,
+}
+
+impl<Data: Clone> VisitMut<Data> for StringFolder {
+    fn visit_expr_mut(&mut self, expr: &mut Node<Data, Expr<Data>>) {
+        walk_expr_mut(self, expr);
+        if let Expr::Add(lhs, rhs) = &**expr {
+            if let (Expr::String(lhs), Expr::String(rhs)) = (&***lhs, &***rhs) {
+                **expr = Expr::String(format!("{lhs}{rhs}").into());
+                self.folded += 1;
+            }
+        }
+    }
+}
+
+/// Tracks if the last consumed token ended a line, so recovery can find the next statement
+struct LineTracker<'a, 'source, T> {
+    tokens: &'a mut T,
+    line_end: bool,
+    _source: PhantomData<Token<'source>>,
+}
+
+impl<'source, T: TokenSource<'source>> LineTracker<'_, 'source, T> {
+    /// Consumes tokens until the end of the current line
+    fn skip_line(&mut self) {
+        while !self.line_end {
+            if self.next().is_none() {
+                break;
+            }
+        }
+    }
+}
+
+impl<'source, T: TokenSource<'source>> Iterator for LineTracker<'_, 'source, T> {
+    type Item = PResult<CacheSpan, (Token<'source>, CacheSpan)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.tokens.next();
+        self.line_end = matches!(next, Some(Ok((Token::Newline, _))));
+        next
+    }
+}
+
+impl<'source, T: TokenSource<'source>> TokenSource<'source> for LineTracker<'_, 'source, T> {
+    fn peek(&mut self) -> Option<PResult<CacheSpan, (Token<'source>, CacheSpan)>> {
+        self.tokens.peek()
+    }
+
+    fn pos(&self) -> usize {
+        self.tokens.pos()
+    }
+
+    fn span(&self, range: Range<usize>) -> CacheSpan {
+        self.tokens.span(range)
+    }
+
+    fn enter(&mut self) -> PResult<CacheSpan, ()> {
+        self.tokens.enter()
+    }
+
+    fn exit(&mut self) {
+        self.tokens.exit()
+    }
+}
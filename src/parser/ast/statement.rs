@@ -1,23 +1,77 @@
 use crate::{
     cache::CacheSpan,
-    parser::{Lexer, PResult, Token},
+    parser::{PResult, Token, TokenSource},
 };
 
-use super::{Expr, Init, Node};
+use super::{expr::map_node, CustomParsers, CustomStatement, Expr, Init, Node, With};
 
 #[derive(Debug, Clone)]
 pub enum Statement<Data> {
     Init(Node<Data, Init<Data>>),
     Expr(Node<Data, Expr<Data>>),
+    Custom(Node<Data, CustomStatement>),
+    With(Node<Data, With<Data>>),
+}
+
+impl<Data: Clone> Statement<Data> {
+    /// Converts the data of every node in this statement with `f`, parents before children
+    pub fn map_data<New>(self, f: &mut impl FnMut(Data) -> New) -> Statement<New> {
+        match self {
+            Statement::Init(init) => {
+                let (data, init) = init.into_parts();
+                Statement::Init(Node::new(f(data), init.map_data(f)))
+            }
+            Statement::Expr(expr) => Statement::Expr(map_node(expr, f)),
+            Statement::Custom(custom) => Statement::Custom(custom.map_data(f)),
+            Statement::With(with) => {
+                let (data, with) = with.into_parts();
+                Statement::With(Node::new(f(data), with.map_data(f)))
+            }
+        }
+    }
+}
+
+/// Converts the data of a statement node and every node inside it
+pub(crate) fn map_statement<Data: Clone, New>(
+    node: Node<Data, Statement<Data>>,
+    f: &mut impl FnMut(Data) -> New,
+) -> Node<New, Statement<New>> {
+    let (data, statement) = node.into_parts();
+    Node::new(f(data), statement.map_data(f))
 }
 
 impl Statement<CacheSpan> {
-    pub fn parse(tokens: &mut Lexer) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+    pub fn parse<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        Self::parse_custom(tokens, &CustomParsers::new())
+    }
+
+    /// Parses a statement, using `parsers` for statements that start with a custom keyword
+    pub fn parse_custom<'source>(
+        tokens: &mut impl TokenSource<'source>,
+        parsers: &CustomParsers,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
         match tokens.expect_peek("assignment or expression")? {
             (Token::Let, _) | (Token::Const, _) | (Token::Static, _) => {
                 let init = Init::parse(tokens)?;
                 Ok(Node::new(init.data().clone(), Self::Init(init)))
             }
+            (Token::With, _) => {
+                let with = With::parse(tokens)?;
+                Ok(Node::new(with.data().clone(), Self::With(with)))
+            }
+            (Token::Ident(keyword), span) if parsers.contains(keyword) => {
+                tokens.next(); // consume keyword
+                let parser = parsers.get(keyword).expect("keyword exists");
+                let node = parser(tokens)?;
+                let span = tokens.span(span.range().start..tokens.pos());
+                tokens.expect_line_end()?;
+
+                let keyword = keyword.to_string();
+                let custom = Node::new(span.clone(), CustomStatement { keyword, node });
+                Ok(Node::new(span, Self::Custom(custom)))
+            }
             _ => {
                 let expr = Expr::parse(tokens)?;
                 tokens.expect_line_end()?;
@@ -0,0 +1,215 @@
+//! Traversal of parsed programs
+//!
+//! [`Visit`] and [`VisitMut`] walk every statement, expression, function and identifier
+//! in a tree. Implementors override only the methods for the nodes they care about, and
+//! call the matching `walk` function from an override to keep descending into children.
+//! New kinds of nodes are handled by the default walkers, so tools built on these traits
+//! keep compiling as the language grows.
+
+use std::sync::Arc;
+
+use crate::symbol::Symbol;
+
+use super::{Expr, Func, Node, Statement};
+
+/// Visits the nodes of a tree by reference
+pub trait Visit<Data> {
+    fn visit_statement(&mut self, statement: &Node<Data, Statement<Data>>) {
+        walk_statement(self, statement);
+    }
+
+    fn visit_expr(&mut self, expr: &Node<Data, Expr<Data>>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_func(&mut self, func: &Func<Data>) {
+        walk_func(self, func);
+    }
+
+    /// Visits an identifier that is bound, assigned or called
+    ///
+    /// Variables that are only read are [`Expr::Var`] expressions, which have no separate node.
+    fn visit_ident(&mut self, _ident: &Node<Data, Symbol>) {}
+}
+
+/// Visits each child of `statement`
+pub fn walk_statement<Data, V: Visit<Data> + ?Sized>(
+    visitor: &mut V,
+    statement: &Node<Data, Statement<Data>>,
+) {
+    match &**statement {
+        Statement::Init(init) => {
+            visitor.visit_ident(&init.ident);
+            visitor.visit_expr(&init.expr);
+        }
+        Statement::Expr(expr) => visitor.visit_expr(expr),
+        // custom statements are opaque outside of their own handlers
+        Statement::Custom(_) => (),
+        Statement::With(with) => {
+            visitor.visit_expr(&with.resource);
+            visitor.visit_ident(&with.ident);
+            visitor.visit_expr(&with.body);
+        }
+    }
+}
+
+/// Visits each child of `expr`, left to right
+pub fn walk_expr<Data, V: Visit<Data> + ?Sized>(visitor: &mut V, expr: &Node<Data, Expr<Data>>) {
+    match &**expr {
+        Expr::None
+        | Expr::Var(_)
+        | Expr::Bool(_)
+        | Expr::Int(_)
+        | Expr::Float(_)
+        | Expr::String(_)
+        | Expr::Prefixed(..) => (),
+        Expr::Func(func) => visitor.visit_func(func),
+        Expr::Call(ident, params) => {
+            visitor.visit_ident(ident);
+            for param in params {
+                visitor.visit_expr(param);
+            }
+        }
+        Expr::Suffixed(inner, _) | Expr::Neg(inner) | Expr::Not(inner) => visitor.visit_expr(inner),
+        Expr::Assign(ident, rhs) | Expr::Walrus(ident, rhs) => {
+            visitor.visit_ident(ident);
+            visitor.visit_expr(rhs);
+        }
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, _, rhs)
+        | Expr::Mod(lhs, _, rhs)
+        | Expr::Pow(lhs, _, rhs)
+        | Expr::And(lhs, rhs)
+        | Expr::Or(lhs, rhs)
+        | Expr::Eq(lhs, rhs)
+        | Expr::Lt(lhs, rhs)
+        | Expr::Gt(lhs, rhs)
+        | Expr::NEq(lhs, rhs)
+        | Expr::LtEq(lhs, rhs)
+        | Expr::GtEq(lhs, rhs) => {
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+        Expr::Ternary(cond, lhs, rhs) => {
+            visitor.visit_expr(cond);
+            visitor.visit_expr(lhs);
+            visitor.visit_expr(rhs);
+        }
+    }
+}
+
+/// Visits the parameters of `func`, then each statement of its body
+pub fn walk_func<Data, V: Visit<Data> + ?Sized>(visitor: &mut V, func: &Func<Data>) {
+    for param in &func.params {
+        visitor.visit_ident(param);
+    }
+    for statement in &func.body {
+        visitor.visit_statement(statement);
+    }
+}
+
+/// Visits the nodes of a tree by mutable reference, so they can be rewritten in place
+pub trait VisitMut<Data: Clone> {
+    fn visit_statement_mut(&mut self, statement: &mut Node<Data, Statement<Data>>) {
+        walk_statement_mut(self, statement);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Node<Data, Expr<Data>>) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_func_mut(&mut self, func: &mut Func<Data>) {
+        walk_func_mut(self, func);
+    }
+
+    /// Visits an identifier that is bound, assigned or called
+    fn visit_ident_mut(&mut self, _ident: &mut Node<Data, Symbol>) {}
+}
+
+/// Visits each child of `statement`
+pub fn walk_statement_mut<Data: Clone, V: VisitMut<Data> + ?Sized>(
+    visitor: &mut V,
+    statement: &mut Node<Data, Statement<Data>>,
+) {
+    match &mut **statement {
+        Statement::Init(init) => {
+            visitor.visit_ident_mut(&mut init.ident);
+            visitor.visit_expr_mut(&mut init.expr);
+        }
+        Statement::Expr(expr) => visitor.visit_expr_mut(expr),
+        Statement::Custom(_) => (),
+        Statement::With(with) => {
+            visitor.visit_expr_mut(&mut with.resource);
+            visitor.visit_ident_mut(&mut with.ident);
+            visitor.visit_expr_mut(&mut with.body);
+        }
+    }
+}
+
+/// Visits each child of `expr`, left to right
+pub fn walk_expr_mut<Data: Clone, V: VisitMut<Data> + ?Sized>(
+    visitor: &mut V,
+    expr: &mut Node<Data, Expr<Data>>,
+) {
+    match &mut **expr {
+        Expr::None
+        | Expr::Var(_)
+        | Expr::Bool(_)
+        | Expr::Int(_)
+        | Expr::Float(_)
+        | Expr::String(_)
+        | Expr::Prefixed(..) => (),
+        // a function shared with a running engine is copied before it is rewritten
+        Expr::Func(func) => visitor.visit_func_mut(Arc::make_mut(func)),
+        Expr::Call(ident, params) => {
+            visitor.visit_ident_mut(ident);
+            for param in params {
+                visitor.visit_expr_mut(param);
+            }
+        }
+        Expr::Suffixed(inner, _) | Expr::Neg(inner) | Expr::Not(inner) => {
+            visitor.visit_expr_mut(inner)
+        }
+        Expr::Assign(ident, rhs) | Expr::Walrus(ident, rhs) => {
+            visitor.visit_ident_mut(ident);
+            visitor.visit_expr_mut(rhs);
+        }
+        Expr::Add(lhs, rhs)
+        | Expr::Sub(lhs, rhs)
+        | Expr::Mul(lhs, rhs)
+        | Expr::Div(lhs, _, rhs)
+        | Expr::Mod(lhs, _, rhs)
+        | Expr::Pow(lhs, _, rhs)
+        | Expr::And(lhs, rhs)
+        | Expr::Or(lhs, rhs)
+        | Expr::Eq(lhs, rhs)
+        | Expr::Lt(lhs, rhs)
+        | Expr::Gt(lhs, rhs)
+        | Expr::NEq(lhs, rhs)
+        | Expr::LtEq(lhs, rhs)
+        | Expr::GtEq(lhs, rhs) => {
+            visitor.visit_expr_mut(lhs);
+            visitor.visit_expr_mut(rhs);
+        }
+        Expr::Ternary(cond, lhs, rhs) => {
+            visitor.visit_expr_mut(cond);
+            visitor.visit_expr_mut(lhs);
+            visitor.visit_expr_mut(rhs);
+        }
+    }
+}
+
+/// Visits the parameters of `func`, then each statement of its body
+pub fn walk_func_mut<Data: Clone, V: VisitMut<Data> + ?Sized>(
+    visitor: &mut V,
+    func: &mut Func<Data>,
+) {
+    for param in &mut func.params {
+        visitor.visit_ident_mut(param);
+    }
+    for statement in &mut func.body {
+        visitor.visit_statement_mut(statement);
+    }
+}
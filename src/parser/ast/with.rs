@@ -0,0 +1,95 @@
+use ariadne::Span;
+
+use crate::{
+    cache::CacheSpan,
+    parser::{PError, PResult, Token, TokenSource},
+    symbol::Symbol,
+};
+
+use super::{expr::map_node, Expr, Node};
+
+/// Binds a host resource for the length of a single expression
+///
+/// `with resource as ident: body` evaluates `body` with the resource bound to `ident`,
+/// then closes the resource even if the body failed.
+#[derive(Debug, Clone)]
+pub struct With<Data> {
+    pub resource: Node<Data, Expr<Data>>,
+    pub ident: Node<Data, Symbol>,
+    pub body: Node<Data, Expr<Data>>,
+}
+
+impl<Data: Clone> With<Data> {
+    /// Converts the data of every node in this statement with `f`, parents before children
+    pub fn map_data<New>(self, f: &mut impl FnMut(Data) -> New) -> With<New> {
+        With {
+            resource: map_node(self.resource, f),
+            ident: self.ident.map_data(&mut *f),
+            body: map_node(self.body, f),
+        }
+    }
+}
+
+impl With<CacheSpan> {
+    pub fn parse<'source>(
+        tokens: &mut impl TokenSource<'source>,
+    ) -> PResult<CacheSpan, Node<CacheSpan, Self>> {
+        let start = match tokens.expect_next("'with'")? {
+            (Token::With, span) => span.start(),
+            (token, span) => {
+                return Err(PError::UnexpectedToken {
+                    expected: "'with'".to_string(),
+                    found: format!("'{token}'"),
+                    data: span,
+                })
+            }
+        };
+
+        let resource = Expr::parse(tokens)?;
+
+        match tokens.expect_next("'as'")? {
+            (Token::As, _) => (),
+            (token, span) => {
+                return Err(PError::UnexpectedToken {
+                    expected: "'as'".to_string(),
+                    found: format!("'{token}'"),
+                    data: span,
+                })
+            }
+        }
+
+        let ident = match tokens.expect_next("identifier")? {
+            (Token::Ident(ident), span) => Node::new(span, Symbol::intern(ident)),
+            (token, span) => {
+                return Err(PError::UnexpectedToken {
+                    expected: "identifier".to_string(),
+                    found: format!("'{token}'"),
+                    data: span,
+                })
+            }
+        };
+
+        match tokens.expect_next("':'")? {
+            (Token::Colon, _) => (),
+            (token, span) => {
+                return Err(PError::UnexpectedToken {
+                    expected: "':'".to_string(),
+                    found: format!("'{token}'"),
+                    data: span,
+                })
+            }
+        }
+
+        let body = Expr::parse(tokens)?;
+        tokens.expect_line_end()?;
+
+        Ok(Node::new(
+            tokens.span(start..body.data().end()),
+            Self {
+                resource,
+                ident,
+                body,
+            },
+        ))
+    }
+}
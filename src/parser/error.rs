@@ -32,6 +32,8 @@ pub enum PError<Data> {
     },
     UnclosedBrace {
         data: Data,
+        found: String,
+        close: Data,
     },
     InvalidAssignment {
         data: Data,
@@ -40,13 +42,59 @@ pub enum PError<Data> {
         data: Data,
         tab: bool,
     },
+    /// Parsing panicked here, either in boba or in a custom statement parser provided by the host
+    Internal {
+        message: String,
+        data: Data,
+    },
+    /// Expressions are nested deeper than the parser allows, like thousands of parentheses
+    /// or a very long operator chain
+    TooDeep {
+        limit: usize,
+        data: Data,
+    },
+}
+
+impl<Data> PError<Data> {
+    /// Returns the code shown in the error report, like `C-001`
+    pub fn code(&self) -> &'static str {
+        match self {
+            PError::UnexpectedEnd { .. } => "C-001",
+            PError::InvalidToken { .. } => "C-002",
+            PError::UnclosedString { .. } => "C-003",
+            PError::ParseNumError { .. } => "C-004",
+            PError::UnexpectedToken { .. } => "C-006",
+            PError::UnclosedBrace { .. } => "C-007",
+            PError::InvalidAssignment { .. } => "C-008",
+            PError::MixedTabsAndSpaces { .. } => "C-009",
+            PError::Internal { .. } => "C-010",
+            PError::TooDeep { .. } => "C-011",
+        }
+    }
+
+    /// Returns the span that caused the error
+    pub fn data(&self) -> &Data {
+        match self {
+            PError::UnexpectedEnd { data, .. }
+            | PError::InvalidToken { data, .. }
+            | PError::UnclosedString { data }
+            | PError::ParseNumError { data, .. }
+            | PError::UnexpectedToken { data, .. }
+            | PError::UnclosedBrace { data, .. }
+            | PError::InvalidAssignment { data }
+            | PError::MixedTabsAndSpaces { data, .. }
+            | PError::Internal { data, .. }
+            | PError::TooDeep { data, .. } => data,
+        }
+    }
 }
 
 impl PError<CacheSpan> {
-    pub fn report(&self) -> Report<CacheSpan> {
+    /// Builds an ariadne [`Report`] labelling the span that caused the error
+    pub fn report(&self) -> Report<'_, CacheSpan> {
         match self {
             PError::UnexpectedEnd { expected, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_code(format!("C-001"))
                     .with_message("Unexpected End of Input")
                     .with_label(
@@ -57,7 +105,7 @@ impl PError<CacheSpan> {
                     .finish()
             }
             PError::InvalidToken { part, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_code(format!("C-002"))
                     .with_message("Invalid Token")
                     .with_label(
@@ -68,7 +116,7 @@ impl PError<CacheSpan> {
                     .finish()
             }
             PError::UnclosedString { data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_code(format!("C-003"))
                     .with_message("Unclosed String")
                     .with_label(
@@ -79,7 +127,7 @@ impl PError<CacheSpan> {
                     .finish()
             }
             PError::ParseNumError { error, data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_code(format!("C-004"))
                     .with_message("Invalid Integer")
                     .with_label(
@@ -93,7 +141,7 @@ impl PError<CacheSpan> {
                 expected,
                 found,
                 data,
-            } => Report::build(ReportKind::Error, data.source().clone(), data.start())
+            } => Report::build(ReportKind::Error, *data.source(), data.start())
                 .with_code(format!("C-006"))
                 .with_message("Unexpected Token")
                 .with_label(
@@ -102,19 +150,24 @@ impl PError<CacheSpan> {
                         .with_message(format!("expected {expected}, found {found}")),
                 )
                 .finish(),
-            PError::UnclosedBrace { data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
-                    .with_code(format!("C-007"))
+            PError::UnclosedBrace { data, found, close } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_code("C-007")
                     .with_message("Unclosed Brace")
                     .with_label(
                         Label::new(data.clone())
                             .with_color(Color::Red)
-                            .with_message(format!("opening brace has no closing brace")),
+                            .with_message("opening brace has no closing brace"),
+                    )
+                    .with_label(
+                        Label::new(close.clone())
+                            .with_color(Color::Yellow)
+                            .with_message(format!("expected closing brace, found {found}")),
                     )
                     .finish()
             }
             PError::InvalidAssignment { data } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_code(format!("C-008"))
                     .with_message("Invalid Assignment")
                     .with_label(
@@ -127,7 +180,7 @@ impl PError<CacheSpan> {
                     .finish()
             }
             PError::MixedTabsAndSpaces { data, tab } => {
-                Report::build(ReportKind::Error, data.source().clone(), data.start())
+                Report::build(ReportKind::Error, *data.source(), data.start())
                     .with_code(format!("C-009"))
                     .with_message("Mixed Tabs and Spaces")
                     .with_label(
@@ -140,6 +193,32 @@ impl PError<CacheSpan> {
                     )
                     .finish()
             }
+            PError::Internal { message, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_code("C-010")
+                    .with_message("Internal Error")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!("parsing panicked here: {message}")),
+                    )
+                    .with_help("panics are bugs in boba or in host code, not in the script")
+                    .finish()
+            }
+            PError::TooDeep { limit, data } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_code("C-011")
+                    .with_message("Nesting Too Deep")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!(
+                                "expression nests more than {limit} levels deep here"
+                            )),
+                    )
+                    .with_help("split the expression up using variables")
+                    .finish()
+            }
         }
     }
 }
@@ -0,0 +1,359 @@
+//! Canonical formatting of boba source
+//!
+//! The formatter parses the source and prints the AST back out with normalized spacing,
+//! one statement per line and at most one blank line between statements. Comments are
+//! carried over from the original text, and numbers keep the digits they were written with.
+//!
+//! Parentheses are only added where the parser needs them to rebuild the same tree.
+//! Some operator chains group differently depending on how their operands are written,
+//! so each statement is parsed again after printing, and any statement that would change
+//! meaning is printed with every compound operand in parentheses instead.
+
+use crate::cache::{BobaCache, CacheData, CacheSpan};
+
+use super::{
+    ast::{init::InitStyle, Expr, Func, Node, Program, Statement},
+    Lexer, PError,
+};
+
+/// Formats boba source, returning every parse error if it is not valid
+///
+/// The errors point into a cache that only lives for this call,
+/// use [`format_data`] to report them against a cache of your own.
+pub fn format_source(source: &str) -> Result<String, Vec<PError<CacheSpan>>> {
+    let mut cache = BobaCache::new();
+    let data = cache
+        .store("source", source)
+        .expect("a new cache has no limits");
+    format_data(data)
+}
+
+/// Formats source that is already stored in a [`BobaCache`]
+pub fn format_data(data: &CacheData) -> Result<String, Vec<PError<CacheSpan>>> {
+    let (program, errors) = Program::parse_recover(&mut Lexer::new(data));
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut formatter = Formatter {
+        text: data.text(),
+        out: String::new(),
+        explicit: false,
+    };
+    formatter.program(&program);
+    Ok(formatter.out)
+}
+
+/// How tightly an expression binds, from greedy function bodies up to atoms
+fn precedence<Data>(expr: &Expr<Data>) -> u8 {
+    match expr {
+        Expr::Func(_) => 0,
+        Expr::Assign(..) | Expr::Walrus(..) => 1,
+        Expr::Ternary(..) => 2,
+        Expr::And(..) | Expr::Or(..) => 3,
+        Expr::Eq(..)
+        | Expr::Lt(..)
+        | Expr::Gt(..)
+        | Expr::NEq(..)
+        | Expr::LtEq(..)
+        | Expr::GtEq(..) => 4,
+        Expr::Add(..) | Expr::Sub(..) => 5,
+        Expr::Mul(..) | Expr::Div(..) | Expr::Mod(..) => 6,
+        Expr::Pow(..) => 7,
+        Expr::Neg(_) | Expr::Not(_) => 8,
+        Expr::None
+        | Expr::Var(_)
+        | Expr::Bool(_)
+        | Expr::Int(_)
+        | Expr::Float(_)
+        | Expr::String(_)
+        | Expr::Prefixed(..)
+        | Expr::Suffixed(..)
+        | Expr::Call(..) => 9,
+    }
+}
+
+/// Checks that `source` parses back into the same statement, ignoring spans
+fn reparses(source: &str, statement: &Node<CacheSpan, Statement<CacheSpan>>) -> bool {
+    if let Statement::Custom(_) = &**statement {
+        return true; // copied as written
+    }
+
+    let mut cache = BobaCache::new();
+    let data = cache
+        .store("statement", source)
+        .expect("a new cache has no limits");
+    match Statement::parse(&mut Lexer::new(data)) {
+        Ok(parsed) => {
+            let strip = |statement: Node<CacheSpan, Statement<CacheSpan>>| {
+                format!("{:?}", statement.into_item().map_data(&mut |_| ()))
+            };
+            strip(parsed) == strip(statement.clone())
+        }
+        Err(_) => false,
+    }
+}
+
+const COMPARISONS: u8 = 4;
+const POWERS: u8 = 7;
+const UNARY: u8 = 8;
+const ATOM: u8 = 9;
+
+struct Formatter<'a> {
+    text: &'a str,
+    out: String,
+    /// Puts every operand that is not an atom in parentheses
+    explicit: bool,
+}
+
+impl Formatter<'_> {
+    fn program(&mut self, program: &Program<CacheSpan>) {
+        let mut end = 0;
+        for statement in &program.statements {
+            let start = statement.data().range().start;
+            self.gap(end, start);
+
+            let printed = self.out.len();
+            self.statement(statement);
+            if !reparses(&self.out[printed..], statement) {
+                self.out.truncate(printed);
+                self.explicit = true;
+                self.statement(statement);
+                self.explicit = false;
+            }
+
+            end = statement.data().range().end;
+        }
+        self.gap(end, self.text.len());
+    }
+
+    /// Carries over the comments between two statements, keeping single blank lines
+    fn gap(&mut self, start: usize, end: usize) {
+        let mut lines = self.text[start..end].lines();
+
+        // the rest of the previous statement's line may hold a trailing comment
+        if start > 0 {
+            let rest = lines.next().unwrap_or_default().trim();
+            if rest.starts_with('#') {
+                self.out.push(' ');
+                self.out.push_str(rest);
+            }
+            self.out.push('\n');
+        }
+
+        let mut blank = false;
+        for line in lines {
+            let line = line.trim();
+            if line.is_empty() {
+                blank = !self.out.is_empty();
+            } else if line.starts_with('#') {
+                if blank {
+                    self.out.push('\n');
+                    blank = false;
+                }
+                self.out.push_str(line);
+                self.out.push('\n');
+            }
+        }
+
+        if blank && end < self.text.len() {
+            self.out.push('\n');
+        }
+    }
+
+    fn source(&self, span: &CacheSpan) -> &str {
+        self.text[span.range().clone()].trim()
+    }
+
+    fn statement(&mut self, statement: &Node<CacheSpan, Statement<CacheSpan>>) {
+        match &**statement {
+            Statement::Init(init) => {
+                self.out.push_str(match *init.style {
+                    InitStyle::Let => "let ",
+                    InitStyle::Static => "static ",
+                    InitStyle::Const => "const ",
+                });
+                self.out.push_str(&init.ident);
+                self.out.push_str(" = ");
+                self.expr(&init.expr, false);
+            }
+            Statement::Expr(expr) => self.expr(expr, false),
+            Statement::With(with) => {
+                self.out.push_str("with ");
+                self.expr(&with.resource, false);
+                self.out.push_str(" as ");
+                self.out.push_str(&with.ident);
+                self.out.push_str(": ");
+                self.expr(&with.body, false);
+            }
+            // custom statements are parsed by the host, so only their original text is known
+            Statement::Custom(_) => {
+                let source = self.source(statement.data()).to_string();
+                self.out.push_str(&source);
+            }
+        }
+    }
+
+    /// Writes an expression, in parentheses if `parens` is set or it is not an atom in explicit mode
+    fn operand(&mut self, expr: &Node<CacheSpan, Expr<CacheSpan>>, parens: bool, trailing: bool) {
+        if parens || (self.explicit && precedence(&**expr) < ATOM) {
+            self.out.push('(');
+            self.expr(expr, false);
+            self.out.push(')');
+        } else {
+            self.expr(expr, trailing);
+        }
+    }
+
+    /// Writes an expression, where `trailing` is set if more operators follow it
+    fn expr(&mut self, expr: &Node<CacheSpan, Expr<CacheSpan>>, trailing: bool) {
+        match &**expr {
+            Expr::None => self.out.push_str("none"),
+            Expr::Var(ident) => self.out.push_str(ident),
+            Expr::Bool(true) => self.out.push_str("true"),
+            Expr::Bool(false) => self.out.push_str("false"),
+            Expr::Int(_) | Expr::Float(_) => {
+                // parenthesized expressions take the span of their parentheses
+                let source = self.source(expr.data());
+                let number =
+                    source.trim_matches(|c: char| c == '(' || c == ')' || c.is_whitespace());
+                let number = number.to_string();
+                self.out.push_str(&number);
+            }
+            Expr::String(string) => self.string(string),
+            Expr::Prefixed(tag, text) => {
+                self.out.push_str(tag);
+                self.string(text);
+            }
+            Expr::Suffixed(number, suffix) => {
+                self.expr(number, false);
+                self.out.push_str(suffix);
+            }
+            Expr::Func(func) if trailing => {
+                self.out.push('(');
+                self.func(func, expr.data());
+                self.out.push(')');
+            }
+            Expr::Func(func) => self.func(func, expr.data()),
+            Expr::Call(ident, params) => {
+                self.out.push_str(ident);
+                self.out.push('(');
+                for (index, param) in params.iter().enumerate() {
+                    if index > 0 {
+                        self.out.push_str(", ");
+                    }
+                    self.expr(param, false);
+                }
+                self.out.push(')');
+            }
+            Expr::Neg(inner) => self.unary("-", inner),
+            Expr::Not(inner) => self.unary("!", inner),
+            Expr::Add(lhs, rhs) => self.binary(expr, lhs, "+", rhs, trailing),
+            Expr::Sub(lhs, rhs) => self.binary(expr, lhs, "-", rhs, trailing),
+            Expr::Mul(lhs, rhs) => self.binary(expr, lhs, "*", rhs, trailing),
+            Expr::Div(lhs, _, rhs) => self.binary(expr, lhs, "/", rhs, trailing),
+            Expr::Mod(lhs, _, rhs) => self.binary(expr, lhs, "%", rhs, trailing),
+            Expr::Pow(lhs, _, rhs) => self.binary(expr, lhs, "**", rhs, trailing),
+            Expr::And(lhs, rhs) => self.binary(expr, lhs, "and", rhs, trailing),
+            Expr::Or(lhs, rhs) => self.binary(expr, lhs, "or", rhs, trailing),
+            Expr::Eq(lhs, rhs) => self.binary(expr, lhs, "==", rhs, trailing),
+            Expr::Lt(lhs, rhs) => self.binary(expr, lhs, "<", rhs, trailing),
+            Expr::Gt(lhs, rhs) => self.binary(expr, lhs, ">", rhs, trailing),
+            Expr::NEq(lhs, rhs) => self.binary(expr, lhs, "!=", rhs, trailing),
+            Expr::LtEq(lhs, rhs) => self.binary(expr, lhs, "<=", rhs, trailing),
+            Expr::GtEq(lhs, rhs) => self.binary(expr, lhs, ">=", rhs, trailing),
+            Expr::Assign(ident, rhs) | Expr::Walrus(ident, rhs) => {
+                self.out.push_str(ident);
+                let walrus = matches!(&**expr, Expr::Walrus(..));
+                self.out.push_str(if walrus { " := " } else { " = " });
+                self.expr(rhs, trailing);
+            }
+            Expr::Ternary(cond, lhs, rhs) => {
+                // the condition is parsed as a single atom
+                self.operand(cond, precedence(&***cond) < UNARY, true);
+                self.out.push_str(" ? ");
+                self.operand(lhs, precedence(&***lhs) < 2, false);
+                self.out.push_str(" : ");
+                self.operand(rhs, precedence(&***rhs) < 2, trailing);
+            }
+        }
+    }
+
+    fn unary(&mut self, op: &str, inner: &Node<CacheSpan, Expr<CacheSpan>>) {
+        self.out.push_str(op);
+        self.operand(inner, precedence(&**inner) < POWERS, false);
+    }
+
+    fn binary(
+        &mut self,
+        expr: &Expr<CacheSpan>,
+        lhs: &Node<CacheSpan, Expr<CacheSpan>>,
+        op: &str,
+        rhs: &Node<CacheSpan, Expr<CacheSpan>>,
+        trailing: bool,
+    ) {
+        let level = precedence(expr);
+        let (left, right) = (precedence(&**lhs), precedence(&**rhs));
+
+        // the parser only continues after a left operand that restarted at the lowest level,
+        // which powers and comparisons never do, and powers only take atoms on the left
+        let lhs_parens = match level {
+            POWERS => left < ATOM,
+            _ => left < level || (left > level && (left == POWERS || left == COMPARISONS)),
+        };
+
+        // operators inside the right operand absorb any operators that follow them
+        let rhs_parens = match level {
+            POWERS => right < POWERS,
+            _ => right <= level || (trailing && right < POWERS),
+        };
+
+        self.operand(lhs, lhs_parens, true);
+        self.out.push(' ');
+        self.out.push_str(op);
+        self.out.push(' ');
+        self.operand(rhs, rhs_parens, trailing);
+    }
+
+    fn func(&mut self, func: &Func<CacheSpan>, span: &CacheSpan) {
+        let start = self.out.len();
+        self.out.push_str("fn(");
+        for (index, param) in func.params.iter().enumerate() {
+            if index > 0 {
+                self.out.push_str(", ");
+            }
+            self.out.push_str(param);
+        }
+        self.out.push_str("):");
+
+        match func.body.as_slice() {
+            [] => (),
+            [statement] if matches!(&**statement, Statement::Expr(_)) => {
+                self.out.push(' ');
+                self.statement(statement);
+            }
+            // bodies the parser cannot produce are kept as written
+            _ => {
+                let source = self.source(span).to_string();
+                self.out.truncate(start);
+                self.out.push_str(&source);
+            }
+        }
+    }
+
+    /// Writes a string literal, splitting it into adjacent literals if it holds both quotes
+    fn string(&mut self, string: &str) {
+        let double = string.contains('\'') && !string.contains('"');
+        let mut quote = if double { '"' } else { '\'' };
+        self.out.push(quote);
+        for char in string.chars() {
+            if char == quote {
+                self.out.push(quote);
+                quote = if quote == '\'' { '"' } else { '\'' };
+                self.out.push(quote);
+            }
+            self.out.push(char);
+        }
+        self.out.push(quote);
+    }
+}
@@ -0,0 +1,125 @@
+//! Classification of source text for editors
+//!
+//! [`highlight`] lexes a source without parsing it, so it keeps working on
+//! incomplete or invalid scripts while they are being typed.
+
+use std::ops::Range;
+
+use crate::cache::{CacheData, CacheSpan};
+
+use super::{Lexer, PError, Token};
+
+/// What a highlighted piece of source text is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HighlightKind {
+    /// Reserved words, including `true`, `false` and `none`
+    Keyword,
+    Number,
+    /// String literals, including their quotes
+    String,
+    Identifier,
+    /// Symbolic operators and the word operators `and` and `or`
+    Operator,
+    /// Parentheses, commas, colons and other separators
+    Punctuation,
+    /// Comments from the `#` to the end of the line
+    Comment,
+    /// Text the lexer could not turn into a token, like an unclosed string
+    Invalid,
+}
+
+/// A classified piece of source text
+#[derive(Debug, Clone)]
+pub struct Highlight {
+    pub kind: HighlightKind,
+    pub span: CacheSpan,
+}
+
+impl HighlightKind {
+    fn of(token: &Token) -> Option<Self> {
+        Some(match token {
+            Token::Newline => return None,
+            Token::Ident(_) => Self::Identifier,
+            Token::Int(_) | Token::UFloat(_) => Self::Number,
+            Token::String(_) => Self::String,
+            Token::None
+            | Token::Bool(_)
+            | Token::Let
+            | Token::Fn
+            | Token::Const
+            | Token::Static
+            | Token::While
+            | Token::With
+            | Token::As
+            | Token::Defer => Self::Keyword,
+            Token::Add
+            | Token::Sub
+            | Token::Mul
+            | Token::Div
+            | Token::Mod
+            | Token::Pow
+            | Token::Walrus
+            | Token::And
+            | Token::Or
+            | Token::Not
+            | Token::Eq
+            | Token::Lt
+            | Token::Gt
+            | Token::NEq
+            | Token::LtEq
+            | Token::GtEq
+            | Token::Assign => Self::Operator,
+            Token::Colon
+            | Token::Question
+            | Token::Dot
+            | Token::Comma
+            | Token::OpenParen
+            | Token::CloseParen
+            | Token::Arrow => Self::Punctuation,
+        })
+    }
+}
+
+/// Classifies every token and comment in `data`, in source order
+///
+/// Whitespace and line breaks are not included. The spans never overlap,
+/// so they can be fed directly to an LSP semantic tokens provider.
+pub fn highlight(data: &CacheData) -> Vec<Highlight> {
+    let mut highlights = Vec::new();
+    let mut end = 0;
+    for item in Lexer::new(data) {
+        let (kind, span) = match item {
+            Ok((token, span)) => (HighlightKind::of(&token), span),
+            Err(PError::InvalidToken { data, .. } | PError::UnclosedString { data }) => {
+                (Some(HighlightKind::Invalid), data)
+            }
+            // the lexer only reports invalid tokens and unclosed strings
+            Err(_) => continue,
+        };
+
+        comments(data, end..span.range().start, &mut highlights);
+        end = span.range().end;
+        if let Some(kind) = kind {
+            highlights.push(Highlight { kind, span });
+        }
+    }
+
+    comments(data, end..data.text().len(), &mut highlights);
+    highlights
+}
+
+/// Finds the comments in a gap between tokens, which otherwise only holds whitespace
+fn comments(data: &CacheData, gap: Range<usize>, highlights: &mut Vec<Highlight>) {
+    let mut offset = gap.start;
+    for line in data.text()[gap].split_inclusive(['\n', '\r']) {
+        if let Some(start) = line.find('#') {
+            let comment = line.trim_end_matches(['\n', '\r']);
+            let span = data.span(offset + start..offset + comment.len());
+            highlights.push(Highlight {
+                kind: HighlightKind::Comment,
+                span,
+            });
+        }
+        offset += line.len();
+    }
+}
@@ -4,39 +4,28 @@ use unicode_segmentation::{GraphemeIndices, UnicodeSegmentation};
 
 use crate::{
     cache::{CacheData, CacheSpan},
-    parser::{PError, PResult, Token},
+    parser::{PError, PResult, Token, TokenSource},
 };
 
+/// How deep expressions can nest before parsing fails with [`PError::TooDeep`]
+///
+/// Parentheses, prefixes and every operator in a chain each add a level. Each level costs
+/// several parser frames, and tree walkers like the formatter recurse once per level,
+/// so this stays well inside the main thread stack.
+pub const MAX_DEPTH: usize = 256;
+
 pub struct Lexer<'source> {
     peeked: Option<(Token<'source>, CacheSpan)>,
     symbols: Peekable<GraphemeIndices<'source>>,
     data: &'source CacheData,
+    offset: usize,
+    end: usize,
     pos: usize,
+    depth: usize,
 }
 
-impl<'source> Lexer<'source> {
-    pub fn new(data: &'source CacheData) -> Self {
-        Self {
-            peeked: None,
-            symbols: data.text().grapheme_indices(true).peekable(),
-            data,
-            pos: 0,
-        }
-    }
-
-    pub fn pos(&self) -> usize {
-        self.pos
-    }
-
-    pub fn pos_span(&self) -> CacheSpan {
-        self.data.span(self.pos..self.pos)
-    }
-
-    pub fn span(&self, range: Range<usize>) -> CacheSpan {
-        self.data.span(range)
-    }
-
-    pub fn peek(&mut self) -> Option<PResult<CacheSpan, (Token<'source>, CacheSpan)>> {
+impl<'source> TokenSource<'source> for Lexer<'source> {
+    fn peek(&mut self) -> Option<PResult<CacheSpan, (Token<'source>, CacheSpan)>> {
         match &self.peeked {
             Some(items) => Some(Ok(items.clone())),
             None => match self.next()? {
@@ -49,54 +38,70 @@ impl<'source> Lexer<'source> {
         }
     }
 
-    pub fn expect_peek(
-        &mut self,
-        expect: impl Into<String>,
-    ) -> PResult<CacheSpan, (Token<'source>, CacheSpan)> {
-        match self.peek() {
-            Some(result) => result,
-            None => Err(PError::UnexpectedEnd {
-                expected: expect.into(),
-                data: self.pos_span(),
-            }),
-        }
+    fn pos(&self) -> usize {
+        self.pos
     }
 
-    pub fn expect_next(
-        &mut self,
-        expect: impl Into<String>,
-    ) -> PResult<CacheSpan, (Token<'source>, CacheSpan)> {
-        match self.next() {
-            Some(result) => result,
-            None => Err(PError::UnexpectedEnd {
-                expected: expect.into(),
-                data: self.pos_span(),
-            }),
+    fn span(&self, range: Range<usize>) -> CacheSpan {
+        self.data.span(range)
+    }
+
+    fn enter(&mut self) -> PResult<CacheSpan, ()> {
+        if self.depth >= MAX_DEPTH {
+            let data = match self.peek() {
+                Some(Ok((_, span))) => span,
+                _ => self.pos_span(),
+            };
+            return Err(PError::TooDeep {
+                limit: MAX_DEPTH,
+                data,
+            });
         }
+
+        self.depth += 1;
+        Ok(())
     }
 
-    pub fn expect_line_end(&mut self) -> PResult<CacheSpan, ()> {
-        match self.next() {
-            None => Ok(()),
-            Some(Err(err)) => Err(err),
-            Some(Ok((token, span))) => match token {
-                Token::Newline => Ok(()),
-                _ => Err(PError::UnexpectedToken {
-                    expected: format!("end of line"),
-                    found: format!("'{token}'"),
-                    data: span,
-                }),
-            },
+    fn exit(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+}
+
+impl<'source> Lexer<'source> {
+    pub fn new(data: &'source CacheData) -> Self {
+        Self::from_text(data, data.text(), 0)
+    }
+
+    /// Creates a lexer over only `range` of the source
+    ///
+    /// Spans produced by the lexer are still relative to the start of the full source,
+    /// so hosts can parse boba snippets embedded in their own file formats.
+    /// Returns `None` if the range is out of bounds or splits a character.
+    pub fn new_range(data: &'source CacheData, range: Range<usize>) -> Option<Self> {
+        let text = data.text().get(range.clone())?;
+        Some(Self::from_text(data, text, range.start))
+    }
+
+    fn from_text(data: &'source CacheData, text: &'source str, offset: usize) -> Self {
+        Self {
+            peeked: None,
+            symbols: text.grapheme_indices(true).peekable(),
+            data,
+            offset,
+            end: offset + text.len(),
+            pos: offset,
+            depth: 0,
         }
     }
 
     fn peek_symbol(&mut self) -> Option<(usize, &'source str)> {
         let (index, symbol) = self.symbols.peek()?;
-        Some((*index, *symbol))
+        Some((*index + self.offset, *symbol))
     }
 
     fn take_symbol(&mut self) -> Option<(usize, &'source str)> {
         let (index, symbol) = self.symbols.next()?;
+        let index = index + self.offset;
         self.pos = index + symbol.len();
         Some((index, symbol))
     }
@@ -240,7 +245,7 @@ impl<'source> Iterator for Lexer<'source> {
                             return Some(Ok((Token::String(str), span)));
                         }
                         None => {
-                            let span = start..self.data.text().len();
+                            let span = start..self.end;
                             return Some(Err(PError::UnclosedString {
                                 data: self.data.span(span),
                             }));
@@ -271,7 +276,7 @@ impl<'source> Iterator for Lexer<'source> {
                             };
                         }
                         None => {
-                            let span = self.data.span(start..self.data.text().len());
+                            let span = self.data.span(start..self.end);
                             let str = &self.data.text()[span.range().clone()];
 
                             // check for keywords
@@ -302,7 +307,7 @@ impl<'source> Iterator for Lexer<'source> {
                                 return Some(Ok((Token::Int(str), span)));
                             }
                             None => {
-                                let span = self.data.span(start..self.data.text().len());
+                                let span = self.data.span(start..self.end);
                                 let str = &self.data.text()[span.range().clone()];
                                 return Some(Ok((Token::Int(str), span)));
                             }
@@ -322,7 +327,7 @@ impl<'source> Iterator for Lexer<'source> {
                                 return Some(Ok((Token::UFloat(str), span)));
                             }
                             None => {
-                                let span = self.data.span(start..self.data.text().len());
+                                let span = self.data.span(start..self.end);
                                 let str = &self.data.text()[span.range().clone()];
                                 return Some(Ok((Token::UFloat(str), span)));
                             }
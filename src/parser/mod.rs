@@ -1,8 +1,14 @@
 pub mod ast;
 pub mod error;
+pub mod format;
+pub mod highlight;
 pub mod lexer;
+pub mod source;
 pub mod token;
 
 pub use error::{PError, PResult};
+pub use format::{format_data, format_source};
+pub use highlight::{highlight, Highlight, HighlightKind};
 pub use lexer::Lexer;
+pub use source::TokenSource;
 pub use token::Token;
@@ -0,0 +1,121 @@
+use std::ops::Range;
+
+use crate::{
+    cache::CacheSpan,
+    parser::{PError, PResult, Token},
+};
+
+/// A stream of tokens that can be consumed by the parser
+///
+/// The [`Lexer`](super::Lexer) is the default source, but hosts may provide their own
+/// to parse boba statements and expressions that are embedded in other file formats.
+pub trait TokenSource<'source>:
+    Iterator<Item = PResult<CacheSpan, (Token<'source>, CacheSpan)>>
+{
+    /// Returns the next token without consuming it
+    fn peek(&mut self) -> Option<PResult<CacheSpan, (Token<'source>, CacheSpan)>>;
+
+    /// Returns the byte position directly after the last consumed token
+    fn pos(&self) -> usize;
+
+    /// Builds a span over `range` in the underlying source
+    fn span(&self, range: Range<usize>) -> CacheSpan;
+
+    /// Called before the parser nests into a sub-expression, like the inside of parentheses
+    /// or the next operand of an operator chain
+    ///
+    /// Sources can fail here with [`PError::TooDeep`] to stop deeply nested input from
+    /// overflowing the stack. The default allows any depth.
+    fn enter(&mut self) -> PResult<CacheSpan, ()> {
+        Ok(())
+    }
+
+    /// Called when the parser leaves a sub-expression started with [`TokenSource::enter`]
+    fn exit(&mut self) {}
+
+    fn pos_span(&self) -> CacheSpan {
+        self.span(self.pos()..self.pos())
+    }
+
+    fn expect_peek(&mut self, expect: &str) -> PResult<CacheSpan, (Token<'source>, CacheSpan)> {
+        match self.peek() {
+            Some(result) => result,
+            None => Err(PError::UnexpectedEnd {
+                expected: expect.to_string(),
+                data: self.pos_span(),
+            }),
+        }
+    }
+
+    fn expect_next(&mut self, expect: &str) -> PResult<CacheSpan, (Token<'source>, CacheSpan)> {
+        match self.next() {
+            Some(result) => result,
+            None => Err(PError::UnexpectedEnd {
+                expected: expect.to_string(),
+                data: self.pos_span(),
+            }),
+        }
+    }
+
+    /// Consumes the closing token for the brace at `open`
+    ///
+    /// Fails with [`PError::UnclosedBrace`] labelling both the opening brace
+    /// and the token found in place of the closing brace.
+    fn expect_close(
+        &mut self,
+        close: Token<'source>,
+        open: &CacheSpan,
+    ) -> PResult<CacheSpan, CacheSpan> {
+        match self.next() {
+            Some(Err(error)) => Err(error),
+            Some(Ok((token, span))) if token == close => Ok(span),
+            Some(Ok((token, span))) => Err(PError::UnclosedBrace {
+                data: open.clone(),
+                found: format!("'{token}'"),
+                close: span,
+            }),
+            None => Err(PError::UnclosedBrace {
+                data: open.clone(),
+                found: "end of input".to_string(),
+                close: self.pos_span(),
+            }),
+        }
+    }
+
+    fn expect_line_end(&mut self) -> PResult<CacheSpan, ()> {
+        match self.next() {
+            None => Ok(()),
+            Some(Err(err)) => Err(err),
+            Some(Ok((token, span))) => match token {
+                Token::Newline => Ok(()),
+                _ => Err(PError::UnexpectedToken {
+                    expected: "end of line".to_string(),
+                    found: format!("'{token}'"),
+                    data: span,
+                }),
+            },
+        }
+    }
+}
+
+impl<'source, T: TokenSource<'source> + ?Sized> TokenSource<'source> for &mut T {
+    fn peek(&mut self) -> Option<PResult<CacheSpan, (Token<'source>, CacheSpan)>> {
+        (**self).peek()
+    }
+
+    fn pos(&self) -> usize {
+        (**self).pos()
+    }
+
+    fn span(&self, range: Range<usize>) -> CacheSpan {
+        (**self).span(range)
+    }
+
+    fn enter(&mut self) -> PResult<CacheSpan, ()> {
+        (**self).enter()
+    }
+
+    fn exit(&mut self) {
+        (**self).exit()
+    }
+}
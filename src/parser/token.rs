@@ -83,6 +83,12 @@ pub enum Token<'source> {
     Static,
     #[display(fmt = "while")]
     While,
+    #[display(fmt = "with")]
+    With,
+    #[display(fmt = "as")]
+    As,
+    #[display(fmt = "defer")]
+    Defer,
 }
 
 impl<'source> Token<'source> {
@@ -98,6 +104,9 @@ impl<'source> Token<'source> {
             "or" => Token::Or,
             "none" => Token::None,
             "while" => Token::While,
+            "with" => Token::With,
+            "as" => Token::As,
+            "defer" => Token::Defer,
         };
 
         KEYWORDS.get(str.as_ref()).cloned()
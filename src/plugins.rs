@@ -0,0 +1,548 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use ariadne::{Color, Label, Report, ReportKind, Span};
+use indexmap::IndexMap;
+
+use crate::{
+    cache::{CacheError, CacheSpan},
+    engine::{
+        builtin::{load_env, load_filesystem, load_print, load_random, load_time},
+        error::RunError,
+        EngineCapabilities, FuncValue, Value,
+    },
+    parser::{ast::Program, Lexer, PError},
+    BobaCache, BobaError, Engine,
+};
+
+/// File extension used when discovering plugin scripts
+pub const PLUGIN_EXTENSION: &str = "boba";
+
+/// Entry points every plugin script is required to define
+pub const ENTRY_POINTS: [&str; 2] = ["init", "update"];
+
+/// Loads a set of natives or values into a plugin [`Engine`]
+pub type Capability = fn(&mut Engine<CacheSpan>);
+
+/// Resource limits applied to every call into a plugin
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quota {
+    pub fuel: Option<u64>,
+    pub memory: Option<usize>,
+    pub time: Option<Duration>,
+}
+
+/// Resources consumed by a plugin since it was loaded
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Usage {
+    pub calls: u64,
+    pub fuel: u64,
+    pub time: Duration,
+    pub memory: usize,
+}
+
+#[derive(Debug)]
+pub enum PluginError {
+    Io {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Cache {
+        plugin: String,
+        error: CacheError,
+    },
+    Parse {
+        plugin: String,
+        error: Box<PError<CacheSpan>>,
+    },
+    Run {
+        plugin: String,
+        error: Box<RunError<CacheSpan>>,
+    },
+    MissingEntry {
+        plugin: String,
+        entry: &'static str,
+        data: CacheSpan,
+    },
+    UnknownCapability {
+        plugin: String,
+        capability: String,
+    },
+    MemoryQuota {
+        plugin: String,
+        used: usize,
+        limit: usize,
+    },
+    Internal {
+        plugin: String,
+        message: String,
+    },
+}
+
+impl PluginError {
+    /// Prints the error to stderr, using ariadne reports where a span is available
+    pub fn eprint(&self, cache: &mut BobaCache) {
+        match self {
+            PluginError::Io { path, error } => {
+                eprintln!("Plugin Error: could not read '{}': {error}", path.display())
+            }
+            PluginError::Cache { plugin, error } => {
+                eprintln!("Plugin Error: could not store '{plugin}': {error}")
+            }
+            PluginError::Parse { plugin, error } => {
+                if error.report().eprint(cache).is_err() {
+                    eprintln!("Plugin Error: '{plugin}' failed to parse [{}]", error.code())
+                }
+            }
+            PluginError::Run { plugin, error } => {
+                if error.report().eprint(cache).is_err() {
+                    eprintln!("Plugin Error: '{plugin}' failed to run [{}]", error.code())
+                }
+            }
+            PluginError::MissingEntry {
+                plugin,
+                entry,
+                data,
+            } => {
+                Report::build(ReportKind::Error, *data.source(), data.start())
+                    .with_message("Missing Entry Point")
+                    .with_code("P-001")
+                    .with_label(
+                        Label::new(data.clone())
+                            .with_color(Color::Red)
+                            .with_message(format!(
+                                "plugin '{plugin}' does not define an '{entry}' function"
+                            )),
+                    )
+                    .finish()
+                    .eprint(cache)
+                    .unwrap_or_else(|_| {
+                        eprintln!("Plugin Error: '{plugin}' does not define an '{entry}' function")
+                    })
+            }
+            PluginError::UnknownCapability { plugin, capability } => {
+                eprintln!("Plugin Error: '{plugin}' was granted unknown capability '{capability}'")
+            }
+            PluginError::MemoryQuota {
+                plugin,
+                used,
+                limit,
+            } => eprintln!(
+                "Plugin Error: '{plugin}' is using ~{used} bytes, exceeding its quota of {limit} bytes"
+            ),
+            PluginError::Internal { plugin, message } => {
+                eprintln!("Plugin Error: '{plugin}' crashed the engine: {message}")
+            }
+        }
+    }
+}
+
+/// A single loaded plugin script with its own isolated [`Engine`]
+pub struct Plugin {
+    name: String,
+    path: PathBuf,
+    engine: Engine<CacheSpan>,
+    init: FuncValue<CacheSpan>,
+    update: FuncValue<CacheSpan>,
+    data: CacheSpan,
+    quota: Quota,
+    usage: Usage,
+}
+
+impl Plugin {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn engine(&self) -> &Engine<CacheSpan> {
+        &self.engine
+    }
+
+    pub fn engine_mut(&mut self) -> &mut Engine<CacheSpan> {
+        &mut self.engine
+    }
+
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    pub fn set_quota(&mut self, quota: Quota) {
+        self.quota = quota;
+    }
+
+    pub fn usage(&self) -> Usage {
+        self.usage
+    }
+
+    pub fn init(&mut self) -> Result<Value<CacheSpan>, PluginError> {
+        let init = self.init.clone();
+        self.call(&init, None)
+    }
+
+    pub fn update(&mut self) -> Result<Value<CacheSpan>, PluginError> {
+        let update = self.update.clone();
+        self.call(&update, None)
+    }
+
+    /// Calls `func` within the plugin quota, further limited to `slice` fuel
+    fn call(
+        &mut self,
+        func: &FuncValue<CacheSpan>,
+        slice: Option<u64>,
+    ) -> Result<Value<CacheSpan>, PluginError> {
+        // unlimited calls still get fuel so that usage can be measured
+        let fuel = match (self.quota.fuel, slice) {
+            (Some(quota), Some(slice)) => quota.min(slice),
+            (Some(fuel), None) | (None, Some(fuel)) => fuel,
+            (None, None) => u64::MAX,
+        };
+
+        let start = Instant::now();
+        self.engine.set_fuel(Some(fuel));
+        self.engine
+            .set_deadline(self.quota.time.map(|time| start + time));
+
+        let output = self.engine.eval_func(func, Vec::new(), &self.data);
+
+        self.usage.calls += 1;
+        self.usage.time += start.elapsed();
+        self.usage.fuel += fuel - self.engine.fuel().unwrap_or(0);
+        self.usage.memory = self.engine.memory_usage();
+        self.engine.set_fuel(None);
+        self.engine.set_deadline(None);
+
+        let value = match output {
+            Ok(value) => value,
+            Err(RunError::Internal { message, .. }) => {
+                return Err(PluginError::Internal {
+                    plugin: self.name.clone(),
+                    message,
+                })
+            }
+            Err(error) => {
+                return Err(PluginError::Run {
+                    plugin: self.name.clone(),
+                    error: Box::new(error),
+                })
+            }
+        };
+
+        match self.quota.memory {
+            Some(limit) if self.usage.memory > limit => Err(PluginError::MemoryQuota {
+                plugin: self.name.clone(),
+                used: self.usage.memory,
+                limit,
+            }),
+            _ => Ok(value),
+        }
+    }
+}
+
+/// Discovers, validates, and runs a collection of plugin scripts
+///
+/// Each plugin is evaluated in its own [`Engine`] that only contains the
+/// capabilities granted to it. Errors are collected per plugin so that a
+/// single broken script does not prevent the others from running.
+///
+/// When a tick fuel budget is set, each [`update`](PluginHost::update) splits it evenly
+/// between plugins and rotates which plugin runs first, so a misbehaving plugin cannot
+/// consume the fuel meant for the others.
+pub struct PluginHost {
+    cache: BobaCache,
+    plugins: Vec<Plugin>,
+    capabilities: IndexMap<String, Capability>,
+    defaults: Vec<String>,
+    grants: IndexMap<String, Vec<String>>,
+    default_quota: Quota,
+    quotas: IndexMap<String, Quota>,
+    tick_fuel: Option<u64>,
+    next: usize,
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        let mut host = Self::empty();
+        host.add_capability("print", load_print);
+        host.add_capability("time", load_time);
+        host.add_capability("random", load_random);
+        host.add_capability("env", load_env);
+        host.add_capability("filesystem", load_filesystem);
+        host.grant_default("print");
+        host
+    }
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            cache: BobaCache::new(),
+            plugins: Vec::new(),
+            capabilities: IndexMap::new(),
+            defaults: Vec::new(),
+            grants: IndexMap::new(),
+            default_quota: Quota::default(),
+            quotas: IndexMap::new(),
+            tick_fuel: None,
+            next: 0,
+        }
+    }
+
+    pub fn cache(&self) -> &BobaCache {
+        &self.cache
+    }
+
+    pub fn cache_mut(&mut self) -> &mut BobaCache {
+        &mut self.cache
+    }
+
+    pub fn plugins(&self) -> &[Plugin] {
+        &self.plugins
+    }
+
+    pub fn plugins_mut(&mut self) -> &mut [Plugin] {
+        &mut self.plugins
+    }
+
+    /// Registers a capability that may be granted to plugins by name
+    pub fn add_capability(&mut self, name: impl Into<String>, capability: Capability) {
+        self.capabilities.insert(name.into(), capability);
+    }
+
+    /// Grants a capability to every plugin that has no explicit grants
+    pub fn grant_default(&mut self, capability: impl Into<String>) {
+        self.defaults.push(capability.into());
+    }
+
+    /// Grants a capability to the plugin with the given name
+    ///
+    /// Once a plugin has an explicit grant, it no longer receives the default capabilities.
+    pub fn grant(&mut self, plugin: impl Into<String>, capability: impl Into<String>) {
+        let grants = self.grants.entry(plugin.into()).or_default();
+        grants.push(capability.into());
+    }
+
+    /// Sets the quota used by plugins that have no explicit quota
+    pub fn set_default_quota(&mut self, quota: Quota) {
+        self.default_quota = quota;
+    }
+
+    /// Sets the quota for the plugin with the given name
+    ///
+    /// This applies to the plugin immediately if it is already loaded.
+    pub fn set_quota(&mut self, plugin: impl Into<String>, quota: Quota) {
+        let plugin = plugin.into();
+        if let Some(loaded) = self.plugins.iter_mut().find(|p| p.name == plugin) {
+            loaded.set_quota(quota);
+        }
+        self.quotas.insert(plugin, quota);
+    }
+
+    pub fn tick_fuel(&self) -> Option<u64> {
+        self.tick_fuel
+    }
+
+    /// Sets the total fuel shared between all plugins during a single [`update`](Self::update)
+    ///
+    /// Fuel is split evenly, but each plugin gets at least one fuel per update,
+    /// so the total can be exceeded when there are more plugins than fuel.
+    pub fn set_tick_fuel(&mut self, fuel: Option<u64>) {
+        self.tick_fuel = fuel;
+    }
+
+    /// Returns the resource usage of every loaded plugin
+    pub fn usage(&self) -> impl Iterator<Item = (&str, Usage)> {
+        self.plugins
+            .iter()
+            .map(|plugin| (plugin.name(), plugin.usage()))
+    }
+
+    /// Loads every plugin script found directly inside `dir`
+    ///
+    /// Scripts are loaded in file name order, and any errors are returned together.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> Vec<PluginError> {
+        let dir = dir.as_ref();
+        let mut paths = match fs::read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file())
+                .filter(|path| path.extension().is_some_and(|ext| ext == PLUGIN_EXTENSION))
+                .collect::<Vec<_>>(),
+            Err(error) => {
+                return vec![PluginError::Io {
+                    path: dir.to_path_buf(),
+                    error,
+                }]
+            }
+        };
+
+        paths.sort();
+        paths
+            .into_iter()
+            .filter_map(|path| self.load_file(path).err())
+            .collect()
+    }
+
+    /// Loads and validates a single plugin script
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<&mut Plugin, PluginError> {
+        let path = path.as_ref();
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => {
+                return Err(PluginError::Io {
+                    path: path.to_path_buf(),
+                    error,
+                })
+            }
+        };
+
+        let name = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => path.display().to_string(),
+        };
+
+        // plugins are limited by the capabilities they are granted, which load the natives
+        let mut engine = Engine::empty();
+        engine.set_capabilities(EngineCapabilities::all());
+        let capabilities = self.grants.get(&name).unwrap_or(&self.defaults);
+        for capability in capabilities {
+            match self.capabilities.get(capability) {
+                Some(load) => load(&mut engine),
+                None => {
+                    return Err(PluginError::UnknownCapability {
+                        plugin: name,
+                        capability: capability.clone(),
+                    })
+                }
+            }
+        }
+
+        let quota = match self.quotas.get(&name) {
+            Some(quota) => *quota,
+            None => self.default_quota,
+        };
+
+        let data = match self.cache.store(path.display().to_string(), text) {
+            Ok(data) => data,
+            Err(error) => {
+                return Err(PluginError::Cache {
+                    plugin: name,
+                    error,
+                })
+            }
+        };
+        let span = data.span(0..0);
+        engine.set_fuel(quota.fuel);
+        engine.set_deadline(quota.time.map(|time| Instant::now() + time));
+        let output = BobaError::catch_panic(|| match Program::parse(&mut Lexer::new(data)) {
+            Ok(mut program) => {
+                // plugins keep their functions for their whole lifetime
+                program.fold_strings();
+                program.dedup_strings();
+                engine
+                    .eval_program(&program.statements)
+                    .map_err(BobaError::Run)
+            }
+            Err(error) => Err(BobaError::Parse(error)),
+        });
+        engine.set_fuel(None);
+        engine.set_deadline(None);
+        if let Err(error) = output {
+            return Err(match error {
+                BobaError::Parse(error) => PluginError::Parse {
+                    plugin: name,
+                    error: Box::new(error),
+                },
+                BobaError::Run(error) => PluginError::Run {
+                    plugin: name,
+                    error: Box::new(error),
+                },
+                BobaError::Cache(error) => PluginError::Cache {
+                    plugin: name,
+                    error,
+                },
+                BobaError::Internal(message) => PluginError::Internal {
+                    plugin: name,
+                    message,
+                },
+            });
+        }
+
+        let mut entries = Vec::with_capacity(ENTRY_POINTS.len());
+        for entry in ENTRY_POINTS {
+            match engine.get_value(entry) {
+                Some(Value::Func(func)) if func.param_count() == 0 => entries.push(func.clone()),
+                _ => {
+                    return Err(PluginError::MissingEntry {
+                        plugin: name,
+                        entry,
+                        data: span,
+                    })
+                }
+            }
+        }
+
+        let update = entries.pop().expect("update entry point");
+        let init = entries.pop().expect("init entry point");
+        self.plugins.push(Plugin {
+            name,
+            path: path.to_path_buf(),
+            engine,
+            init,
+            update,
+            data: span,
+            quota,
+            usage: Usage::default(),
+        });
+
+        Ok(self.plugins.last_mut().expect("plugin was just pushed"))
+    }
+
+    /// Calls the `init` entry point of every loaded plugin
+    pub fn init(&mut self) -> Vec<PluginError> {
+        self.plugins
+            .iter_mut()
+            .filter_map(|plugin| plugin.init().err())
+            .collect()
+    }
+
+    /// Calls the `update` entry point of every loaded plugin
+    ///
+    /// Plugins are run in round-robin order, starting one plugin later on each call.
+    pub fn update(&mut self) -> Vec<PluginError> {
+        let count = self.plugins.len();
+        if count == 0 {
+            return Vec::new();
+        }
+
+        let start = self.next % count;
+        self.next = (start + 1) % count;
+
+        let mut errors = Vec::new();
+        for offset in 0..count {
+            // the remainder goes to the plugins that run first, which rotate every tick,
+            // and every plugin gets at least one fuel so it can make progress
+            let slice = self.tick_fuel.map(|fuel| {
+                let (share, remainder) = (fuel / count as u64, fuel % count as u64);
+                (share + u64::from((offset as u64) < remainder)).max(1)
+            });
+
+            let plugin = &mut self.plugins[(start + offset) % count];
+            let update = plugin.update.clone();
+            if let Err(error) = plugin.call(&update, slice) {
+                errors.push(error);
+            }
+        }
+        errors
+    }
+}
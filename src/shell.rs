@@ -1,14 +1,83 @@
-use reedline::{DefaultPrompt, DefaultPromptSegment, Reedline, Signal};
+use std::collections::VecDeque;
+
+use reedline::{
+    DefaultPrompt, DefaultPromptSegment, Reedline, Signal, ValidationResult, Validator,
+};
 
 use crate::{
+    cache::{CacheId, CacheSpan},
     engine::Value,
-    parser::{ast::Statement, lexer::Lexer},
-    BobaCache, Engine,
+    parser::{ast::Program, lexer::Lexer, PError},
+    BobaCache, BobaError, Engine,
 };
 
+/// Number of entered lines whose source is kept in the engine cache for error reports
+///
+/// Errors raised later inside functions defined on older lines still print their code,
+/// but without the source they point at.
+pub const KEPT_SOURCES: usize = 32;
+
+/// Returns true if `source` ends inside a string literal
+fn in_string(source: &str) -> bool {
+    let mut cache = BobaCache::new();
+    let Ok(data) = cache.store("shell", source) else {
+        return false;
+    };
+    matches!(
+        Program::parse(&mut Lexer::new(data)),
+        Err(PError::UnclosedString { .. })
+    )
+}
+
+/// Joins the lines of a multi-line entry into the source that is evaluated
+///
+/// Newlines only continue a statement inside string literals, so every other
+/// line break the shell asked for is replaced by a space.
+pub fn join_lines(buffer: &str) -> String {
+    let mut joined = String::with_capacity(buffer.len());
+    for (index, line) in buffer.split('\n').enumerate() {
+        if index > 0 {
+            joined.push(if in_string(&joined) { '\n' } else { ' ' });
+        }
+        joined.push_str(line);
+    }
+    joined
+}
+
+/// Returns true if `source` ends before a statement could be completed
+///
+/// Unclosed strings and braces, or expressions that are cut off at the end of the
+/// source are incomplete, and the shell will wait for more input before evaluating.
+/// Multi-line entries should be passed through [`join_lines`] first.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut cache = BobaCache::new();
+    let data = match cache.store("shell", source) {
+        Ok(data) => data,
+        Err(_) => return false,
+    };
+    match Program::parse(&mut Lexer::new(data)) {
+        Ok(_) => false,
+        Err(PError::UnexpectedEnd { .. } | PError::UnclosedString { .. }) => true,
+        Err(PError::UnclosedBrace { close, .. }) => close.range().start >= source.len(),
+        Err(_) => false,
+    }
+}
+
+struct BobaValidator;
+
+impl Validator for BobaValidator {
+    fn validate(&self, line: &str) -> ValidationResult {
+        match is_incomplete(&join_lines(line)) {
+            true => ValidationResult::Incomplete,
+            false => ValidationResult::Complete,
+        }
+    }
+}
+
 pub struct Session {
     prompt: DefaultPrompt,
     line_editor: Reedline,
+    sources: VecDeque<CacheId>,
 }
 
 impl Default for Session {
@@ -18,7 +87,8 @@ impl Default for Session {
                 DefaultPromptSegment::Basic(format!("boba ")),
                 DefaultPromptSegment::Empty,
             ),
-            line_editor: Reedline::create(),
+            line_editor: Reedline::create().with_validator(Box::new(BobaValidator)),
+            sources: VecDeque::new(),
         }
     }
 }
@@ -29,11 +99,14 @@ impl Session {
     }
 
     pub fn start_console(&mut self) {
-        let mut engine = Engine::new();
-        let mut cache = BobaCache::new();
+        self.start_with(Engine::with_prelude())
+    }
+
+    /// Starts the console using `engine`, so hosts can provide their own natives
+    pub fn start_with(&mut self, mut engine: Engine<CacheSpan>) {
         loop {
-            let data = match self.line_editor.read_line(&self.prompt) {
-                Ok(Signal::Success(buffer)) => cache.store("shell", buffer),
+            let buffer = match self.line_editor.read_line(&self.prompt) {
+                Ok(Signal::Success(buffer)) => buffer,
                 Ok(Signal::CtrlD) => {
                     println!("Closing Shell...");
                     return;
@@ -48,25 +121,41 @@ impl Session {
                 }
             };
 
-            let mut lexer = Lexer::new(data);
-            if lexer.peek().is_none() {
-                continue; // if there are no tokens, do nothing
+            if buffer.trim().is_empty() {
+                continue; // if there is no input, do nothing
             }
 
-            match Statement::parse(&mut lexer) {
-                Ok(statement) => match engine.eval_statement(&statement) {
-                    Ok(Value::None) => continue,
-                    Ok(value) => println!("{value}"),
-                    Err(e) => {
-                        e.report().eprint(&mut cache).unwrap();
-                        continue;
-                    }
-                },
+            match self.eval_line(&mut engine, join_lines(&buffer)) {
+                Ok(Value::None) => continue,
+                Ok(value) => println!("{}", value.display(engine.float_format())),
                 Err(e) => {
-                    e.report().eprint(&mut cache).unwrap();
+                    e.eprint(engine.cache_mut());
                     continue;
                 }
             }
         }
     }
+
+    /// Evaluates an entered line, dropping the oldest kept source from the cache
+    fn eval_line(
+        &mut self,
+        engine: &mut Engine<CacheSpan>,
+        source: String,
+    ) -> Result<Value<CacheSpan>, BobaError> {
+        let id = engine.cache_mut().store("shell", source)?.id();
+        self.sources.push_back(id);
+        if self.sources.len() > KEPT_SOURCES {
+            if let Some(oldest) = self.sources.pop_front() {
+                engine.cache_mut().remove(oldest);
+            }
+        }
+
+        BobaError::catch_panic(|| {
+            let data = engine.cache().load(id).expect("source was just stored");
+            let mut program = Program::parse(&mut Lexer::new(data))?;
+            program.fold_strings();
+            program.dedup_strings();
+            Ok(engine.eval_program(&program.statements)?)
+        })
+    }
 }
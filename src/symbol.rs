@@ -0,0 +1,213 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Debug, Display},
+    hash::{BuildHasher, Hash, Hasher},
+    ops::Deref,
+    sync::{Arc, RwLock, Weak},
+};
+
+use hashbrown::{hash_map::DefaultHashBuilder, HashTable};
+use once_cell::sync::Lazy;
+
+static SYMBOLS: Lazy<RwLock<Symbols>> = Lazy::new(Default::default);
+
+/// Weak handles to every interned name, so names are freed once no symbol uses them
+///
+/// Each entry keeps the hash of its name, since a dropped name can no longer be hashed.
+#[derive(Default)]
+struct Symbols {
+    hasher: DefaultHashBuilder,
+    names: HashTable<(u64, Weak<str>)>,
+}
+
+impl Symbols {
+    fn find(&self, hash: u64, name: &str) -> Option<Arc<str>> {
+        let matches = |weak: &Weak<str>| weak.upgrade().filter(|found| **found == *name);
+        let (_, weak) = self
+            .names
+            .find(hash, |(h, weak)| *h == hash && matches(weak).is_some())?;
+        matches(weak)
+    }
+
+    fn insert(&mut self, hash: u64, name: Arc<str>) {
+        // clear out dropped names before growing the table
+        if self.names.len() == self.names.capacity() {
+            self.names.retain(|(_, weak)| weak.strong_count() > 0);
+        }
+        self.names
+            .insert_unique(hash, (hash, Arc::downgrade(&name)), |(hash, _)| *hash);
+    }
+}
+
+/// An interned identifier
+///
+/// Identifiers are interned once while parsing, so every symbol with the same name shares
+/// one allocation and scopes can compare and hash them by pointer instead of by string.
+/// A name is freed once the last symbol using it is dropped.
+#[derive(Clone)]
+pub struct Symbol(Arc<str>);
+
+impl Symbol {
+    /// Returns the symbol for `name`, interning it if it has not been seen before
+    pub fn intern(name: &str) -> Self {
+        if let Some(symbol) = Self::get(name) {
+            return symbol;
+        }
+
+        let mut symbols = SYMBOLS.write().unwrap_or_else(|error| error.into_inner());
+        let hash = symbols.hasher.hash_one(name);
+        if let Some(name) = symbols.find(hash, name) {
+            return Symbol(name); // interned by another thread in the meantime
+        }
+
+        let name: Arc<str> = name.into();
+        symbols.insert(hash, name.clone());
+        Symbol(name)
+    }
+
+    /// Returns the symbol for `name` only if it is currently interned
+    pub fn get(name: &str) -> Option<Self> {
+        let symbols = SYMBOLS.read().unwrap_or_else(|error| error.into_inner());
+        let hash = symbols.hasher.hash_one(name);
+        symbols.find(hash, name).map(Symbol)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).cast::<u8>().hash(state)
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Symbol {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Self::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Self::intern(&name)
+    }
+}
+
+impl From<Symbol> for String {
+    fn from(symbol: Symbol) -> Self {
+        symbol.as_str().to_string()
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+/// Names an identifier, either as an already interned [`Symbol`] or as a string
+///
+/// Lets host facing methods keep accepting strings while the engine
+/// itself passes symbols straight through without any lookup.
+pub trait AsSymbol {
+    /// Returns the symbol for this name, interning it if needed
+    fn to_symbol(&self) -> Symbol;
+
+    /// Returns the symbol for this name if it has been interned,
+    /// used by lookups so that unknown names are not interned
+    fn find_symbol(&self) -> Option<Symbol>;
+}
+
+impl AsSymbol for Symbol {
+    fn to_symbol(&self) -> Symbol {
+        self.clone()
+    }
+
+    fn find_symbol(&self) -> Option<Symbol> {
+        Some(self.clone())
+    }
+}
+
+impl AsSymbol for str {
+    fn to_symbol(&self) -> Symbol {
+        Symbol::intern(self)
+    }
+
+    fn find_symbol(&self) -> Option<Symbol> {
+        Symbol::get(self)
+    }
+}
+
+impl AsSymbol for String {
+    fn to_symbol(&self) -> Symbol {
+        Symbol::intern(self)
+    }
+
+    fn find_symbol(&self) -> Option<Symbol> {
+        Symbol::get(self)
+    }
+}
+
+impl<T: AsSymbol + ?Sized> AsSymbol for &T {
+    fn to_symbol(&self) -> Symbol {
+        (**self).to_symbol()
+    }
+
+    fn find_symbol(&self) -> Option<Symbol> {
+        (**self).find_symbol()
+    }
+}